@@ -0,0 +1,130 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::WindowsAndMessaging::*;
+
+use crate::keyboard_hook;
+
+/// App command ids of the volume buttons (`winuser.h`)
+const APPCOMMAND_VOLUME_MUTE: u32 = 8;
+const APPCOMMAND_VOLUME_DOWN: u32 = 9;
+const APPCOMMAND_VOLUME_UP: u32 = 10;
+
+/// Message id Windows registered for "SHELLHOOK", 0 until the window exists
+static SHELL_HOOK_MESSAGE: AtomicU32 = AtomicU32::new(0);
+
+/// Hidden window that receives the volume app commands (`WM_APPCOMMAND`)
+/// some Bluetooth headsets and remotes send instead of key presses, and
+/// also runs them through the keyboard hook's actions per
+/// `app_commands_also_adjust`.
+///
+/// App commands go to the focused window and reach the shell only when it
+/// leaves them unhandled, so the window registers as a shell hook window
+/// to see them. Windows still changes the system volume for them as well.
+/// Commands generated from a key press are skipped, since the keyboard
+/// hook already saw the key. Needs the keyboard hook to be installed.
+pub struct AppCommandWindow {
+    hwnd: HWND,
+}
+
+impl AppCommandWindow {
+    pub fn create() -> Result<Self> {
+        unsafe {
+            let instance = GetModuleHandleW(None)?;
+            let class_name = w!("VolimeAppCommandClass");
+
+            let wc = WNDCLASSW {
+                lpfnWndProc: Some(Self::wnd_proc),
+                hInstance: instance.into(),
+                lpszClassName: class_name,
+                ..Default::default()
+            };
+
+            let atom = RegisterClassW(&wc);
+            if atom == 0 {
+                return Err(Error::from_win32());
+            }
+
+            // Ventana de nivel superior oculta: las ventanas solo de mensajes
+            // no reciben los mensajes del shell hook
+            let hwnd = CreateWindowExW(
+                WS_EX_TOOLWINDOW,
+                class_name,
+                w!("Volime app commands"),
+                WINDOW_STYLE(0),
+                0,
+                0,
+                0,
+                0,
+                None,
+                None,
+                instance,
+                None,
+            )?;
+
+            SHELL_HOOK_MESSAGE.store(RegisterWindowMessageW(w!("SHELLHOOK")), Ordering::SeqCst);
+            if !RegisterShellHookWindow(hwnd).as_bool() {
+                let error = Error::from_win32();
+                let _ = DestroyWindow(hwnd);
+                return Err(error);
+            }
+
+            log_event!("App command window created");
+
+            Ok(AppCommandWindow { hwnd })
+        }
+    }
+
+    unsafe extern "system" fn wnd_proc(
+        hwnd: HWND,
+        msg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        let shell_hook_message = SHELL_HOOK_MESSAGE.load(Ordering::SeqCst);
+        if shell_hook_message != 0 && msg == shell_hook_message {
+            if wparam.0 as u32 == HSHELL_APPCOMMAND {
+                handle_app_command(lparam);
+            }
+            return LRESULT(0);
+        }
+
+        match msg {
+            WM_APPCOMMAND if handle_app_command(lparam) => LRESULT(1),
+            _ => DefWindowProcW(hwnd, msg, wparam, lparam),
+        }
+    }
+}
+
+impl Drop for AppCommandWindow {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = DeregisterShellHookWindow(self.hwnd);
+            let _ = DestroyWindow(self.hwnd);
+            log_event!("App command window destroyed");
+        }
+    }
+}
+
+/// Run a volume app command, given the `lParam` of `WM_APPCOMMAND`.
+/// Returns whether Volime acted on it.
+fn handle_app_command(lparam: LPARAM) -> bool {
+    let high_word = ((lparam.0 as u32) >> 16) & 0xFFFF;
+    let command = high_word & !FAPPCOMMAND_MASK;
+    let device = high_word & FAPPCOMMAND_MASK;
+
+    // Las teclas ya pasaron por el hook de teclado
+    if device == FAPPCOMMAND_KEY {
+        return false;
+    }
+
+    let (volume_up, volume_down, mute) = match command {
+        APPCOMMAND_VOLUME_UP => (true, false, false),
+        APPCOMMAND_VOLUME_DOWN => (false, true, false),
+        APPCOMMAND_VOLUME_MUTE => (false, false, true),
+        _ => return false,
+    };
+    log_event!("Volume app command {} from device {:#x}", command, device);
+    keyboard_hook::run_app_command(volume_up, volume_down, mute)
+}
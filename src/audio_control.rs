@@ -1,221 +1,2789 @@
+use parking_lot::{Mutex, RwLock};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::ffi::OsString;
 use std::os::windows::ffi::OsStringExt;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use windows::core::*;
+use windows::Win32::Devices::FunctionDiscovery::PKEY_Device_FriendlyName;
 use windows::Win32::Foundation::*;
+use windows::Win32::Graphics::Gdi::{
+    MonitorFromPoint, MonitorFromWindow, MONITOR_DEFAULTTONEAREST,
+};
+use windows::Win32::Media::Audio::Endpoints::{IAudioEndpointVolume, IAudioMeterInformation};
 use windows::Win32::Media::Audio::*;
+use windows::Win32::System::Com::StructuredStorage::PropVariantToStringAlloc;
 use windows::Win32::System::Com::*;
 use windows::Win32::System::Threading::*;
 use windows::Win32::UI::WindowsAndMessaging::*;
 
+use crate::config::{app_key, Config, DeviceRole, KeyAction, MuteKeyAction, TargetStrategy};
+use crate::policy_config::{IPolicyConfig, POLICY_CONFIG_CLIENT};
+use crate::session_events::{SessionCreationWatcher, SessionWatcher};
+use crate::system_tray::ModifierKey;
+
+/// How the adjusted audio session was found for the foreground app
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatchKind {
+    /// The session belongs to the foreground process itself
+    ByPid,
+    /// The session belongs to another process with the same executable name
+    /// (e.g. a browser's audio child process)
+    ByName,
+    /// The session belongs to the app named by a `window_class_rules` entry
+    /// for the foreground window's class
+    ByWindowClass,
+    /// No session was found
+    None,
+    /// No session was found, so the system master volume was adjusted
+    Master,
+    /// No shared session was found but the device is playing, most likely
+    /// the app in WASAPI exclusive mode, so the device volume was adjusted
+    Exclusive,
+}
+
+impl MatchKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MatchKind::ByPid => "by PID",
+            MatchKind::ByName => "by name",
+            MatchKind::ByWindowClass => "by window class rule",
+            MatchKind::None => "no session",
+            MatchKind::Master => "master volume fallback",
+            MatchKind::Exclusive => "device volume (exclusive mode)",
+        }
+    }
+}
+
 pub struct VolumeInfo {
     pub app_name: String,
     pub exe_path: Option<String>,
+    /// Above 1.0 for a boosted app; the toast draws the excess separately
+    pub volume: f32,
+    /// Volume before this change (the same as `volume` when it didn't move)
+    pub previous_volume: f32,
+    pub is_muted: bool,
+    /// A volume-up was clamped by the app's configured cap
+    pub cap_reached: bool,
+    /// A volume-down stopped at the app's configured floor
+    pub floor_reached: bool,
+    /// A volume step landed exactly on 0% or 100%
+    pub limit_reached: bool,
+    /// Output device of the session, when it isn't the default one
+    pub device_name: Option<String>,
+    /// The app has no audio session, so nothing was adjusted
+    pub is_no_session: bool,
+    /// Quiet hours were in effect, so the volume was capped by them too
+    pub quiet_hours: bool,
+    /// The change is temporary and reverts after this long
+    pub restore_in: Option<Duration>,
+    pub match_kind: MatchKind,
+}
+
+impl VolumeInfo {
+    /// Placeholder for an app without an audio session
+    fn no_session(app_name: String, exe_path: Option<String>) -> Self {
+        VolumeInfo {
+            app_name: format!("{} (no audio session)", app_name),
+            exe_path,
+            volume: 0.0,
+            previous_volume: 0.0,
+            is_muted: false,
+            cap_reached: false,
+            floor_reached: false,
+            limit_reached: false,
+            device_name: None,
+            is_no_session: true,
+            quiet_hours: false,
+            restore_in: None,
+            match_kind: MatchKind::None,
+        }
+    }
+}
+
+/// One entry of `AudioController::list_sessions`
+pub struct SessionInfo {
+    pub app_name: String,
+    /// Process of the session (0 for the system sounds)
+    pub process_id: u32,
+    pub exe_path: Option<String>,
     pub volume: f32,
     pub is_muted: bool,
+    /// Current peak level (0.0 - 1.0) from the session's meter
+    pub peak: f32,
+    /// The session is playing right now
+    pub is_active: bool,
+    /// The Windows system sounds session, listed as `SYSTEM_SOUNDS`
+    pub is_system_sounds: bool,
+}
+
+/// Name the system sounds session goes by, since it has no process of its own
+pub const SYSTEM_SOUNDS: &str = "System Sounds";
+
+/// One entry of `AudioController::session_diagnostics`
+pub struct SessionDiagnostic {
+    /// Process name, or the session's display name when the process is gone
+    pub name: String,
+    pub process_id: u32,
+    pub state: AudioSessionState,
+    pub is_system_sounds: bool,
+    /// The owning process no longer runs, so the session is a leftover
+    pub process_gone: bool,
+}
+
+/// Posted to the window registered with `set_notify_window` when the list
+/// of apps muted through Volime changes
+pub const WM_MUTED_APPS_CHANGED: u32 = WM_APP + 2;
+
+/// Posted to the window registered with `set_notify_window` once adjusting
+/// the focused app has failed `FAILURE_NOTIFY_THRESHOLD` times in a row
+pub const WM_VOLUME_CONTROL_FAILING: u32 = WM_APP + 3;
+
+/// Consecutive failures before `WM_VOLUME_CONTROL_FAILING` is posted
+const FAILURE_NOTIFY_THRESHOLD: u32 = 3;
+
+/// A volume change being applied gradually (see `Config::volume_ramp_ms`)
+struct Ramp {
+    volume: ISimpleAudioVolume,
+    from: f32,
+    to: f32,
+    started: Instant,
+    duration: Duration,
+}
+
+impl Ramp {
+    /// Interpolated level for now, and whether the ramp has finished
+    fn current(&self) -> (f32, bool) {
+        let progress = self.started.elapsed().as_secs_f32() / self.duration.as_secs_f32();
+        if progress >= 1.0 {
+            (self.to, true)
+        } else {
+            (self.from + (self.to - self.from) * progress, false)
+        }
+    }
+}
+
+/// A change requested of a session or the master volume
+#[derive(Clone, Copy)]
+enum Adjustment {
+    /// One configured step up or down (neither just reports the level)
+    Step {
+        up: bool,
+        down: bool,
+    },
+    Mute,
+    /// Arbitrary amount, negative to lower
+    By(f32),
+    /// Nothing: only read the level, not even unmuting or clamping to the cap
+    Read,
+}
+
+impl Adjustment {
+    /// The volume keys' flags; mute wins over up/down
+    fn from_keys(volume_up: bool, volume_down: bool, mute: bool) -> Self {
+        if mute {
+            Adjustment::Mute
+        } else {
+            Adjustment::Step {
+                up: volume_up,
+                down: volume_down,
+            }
+        }
+    }
+
+    /// Step size and direction of the change, `step` giving the size of one
+    /// configured step; `Mute` and `Read` move nothing
+    fn resolve(self, step: impl FnOnce() -> f32) -> (f32, bool, bool) {
+        match self {
+            Adjustment::Step { up, down } => (step(), up, down),
+            Adjustment::By(delta) => (delta.abs(), delta > 0.0, delta < 0.0),
+            Adjustment::Mute | Adjustment::Read => (0.0, false, false),
+        }
+    }
+}
+
+/// App that the focused-app actions adjust, picked by `target_strategy`
+#[derive(Clone)]
+struct Target {
+    process_id: u32,
+    process_name: String,
+    exe_path: Option<String>,
+    /// Class name of the target window, for `window_class_rules`
+    window_class: Option<String>,
+}
+
+/// A matched audio session and where it was found
+struct FoundSession {
+    control: IAudioSessionControl2,
+    match_kind: MatchKind,
+    /// Friendly name of the device, when it isn't the default one
+    device_name: Option<String>,
+}
+
+/// Apps turned down while the priority app plays
+struct Ducking {
+    /// Volume of each target app before it was ducked, keyed by app name
+    volumes: HashMap<String, f32>,
+    /// Last time the priority app was heard
+    last_heard: Instant,
+}
+
+/// Session raised while the boost key is held, and what to restore. The
+/// session itself is kept so the right app is restored even if the focus
+/// moved meanwhile.
+struct Boost {
+    volume: ISimpleAudioVolume,
+    previous_volume: f32,
+    app_name: String,
+    exe_path: Option<String>,
+    match_kind: MatchKind,
+    device_name: Option<String>,
+    quiet_hours: bool,
+}
+
+impl Boost {
+    /// Current state of the boosted session for the toast
+    fn volume_info(&self) -> Result<VolumeInfo> {
+        unsafe {
+            Ok(VolumeInfo {
+                app_name: self.app_name.clone(),
+                exe_path: self.exe_path.clone(),
+                volume: self.volume.GetMasterVolume()?,
+                previous_volume: self.previous_volume,
+                is_muted: self.volume.GetMute()?.as_bool(),
+                cap_reached: false,
+                floor_reached: false,
+                limit_reached: false,
+                device_name: self.device_name.clone(),
+                is_no_session: false,
+                quiet_hours: self.quiet_hours,
+                restore_in: None,
+                match_kind: self.match_kind,
+            })
+        }
+    }
+}
+
+/// A temporary change waiting to revert, per `temporary_key`
+struct TemporaryChange {
+    app_name: String,
+    /// Volume from before the first temporary change
+    volume: f32,
+    restore_at: Instant,
 }
 
+/// How long Modifier + `temporary_key` waits for the change it applies to
+const TEMPORARY_ARM_WINDOW: Duration = Duration::from_secs(10);
+
+/// How often the priority app's meter is checked for ducking
+const DUCKING_POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// Peak meter level above which a session or device counts as audible
+const AUDIBLE_PEAK_THRESHOLD: f32 = 0.01;
+/// How often the meters are checked for `TargetStrategy::RecentlyAudible`
+const RECENTLY_AUDIBLE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// Most apps remembered for `TargetStrategy::RecentlyAudible`
+const RECENTLY_AUDIBLE_CAPACITY: usize = 8;
+
+/// How long a new session is left alone before its `startup_volume` is set,
+/// so it lands after the app's own initial volume
+const STARTUP_VOLUME_DELAY: Duration = Duration::from_millis(500);
+
+/// Presses closer than this count as one held key for acceleration
+const ACCELERATION_WINDOW: Duration = Duration::from_millis(200);
+/// Largest step multiplier reached by acceleration
+const MAX_ACCELERATION: u32 = 5;
+
 pub struct AudioController {
-    device_enumerator: IMMDeviceEnumerator,
+    /// Re-created by `reset_after_resume`
+    device_enumerator: Mutex<IMMDeviceEnumerator>,
+    config: Arc<RwLock<Config>>,
+    last_app: Mutex<Option<String>>,
+    /// The two most recent window targets of different apps, newest first.
+    /// The newest also stands in for windows that have no process.
+    last_two_targets: Mutex<[Option<Target>; 2]>,
+    /// One of `last_two_targets` picked with `swap_target`, adjusted instead
+    /// of the window until its app exits
+    active_target: Mutex<Option<Target>>,
+    /// Time of the last volume step and how many quick presses preceded it
+    press_streak: Mutex<Option<(Instant, u32)>>,
+    /// Ramps in progress, keyed by `config::app_key`
+    ramps: Mutex<HashMap<String, Ramp>>,
+    /// Quiet hours were in effect at the last tick
+    quiet_hours_active: AtomicBool,
+    /// Target apps ducked while the priority app plays, if it does
+    ducking: Mutex<Option<Ducking>>,
+    /// When the priority app was last checked for ducking
+    ducking_checked_at: Mutex<Option<Instant>>,
+    /// Apps last heard, newest first, for `TargetStrategy::RecentlyAudible`
+    recently_audible: Mutex<VecDeque<(Target, Instant)>>,
+    /// When the meters were last checked for `recently_audible`
+    audible_checked_at: Mutex<Option<Instant>>,
+    /// App raised by the boost key while it is held
+    boost: Mutex<Option<Boost>>,
+    /// Apps muted through Volime and not unmuted since
+    muted_apps: Mutex<Vec<String>>,
+    /// Adjustments of the focused app that failed since the last success
+    consecutive_failures: AtomicU32,
+    notify_hwnd: Mutex<Option<isize>>,
+    /// Volumes to restore for apps turned down by `toggle_focused_app_whisper`
+    whispering: Mutex<HashMap<String, f32>>,
+    /// Apps muted by the last `toggle_focused_app_solo`
+    solo_muted: Mutex<Vec<String>>,
+    /// Non-default endpoint each app was last found on, keyed by
+    /// `config::app_key`, so routed apps are looked up there first
+    session_devices: Mutex<HashMap<String, String>>,
+    session_watcher: SessionWatcher,
+    /// Sessions created on the default device, for `startup_volume`
+    session_creation: Mutex<Option<SessionCreationWatcher>>,
+    /// Instance IDs of the created sessions already handled, see
+    /// `startup_volume_once`
+    startup_handled: Mutex<HashSet<String>>,
+    /// When `temporary_key` was pressed, until an app volume step uses it
+    temporary_armed: Mutex<Option<Instant>>,
+    /// Temporary changes to revert, keyed by `config::app_key`
+    temporary: Mutex<HashMap<String, TemporaryChange>>,
+}
+
+/// Startup volume to give a session that appeared, named by its instance ID
+/// and app. Each session is handled once, so the app can be raised again
+/// afterwards, and apps without a `startup_volume` are left alone.
+fn startup_volume_once(
+    handled: &mut HashSet<String>,
+    instance_id: &str,
+    app_name: &str,
+    config: &Config,
+) -> Option<f32> {
+    if !handled.insert(instance_id.to_string()) {
+        return None;
+    }
+    config.effective_startup_volume(app_name)
+}
+
+/// Apply one volume step to `current`, keeping the result within `[0.0, max]`.
+/// With `proportional`, the step is that fraction of `current` instead, but
+/// never less than `step` so 0% can still be reached.
+pub fn compute_new_volume(
+    current: f32,
+    step: f32,
+    volume_up: bool,
+    volume_down: bool,
+    max: f32,
+    proportional: Option<f32>,
+) -> f32 {
+    let step = match proportional {
+        Some(ratio) => step.max(current * ratio),
+        None => step,
+    };
+    let new_volume = if volume_up {
+        current + step
+    } else if volume_down {
+        current - step
+    } else {
+        current
+    };
+
+    new_volume.min(max).clamp(0.0, 1.0)
+}
+
+/// Move `new_volume`, one step from `current`, onto the nearest of `points`
+/// within `threshold` of it. Only points past `current` in the direction of
+/// the step count, so a step never snaps back to where it started and the
+/// volume can still stop between points. Points outside 0.0 - 1.0 are ignored.
+pub fn snap_volume(current: f32, new_volume: f32, points: &[f32], threshold: f32) -> f32 {
+    let ahead = |point: f32| {
+        if new_volume > current {
+            point > current
+        } else {
+            point < current
+        }
+    };
+    points
+        .iter()
+        .copied()
+        .filter(|point| (0.0..=1.0).contains(point))
+        .filter(|point| new_volume != current && ahead(*point))
+        // Allow for rounding, so a step landing 2% away snaps with a 2% threshold
+        .filter(|point| (point - new_volume).abs() <= threshold + 1e-4)
+        .min_by(|a, b| (a - new_volume).abs().total_cmp(&(b - new_volume).abs()))
+        .unwrap_or(new_volume)
+}
+
+/// Whether a volume step ended exactly on 0% (down) or 100% (up)
+fn at_limit(new_volume: f32, volume_up: bool, volume_down: bool) -> bool {
+    (volume_up && new_volume >= 1.0) || (volume_down && new_volume <= 0.0)
+}
+
+/// Whether an app stays muted after a volume step, per `unmute_on_adjust`
+fn muted_after_step(was_muted: bool, unmute_on_adjust: bool) -> bool {
+    was_muted && !unmute_on_adjust
 }
 
 impl AudioController {
-    pub fn new() -> Result<Self> {
+    pub fn new(config: Arc<RwLock<Config>>) -> Result<Self> {
         unsafe {
             let device_enumerator: IMMDeviceEnumerator =
                 CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
 
-            Ok(AudioController { device_enumerator })
+            let controller = AudioController {
+                device_enumerator: Mutex::new(device_enumerator),
+                config,
+                last_app: Mutex::new(None),
+                last_two_targets: Mutex::new([None, None]),
+                active_target: Mutex::new(None),
+                press_streak: Mutex::new(None),
+                ramps: Mutex::new(HashMap::new()),
+                quiet_hours_active: AtomicBool::new(false),
+                ducking: Mutex::new(None),
+                ducking_checked_at: Mutex::new(None),
+                recently_audible: Mutex::new(VecDeque::new()),
+                audible_checked_at: Mutex::new(None),
+                boost: Mutex::new(None),
+                muted_apps: Mutex::new(Vec::new()),
+                consecutive_failures: AtomicU32::new(0),
+                notify_hwnd: Mutex::new(None),
+                whispering: Mutex::new(HashMap::new()),
+                solo_muted: Mutex::new(Vec::new()),
+                session_devices: Mutex::new(HashMap::new()),
+                session_watcher: SessionWatcher::new(),
+                session_creation: Mutex::new(None),
+                startup_handled: Mutex::new(HashSet::new()),
+                temporary_armed: Mutex::new(None),
+                temporary: Mutex::new(HashMap::new()),
+            };
+            controller.watch_session_creation();
+            Ok(controller)
         }
     }
 
+    /// Report external volume changes of adjusted sessions to `hwnd`
+    /// (see `session_events::WM_SESSION_VOLUME_CHANGED`)
+    pub fn watch_volume_changes(&self, hwnd: HWND) {
+        self.session_watcher.set_notify_window(hwnd);
+    }
+
+    /// Process name of the last app whose volume was adjusted
+    pub fn last_app_name(&self) -> Option<String> {
+        self.last_app.lock().clone()
+    }
+
+    /// Adjust the focused app. Repeated failures are reported to the notify
+    /// window, since nothing else would tell the user.
     pub fn adjust_focused_app_volume(
         &self,
         volume_up: bool,
         volume_down: bool,
         mute: bool,
     ) -> Result<VolumeInfo> {
-        unsafe {
-            // Obtener ventana en primer plano
-            let hwnd = GetForegroundWindow();
-            if hwnd.is_invalid() {
-                return Err(Error::from(E_FAIL));
-            }
+        let result =
+            self.try_adjust_focused_app(Adjustment::from_keys(volume_up, volume_down, mute));
+
+        if result.is_ok() {
+            self.consecutive_failures.store(0, Ordering::SeqCst);
+        } else if self.consecutive_failures.fetch_add(1, Ordering::SeqCst) + 1
+            == FAILURE_NOTIFY_THRESHOLD
+        {
+            log_event!(
+                "Adjusting the volume failed {} times in a row",
+                FAILURE_NOTIFY_THRESHOLD
+            );
+            self.post_notify(WM_VOLUME_CONTROL_FAILING);
+        }
+
+        result
+    }
+
+    /// Add `delta` (negative to lower) to the focused app's volume, clamped to
+    /// 0 - 100% and the app's cap, regardless of the configured step. Meant
+    /// for scripting; the keys go through `adjust_focused_app_volume`.
+    pub fn adjust_focused_app_by(&self, delta: f32) -> Result<VolumeInfo> {
+        self.try_adjust_focused_app(Adjustment::By(delta))
+    }
+
+    /// Current volume of the focused app (or of what the keys would adjust
+    /// instead), changing nothing. The app isn't recorded as last adjusted.
+    pub fn get_focused_app_volume(&self) -> Result<VolumeInfo> {
+        self.try_adjust_focused_app(Adjustment::Read)
+    }
 
-            // Obtener PID de la ventana
-            let mut process_id: u32 = 0;
-            GetWindowThreadProcessId(hwnd, Some(&mut process_id));
+    /// Press a volume key as if `modifier` were held, whatever the real
+    /// keyboard state, for launchers and other tools driving Volime. The key
+    /// runs the action `key_actions` (or `modifier_actions`) maps it to.
+    /// Fails for a modifier with no actions, as its keys do nothing.
+    pub fn press_volume_key(
+        &self,
+        modifier: ModifierKey,
+        volume_up: bool,
+        volume_down: bool,
+    ) -> Result<VolumeInfo> {
+        let Some(key_actions) = self.config.read().key_actions_for(modifier) else {
+            return Err(Error::new(
+                E_INVALIDARG,
+                format!("No key actions for the {} modifier", modifier),
+            ));
+        };
+        self.perform_key_action(key_actions.for_key(volume_up, volume_down))
+    }
 
-            if process_id == 0 {
-                return Err(Error::from(E_FAIL));
+    /// Run a key action the way Modifier + a volume key does: the same
+    /// target, steps, caps and `mute_key_action`. The caller shows the
+    /// result, e.g. with `ToastUI::show_volume_in_context`.
+    pub fn perform_key_action(&self, action: KeyAction) -> Result<VolumeInfo> {
+        let mute_key_action = self.config.read().mute_key_action;
+        match (action, mute_key_action) {
+            (KeyAction::AppUp, _) => self.adjust_focused_app_volume(true, false, false),
+            (KeyAction::AppDown, _) => self.adjust_focused_app_volume(false, true, false),
+            (KeyAction::AppMute, MuteKeyAction::Whisper(level)) => {
+                self.toggle_focused_app_whisper(level)
+            }
+            (KeyAction::AppMute, MuteKeyAction::Solo) | (KeyAction::Solo, _) => {
+                self.toggle_focused_app_solo()
             }
+            (KeyAction::AppMute, MuteKeyAction::Toggle) => {
+                self.adjust_focused_app_volume(false, false, true)
+            }
+            (KeyAction::MasterUp, _) => self.adjust_master_volume(true, false, false),
+            (KeyAction::MasterDown, _) => self.adjust_master_volume(false, true, false),
+            (KeyAction::MasterMute, _) => self.adjust_master_volume(false, false, true),
+            // Depende de la tecla pulsada (`KeyActions::for_key`)
+            (KeyAction::Default, _) => Err(Error::new(
+                E_INVALIDARG,
+                "The default action depends on the key pressed",
+            )),
+        }
+    }
 
-            // Obtener nombre y ruta del proceso
-            let (process_name, exe_path) = self.get_process_info(process_id)?;
+    fn try_adjust_focused_app(&self, adjustment: Adjustment) -> Result<VolumeInfo> {
+        let target = self.resolve_target()?;
 
-            // Obtener dispositivo de audio predeterminado
-            let device = self
-                .device_enumerator
-                .GetDefaultAudioEndpoint(eRender, eConsole)?;
+        if let Some((found, app_name)) = self.find_target_session(&target)? {
+            return self.adjust_found_session(found, adjustment, app_name, target.exe_path);
+        }
 
-            // Obtener sesión de audio
-            let session_manager: IAudioSessionManager2 = device.Activate(CLSCTX_ALL, None)?;
-            let session_enumerator = session_manager.GetSessionEnumerator()?;
+        // If session not found, return basic info
+        log_event!(
+            "[DEBUG] No audio session found for '{}' (PID {})",
+            target.process_name,
+            target.process_id
+        );
 
-            let count = session_enumerator.GetCount()?;
+        // Exclusive-mode streams bypass the mixer and have no session; only
+        // the device volume still reaches them
+        if self.config.read().exclusive_fallback && self.device_is_playing() {
+            let mut volume_info = self.adjust_master(adjustment)?;
+            volume_info.app_name = target.process_name;
+            volume_info.exe_path = target.exe_path;
+            volume_info.match_kind = MatchKind::Exclusive;
+            return Ok(volume_info);
+        }
 
-            // Primero intentar buscar por PID exacto
-            for i in 0..count {
-                let session_control = session_enumerator.GetSession(i)?;
-                let session_control2: IAudioSessionControl2 = session_control.cast()?;
-
-                let session_pid = session_control2.GetProcessId()?;
-
-                if session_pid == process_id {
-                    println!("[DEBUG] Found session with exact PID: {}", session_pid);
-                    return self.adjust_session_volume(
-                        session_control2,
-                        volume_up,
-                        volume_down,
-                        mute,
-                        process_name,
-                        exe_path,
-                    );
+        if self.config.read().fallback_to_master {
+            return self.adjust_master(adjustment);
+        }
+        Ok(VolumeInfo::no_session(target.process_name, target.exe_path))
+    }
+
+    /// Window picked by `target_strategy` and its process
+    fn resolve_target(&self) -> Result<Target> {
+        if let Some(target) = self.active_target() {
+            return Ok(target);
+        }
+
+        let strategy = self.config.read().target_strategy;
+        if strategy == TargetStrategy::RecentlyAudible {
+            if let Some(target) = self.recently_audible_target() {
+                self.record_target(target.clone());
+                return Ok(target);
+            }
+        }
+
+        let hwnd = unsafe {
+            match strategy {
+                TargetStrategy::Foreground | TargetStrategy::RecentlyAudible => {
+                    GetForegroundWindow()
                 }
+                TargetStrategy::CursorWindow => window_under_cursor(),
+                TargetStrategy::CursorMonitorForeground => foreground_on_cursor_monitor(),
             }
+        };
 
-            // If not found by PID, search by process name
-            // This handles cases like Chrome where audio is in a child process
-            println!(
-                "[DEBUG] Session with PID {} not found. Searching by name: {}",
-                process_id, process_name
-            );
+        // Some system surfaces (e.g. the desktop during a switch or a lock
+        // screen transition) report no window or PID 0. Rather than failing,
+        // keep controlling the app targeted before, if it is still running.
+        let Ok(process_id) = window_process_id(hwnd) else {
+            return self.last_target().ok_or_else(|| {
+                Error::new(
+                    E_FAIL,
+                    "The target window has no process and no app was targeted before",
+                )
+            });
+        };
 
-            for i in 0..count {
-                let session_control = session_enumerator.GetSession(i)?;
-                let session_control2: IAudioSessionControl2 = session_control.cast()?;
+        let (process_name, exe_path) = self.get_process_info(process_id)?;
+        let target = Target {
+            process_id,
+            process_name,
+            exe_path,
+            window_class: window_class(hwnd),
+        };
+        self.record_target(target.clone());
+        Ok(target)
+    }
 
-                let session_pid = session_control2.GetProcessId()?;
+    /// Make `target` the newest of `last_two_targets`, keeping the previous
+    /// app as the other one
+    fn record_target(&self, target: Target) {
+        let mut targets = self.last_two_targets.lock();
+        let same_app = targets[0].as_ref().is_some_and(|newest| {
+            newest
+                .process_name
+                .eq_ignore_ascii_case(&target.process_name)
+        });
+        if same_app {
+            targets[0] = Some(target);
+        } else {
+            targets[1] = targets[0].replace(target);
+        }
+    }
 
-                // Try to get process info, but continue if it fails
-                let session_process_name = match self.get_process_info(session_pid) {
-                    Ok((name, _)) => name,
-                    Err(_) => {
-                        println!(
-                            "[DEBUG] Session {}: PID {} - Could not get process name",
-                            i, session_pid
-                        );
-                        continue;
-                    }
-                };
+    /// Whether the target's process is still running (and its PID wasn't
+    /// reused by another app)
+    fn is_running(&self, target: &Target) -> bool {
+        self.get_process_info(target.process_id)
+            .is_ok_and(|(process_name, _)| process_name == target.process_name)
+    }
 
-                println!(
-                    "[DEBUG] Session {}: PID {} - {}",
-                    i, session_pid, session_process_name
-                );
+    /// The last target, unless its process has exited
+    fn last_target(&self) -> Option<Target> {
+        let target = self.last_two_targets.lock()[0].clone()?;
+        if !self.is_running(&target) {
+            return None;
+        }
 
-                // Compare process names (case-insensitive)
-                if session_process_name.to_lowercase() == process_name.to_lowercase() {
-                    println!(
-                        "[DEBUG] Found session with matching name! PID: {}",
-                        session_pid
-                    );
-                    return self.adjust_session_volume(
-                        session_control2,
-                        volume_up,
-                        volume_down,
-                        mute,
-                        process_name,
-                        exe_path,
-                    );
+        log_event!(
+            "[DEBUG] Target window has no process, using the previous target '{}'",
+            target.process_name
+        );
+        Some(target)
+    }
+
+    /// The app heard most recently within `recently_audible_window_ms` that
+    /// is still running
+    fn recently_audible_target(&self) -> Option<Target> {
+        let window = self.config.read().recently_audible_window();
+        let recently_audible = self.recently_audible.lock();
+        let (target, heard_at) = recently_audible
+            .iter()
+            .take_while(|(_, heard_at)| heard_at.elapsed() <= window)
+            .find(|(target, _)| self.is_running(target))?;
+
+        log_event!(
+            "[DEBUG] Targeting '{}', heard {} ms ago",
+            target.process_name,
+            heard_at.elapsed().as_millis()
+        );
+        Some(target.clone())
+    }
+
+    /// The target picked with `swap_target`, dropped once its app exits
+    fn active_target(&self) -> Option<Target> {
+        let mut active_target = self.active_target.lock();
+        let target = active_target.clone()?;
+        if self.is_running(&target) {
+            return Some(target);
+        }
+
+        log_event!("Active target '{}' exited", target.process_name);
+        *active_target = None;
+        None
+    }
+
+    /// Make the other of the two most recent targets the one the app volume
+    /// keys adjust, instead of the focused window. Returns its app name, or
+    /// None when fewer than two apps were targeted (or the other exited).
+    pub fn swap_target(&self) -> Option<String> {
+        let [Some(newest), Some(previous)] = self.last_two_targets.lock().clone() else {
+            return None;
+        };
+
+        let mut active_target = self.active_target.lock();
+        let current = active_target.as_ref().unwrap_or(&newest);
+        let next = if current
+            .process_name
+            .eq_ignore_ascii_case(&previous.process_name)
+        {
+            newest
+        } else {
+            previous
+        };
+        if !self.is_running(&next) {
+            return None;
+        }
+
+        let app_name = next.process_name.clone();
+        *active_target = Some(next);
+        Some(app_name)
+    }
+
+    /// Session to adjust for the target process, with the app name to report
+    /// it under. Precedence: the process' own session, then the app named by
+    /// a window class rule, then any session of the same executable.
+    fn find_target_session(&self, target: &Target) -> Result<Option<(FoundSession, String)>> {
+        let process_id = target.process_id;
+        let process_name = target.process_name.as_str();
+        let class_target = target.window_class.as_ref().and_then(|class| {
+            let config = self.config.read();
+            config.window_class_rules.get(class).cloned()
+        });
+        if let Some(target) = class_target {
+            if let Some(mut by_rule) = self.find_session(Some(process_id), &target)? {
+                if by_rule.match_kind == MatchKind::ByName {
+                    by_rule.match_kind = MatchKind::ByWindowClass;
+                    return Ok(Some((by_rule, target)));
                 }
+                return Ok(Some((by_rule, process_name.to_string())));
             }
-
-            // If session not found, return basic info
-            println!("[DEBUG] No audio session found for {}", process_name);
-            Ok(VolumeInfo {
-                app_name: format!("{} (no audio session)", process_name),
-                exe_path,
-                volume: 0.0,
-                is_muted: false,
-            })
         }
+
+        Ok(self
+            .find_session(Some(process_id), process_name)?
+            .map(|found| (found, process_name.to_string())))
     }
 
-    fn adjust_session_volume(
+    /// Apply `adjustment` to a matched session, reporting where it was found
+    fn adjust_found_session(
+        &self,
+        found: FoundSession,
+        adjustment: Adjustment,
+        app_name: String,
+        exe_path: Option<String>,
+    ) -> Result<VolumeInfo> {
+        let mut volume_info =
+            self.adjust_session_volume(found.control, adjustment, app_name, exe_path)?;
+        volume_info.device_name = found.device_name;
+        volume_info.match_kind = found.match_kind;
+        Ok(volume_info)
+    }
+
+    /// Adjust the system master volume of the default device, used when the
+    /// focused app has no session and `fallback_to_master` is enabled, and by
+    /// the double-tap master mode
+    pub fn adjust_master_volume(
         &self,
-        session_control2: IAudioSessionControl2,
         volume_up: bool,
         volume_down: bool,
         mute: bool,
-        process_name: String,
-        exe_path: Option<String>,
     ) -> Result<VolumeInfo> {
+        self.adjust_master(Adjustment::from_keys(volume_up, volume_down, mute))
+    }
+
+    /// Whether the default device is outputting sound, per its peak meter
+    fn device_is_playing(&self) -> bool {
+        let peak = self.default_device().and_then(|device| unsafe {
+            device
+                .Activate::<IAudioMeterInformation>(CLSCTX_ALL, None)?
+                .GetPeakValue()
+        });
+        peak.is_ok_and(|peak| peak > AUDIBLE_PEAK_THRESHOLD)
+    }
+
+    fn adjust_master(&self, adjustment: Adjustment) -> Result<VolumeInfo> {
         unsafe {
-            let simple_audio = session_control2.cast::<ISimpleAudioVolume>()?;
+            let device = self.default_device()?;
+            let endpoint_volume: IAudioEndpointVolume = device.Activate(CLSCTX_ALL, None)?;
+            let previous_volume = endpoint_volume.GetMasterVolumeLevelScalar()?;
 
-            let new_volume;
-            let is_muted;
+            let mut limit_reached = false;
+            if let Adjustment::Mute = adjustment {
+                let current_mute = endpoint_volume.GetMute()?.as_bool();
+                endpoint_volume.SetMute(!current_mute, std::ptr::null())?;
+            } else if !matches!(adjustment, Adjustment::Read) {
+                let (volume_step, volume_up, volume_down) = self.resolve_step(adjustment);
+                let new_volume = compute_new_volume(
+                    previous_volume,
+                    volume_step,
+                    volume_up,
+                    volume_down,
+                    1.0,
+                    self.proportional_ratio(adjustment),
+                );
+                let new_volume = self.snap_step(adjustment, previous_volume, new_volume, 1.0);
+                limit_reached = at_limit(new_volume, volume_up, volume_down);
+                // Already at the limit: nothing to set, the toast still confirms it
+                if new_volume != previous_volume {
+                    endpoint_volume.SetMasterVolumeLevelScalar(new_volume, std::ptr::null())?;
+                }
+                if self.config.read().input.unmute_on_adjust && endpoint_volume.GetMute()?.as_bool()
+                {
+                    endpoint_volume.SetMute(false, std::ptr::null())?;
+                }
+            }
 
-            if mute {
-                // Toggle mute
-                let current_mute = simple_audio.GetMute()?.as_bool();
-                simple_audio.SetMute(!current_mute, std::ptr::null())?;
-                new_volume = simple_audio.GetMasterVolume()?;
-                is_muted = !current_mute;
-            } else {
-                // Ajustar volumen
-                let current_volume = simple_audio.GetMasterVolume()?;
-                let volume_step = 0.01; // 5% por paso
+            Ok(VolumeInfo {
+                app_name: "System volume".to_string(),
+                exe_path: None,
+                volume: endpoint_volume.GetMasterVolumeLevelScalar()?,
+                previous_volume,
+                is_muted: endpoint_volume.GetMute()?.as_bool(),
+                cap_reached: false,
+                floor_reached: false,
+                limit_reached,
+                device_name: None,
+                is_no_session: false,
+                quiet_hours: false,
+                restore_in: None,
+                match_kind: MatchKind::Master,
+            })
+        }
+    }
 
-                new_volume = if volume_up {
-                    (current_volume + volume_step).min(1.0)
-                } else if volume_down {
-                    (current_volume - volume_step).max(0.0)
-                } else {
-                    current_volume
-                };
+    /// Window that receives `WM_MUTED_APPS_CHANGED` and
+    /// `WM_VOLUME_CONTROL_FAILING`
+    pub fn set_notify_window(&self, hwnd: HWND) {
+        *self.notify_hwnd.lock() = Some(hwnd.0 as isize);
+    }
 
-                simple_audio.SetMasterVolume(new_volume, std::ptr::null())?;
-                is_muted = simple_audio.GetMute()?.as_bool();
+    fn post_notify(&self, message: u32) {
+        if let Some(hwnd) = *self.notify_hwnd.lock() {
+            unsafe {
+                let _ = PostMessageW(HWND(hwnd as *mut _), message, WPARAM(0), LPARAM(0));
+            }
+        }
+    }
+
+    /// Apps muted through Volime that are still muted, in muting order
+    pub fn muted_apps(&self) -> Vec<String> {
+        self.muted_apps.lock().clone()
+    }
+
+    fn record_mute(&self, app_name: &str, muted: bool) {
+        let mut muted_apps = self.muted_apps.lock();
+        let position = muted_apps
+            .iter()
+            .position(|app| app.eq_ignore_ascii_case(app_name));
+
+        let changed = match (position, muted) {
+            (None, true) => {
+                muted_apps.push(app_name.to_string());
+                true
+            }
+            (Some(index), false) => {
+                muted_apps.remove(index);
+                true
             }
+            _ => false,
+        };
+        drop(muted_apps);
+
+        if changed {
+            self.post_notify(WM_MUTED_APPS_CHANGED);
+        }
+    }
+
+    /// Set mute of the focused app to an explicit state. Unlike the toggle
+    /// used by the keys it doesn't read the current state, so applying it
+    /// twice is harmless. For embedders and scripted callers; the keys use
+    /// the toggle.
+    pub fn set_focused_app_mute(&self, muted: bool) -> Result<VolumeInfo> {
+        let target = self.resolve_target()?;
+        let Some((found, app_name)) = self.find_target_session(&target)? else {
+            return Ok(VolumeInfo::no_session(target.process_name, target.exe_path));
+        };
+        let exe_path = target.exe_path;
+
+        unsafe {
+            let simple_audio = found.control.cast::<ISimpleAudioVolume>()?;
+            simple_audio.SetMute(muted, std::ptr::null())?;
+            let volume = simple_audio.GetMasterVolume()?;
+            self.record_mute(&app_name, muted);
+
+            *self.last_app.lock() = Some(app_name.clone());
+            self.session_watcher.watch(&found.control, &app_name);
 
             Ok(VolumeInfo {
-                app_name: process_name,
+                app_name,
                 exe_path,
-                volume: new_volume,
-                is_muted,
+                volume,
+                previous_volume: volume,
+                is_muted: muted,
+                cap_reached: false,
+                floor_reached: false,
+                limit_reached: false,
+                device_name: found.device_name,
+                is_no_session: false,
+                quiet_hours: false,
+                restore_in: None,
+                match_kind: found.match_kind,
             })
         }
     }
 
-    fn get_process_info(&self, process_id: u32) -> Result<(String, Option<String>)> {
+    /// Turn the focused app down to `level`, or back to the volume it had
+    /// when the previous call turned it down
+    pub fn toggle_focused_app_whisper(&self, level: f32) -> Result<VolumeInfo> {
+        let target = self.resolve_target()?;
+        let Some((found, app_name)) = self.find_target_session(&target)? else {
+            return Ok(VolumeInfo::no_session(target.process_name, target.exe_path));
+        };
+        let exe_path = target.exe_path;
+
+        let (max_volume, quiet_hours) = self.config.read().effective_max_volume(&app_name);
+
         unsafe {
-            let process_handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id)?;
+            let simple_audio = found.control.cast::<ISimpleAudioVolume>()?;
+            let previous_volume = simple_audio.GetMasterVolume()?;
 
-            if process_handle.is_invalid() {
-                return Ok((format!("PID {}", process_id), None));
+            let mut whispering = self.whispering.lock();
+            let volume = match whispering.remove(&app_key(&app_name)) {
+                Some(previous) => previous.min(max_volume),
+                None => {
+                    whispering.insert(app_key(&app_name), previous_volume);
+                    level.min(max_volume).clamp(0.0, 1.0)
+                }
+            };
+            drop(whispering);
+
+            simple_audio.SetMasterVolume(volume, std::ptr::null())?;
+            let is_muted = simple_audio.GetMute()?.as_bool();
+
+            *self.last_app.lock() = Some(app_name.clone());
+            self.session_watcher.watch(&found.control, &app_name);
+
+            Ok(VolumeInfo {
+                app_name,
+                exe_path,
+                volume,
+                previous_volume,
+                is_muted,
+                cap_reached: false,
+                floor_reached: false,
+                limit_reached: false,
+                device_name: found.device_name,
+                is_no_session: false,
+                quiet_hours,
+                restore_in: None,
+                match_kind: found.match_kind,
+            })
+        }
+    }
+
+    /// Mute every other app on the default device so only the focused one is
+    /// heard. The next call unmutes the apps muted by this one instead.
+    pub fn toggle_focused_app_solo(&self) -> Result<VolumeInfo> {
+        let target = self.resolve_target()?;
+        let Some((found, app_name)) = self.find_target_session(&target)? else {
+            return Ok(VolumeInfo::no_session(target.process_name, target.exe_path));
+        };
+        let exe_path = target.exe_path;
+
+        let solo_system_sounds = self.config.read().solo_system_sounds;
+        let mut solo_muted = self.solo_muted.lock();
+        if solo_muted.is_empty() {
+            for session in self.list_sessions()? {
+                if session.is_muted
+                    || session.app_name.eq_ignore_ascii_case(&app_name)
+                    || (session.is_system_sounds && !solo_system_sounds)
+                {
+                    continue;
+                }
+                match self.set_app_mute_by_name(&session.app_name, true) {
+                    Ok(true) => solo_muted.push(session.app_name),
+                    Ok(false) => {}
+                    Err(e) => log_event!("Solo: error muting '{}': {}", session.app_name, e),
+                }
             }
+            log_event!("Solo '{}': muted {} apps", app_name, solo_muted.len());
+        } else {
+            for app_name in solo_muted.drain(..) {
+                if let Err(e) = self.set_app_mute_by_name(&app_name, false) {
+                    log_event!("Solo: error unmuting '{}': {}", app_name, e);
+                }
+            }
+            log_event!("Solo '{}' ended", app_name);
+        }
+        drop(solo_muted);
+
+        unsafe {
+            let simple_audio = found.control.cast::<ISimpleAudioVolume>()?;
+            let volume = simple_audio.GetMasterVolume()?;
+
+            Ok(VolumeInfo {
+                app_name,
+                exe_path,
+                volume,
+                previous_volume: volume,
+                is_muted: simple_audio.GetMute()?.as_bool(),
+                cap_reached: false,
+                floor_reached: false,
+                limit_reached: false,
+                device_name: found.device_name,
+                is_no_session: false,
+                quiet_hours: false,
+                restore_in: None,
+                match_kind: found.match_kind,
+            })
+        }
+    }
+
+    /// Set the mute state of `app_name` without focusing it; false when it
+    /// has no session
+    pub fn set_app_mute_by_name(&self, app_name: &str, muted: bool) -> Result<bool> {
+        let Some(found) = self.find_session(None, app_name)? else {
+            return Ok(false);
+        };
 
-            let mut buffer = [0u16; 260];
-            let mut size = buffer.len() as u32;
+        unsafe {
+            let simple_audio = found.control.cast::<ISimpleAudioVolume>()?;
+            simple_audio.SetMute(muted, std::ptr::null())?;
+        }
+        self.record_mute(app_name, muted);
+        Ok(true)
+    }
 
-            let pwstr = PWSTR::from_raw(buffer.as_mut_ptr());
+    /// Toggle mute of the last adjusted app, regardless of which window has focus
+    pub fn toggle_last_app_mute(&self) -> Result<VolumeInfo> {
+        let Some(app_name) = self.last_app_name() else {
+            return Ok(VolumeInfo::no_session(String::new(), None));
+        };
 
-            if QueryFullProcessImageNameW(process_handle, PROCESS_NAME_WIN32, pwstr, &mut size)
-                .is_ok()
-            {
-                let _ = CloseHandle(process_handle);
-                let path = OsString::from_wide(&buffer[..size as usize]);
-                let path_str = path.to_string_lossy().to_string();
+        self.adjust_app_volume(&app_name, false, false, true)
+    }
 
-                // Extraer solo el nombre del archivo
-                let name = path_str.split('\\').last().unwrap_or(&path_str).to_string();
+    /// Adjust the session of `app_name` by process name, regardless of which
+    /// window has focus
+    pub fn adjust_app_volume(
+        &self,
+        app_name: &str,
+        volume_up: bool,
+        volume_down: bool,
+        mute: bool,
+    ) -> Result<VolumeInfo> {
+        match self.find_session(None, app_name)? {
+            Some(found) => {
+                let exe_path = unsafe { found.control.GetProcessId() }
+                    .ok()
+                    .and_then(|pid| self.get_process_info(pid).ok())
+                    .and_then(|(_, path)| path);
 
-                return Ok((name, Some(path_str)));
+                self.adjust_found_session(
+                    found,
+                    Adjustment::from_keys(volume_up, volume_down, mute),
+                    app_name.to_string(),
+                    exe_path,
+                )
             }
+            None => {
+                log_event!("[DEBUG] '{}' no longer has an audio session", app_name);
+                Ok(VolumeInfo::no_session(app_name.to_string(), None))
+            }
+        }
+    }
 
-            let _ = CloseHandle(process_handle);
-            Ok((format!("PID {}", process_id), None))
+    /// Set the volume and mute state of `app_name` to exact values, e.g. when
+    /// restoring a snapshot. The app's cap still applies.
+    pub fn set_app_volume(&self, app_name: &str, volume: f32, muted: bool) -> Result<VolumeInfo> {
+        self.set_named_app(app_name, volume, Some(muted))
+    }
+
+    /// Set the volume of the app whose process is named `name` (any case,
+    /// e.g. "Spotify.exe") to `level`, leaving its mute state alone. The
+    /// app's cap still applies. When no session matches, the result has
+    /// `is_no_session` set and nothing changes.
+    pub fn set_app_volume_by_name(&self, name: &str, level: f32) -> Result<VolumeInfo> {
+        self.set_named_app(name, level, None)
+    }
+
+    /// Shared by `set_app_volume` and `set_app_volume_by_name`
+    fn set_named_app(
+        &self,
+        app_name: &str,
+        volume: f32,
+        muted: Option<bool>,
+    ) -> Result<VolumeInfo> {
+        let Some(found) = self.find_session(None, app_name)? else {
+            return Ok(VolumeInfo::no_session(app_name.to_string(), None));
+        };
+
+        let (volume, quiet_hours) = self.config.read().limit_volume(app_name, volume);
+
+        let (previous_volume, muted) = unsafe {
+            let simple_audio = found.control.cast::<ISimpleAudioVolume>()?;
+            let previous_volume = simple_audio.GetMasterVolume()?;
+            simple_audio.SetMasterVolume(volume, std::ptr::null())?;
+            let muted = match muted {
+                Some(muted) => {
+                    simple_audio.SetMute(muted, std::ptr::null())?;
+                    self.record_mute(app_name, muted);
+                    muted
+                }
+                None => simple_audio.GetMute()?.as_bool(),
+            };
+            (previous_volume, muted)
+        };
+
+        Ok(VolumeInfo {
+            app_name: app_name.to_string(),
+            exe_path: None,
+            volume,
+            previous_volume,
+            is_muted: muted,
+            cap_reached: false,
+            floor_reached: false,
+            limit_reached: false,
+            device_name: found.device_name,
+            is_no_session: false,
+            quiet_hours,
+            restore_in: None,
+            match_kind: found.match_kind,
+        })
+    }
+
+    /// Raise the focused app to `level` (within its cap) until `end_boost`,
+    /// remembering its volume. While already boosting, only reports the
+    /// boosted app, so key auto-repeat keeps the toast up.
+    pub fn start_boost(&self, level: f32) -> Result<VolumeInfo> {
+        let mut boost = self.boost.lock();
+        if let Some(boost) = boost.as_ref() {
+            return boost.volume_info();
         }
+
+        let target = self.resolve_target()?;
+        let Some((found, app_name)) = self.find_target_session(&target)? else {
+            return Ok(VolumeInfo::no_session(target.process_name, target.exe_path));
+        };
+
+        let (max_volume, quiet_hours) = self.config.read().effective_max_volume(&app_name);
+        let volume = found.control.cast::<ISimpleAudioVolume>()?;
+        let previous_volume = unsafe { volume.GetMasterVolume()? };
+        // A boost never turns the app down
+        let level = level.min(max_volume).clamp(0.0, 1.0).max(previous_volume);
+        unsafe { volume.SetMasterVolume(level, std::ptr::null())? };
+        log_event!(
+            "Boosting '{}' from {}% to {}%",
+            app_name,
+            (previous_volume * 100.0).round() as i32,
+            (level * 100.0).round() as i32
+        );
+
+        let started = Boost {
+            volume,
+            previous_volume,
+            app_name,
+            exe_path: target.exe_path,
+            match_kind: found.match_kind,
+            device_name: found.device_name,
+            quiet_hours,
+        };
+        let volume_info = started.volume_info();
+        *boost = Some(started);
+        volume_info
+    }
+
+    /// Give the app raised by `start_boost` its previous volume back. `None`
+    /// when nothing was boosted.
+    pub fn end_boost(&self) -> Result<Option<VolumeInfo>> {
+        let Some(boost) = self.boost.lock().take() else {
+            return Ok(None);
+        };
+
+        unsafe {
+            boost
+                .volume
+                .SetMasterVolume(boost.previous_volume, std::ptr::null())?
+        };
+        log_event!(
+            "Boost of '{}' ended, restored to {}%",
+            boost.app_name,
+            (boost.previous_volume * 100.0).round() as i32
+        );
+        boost.volume_info().map(Some)
+    }
+
+    /// Whether the focused app has an audio session to adjust, found the same
+    /// way the volume keys find it. Changes no volume.
+    pub fn focused_app_has_session(&self) -> Result<bool> {
+        let target = self.resolve_target()?;
+        Ok(self.find_target_session(&target)?.is_some())
+    }
+
+    /// Process name of the foreground window's app
+    pub fn focused_app_name(&self) -> Option<String> {
+        self.resolve_target().ok().map(|target| target.process_name)
+    }
+
+    /// Audio sessions on the default render device, one per process name,
+    /// with the playing ones first
+    pub fn list_sessions(&self) -> Result<Vec<SessionInfo>> {
+        unsafe {
+            let device = self.default_device()?;
+            let session_manager: IAudioSessionManager2 = device.Activate(CLSCTX_ALL, None)?;
+            let session_enumerator = session_manager.GetSessionEnumerator()?;
+
+            let mut sessions: Vec<SessionInfo> = Vec::new();
+            for i in 0..session_enumerator.GetCount()? {
+                let Ok(session_control) = session_enumerator.GetSession(i) else {
+                    continue;
+                };
+                let state = session_control
+                    .GetState()
+                    .unwrap_or(AudioSessionStateExpired);
+                if state == AudioSessionStateExpired {
+                    continue;
+                }
+
+                let Ok(session_control2) = session_control.cast::<IAudioSessionControl2>() else {
+                    continue;
+                };
+                let Ok(session_pid) = session_control2.GetProcessId() else {
+                    continue;
+                };
+                // Inaccessible processes are skipped
+                let Some((app_name, exe_path)) =
+                    self.session_app_info(&session_control2, session_pid)
+                else {
+                    continue;
+                };
+                if sessions
+                    .iter()
+                    .any(|session| session.app_name.eq_ignore_ascii_case(&app_name))
+                {
+                    continue;
+                }
+
+                let Ok(simple_audio) = session_control.cast::<ISimpleAudioVolume>() else {
+                    continue;
+                };
+                let peak = session_control
+                    .cast::<IAudioMeterInformation>()
+                    .and_then(|meter| meter.GetPeakValue())
+                    .unwrap_or(0.0);
+
+                sessions.push(SessionInfo {
+                    app_name,
+                    process_id: session_pid,
+                    exe_path,
+                    volume: simple_audio.GetMasterVolume().unwrap_or(0.0),
+                    is_muted: simple_audio.GetMute().is_ok_and(|muted| muted.as_bool()),
+                    peak,
+                    is_active: state == AudioSessionStateActive,
+                    is_system_sounds: is_system_sounds(&session_control2),
+                });
+            }
+
+            sessions.sort_by_key(|session| !session.is_active);
+            Ok(sessions)
+        }
+    }
+
+    /// Every session on the default render device, expired ones included,
+    /// with what is needed to tell leftovers of crashed apps apart. Read-only.
+    pub fn session_diagnostics(&self) -> Result<Vec<SessionDiagnostic>> {
+        unsafe {
+            let device = self.default_device()?;
+            let session_manager: IAudioSessionManager2 = device.Activate(CLSCTX_ALL, None)?;
+            let session_enumerator = session_manager.GetSessionEnumerator()?;
+
+            let mut sessions = Vec::new();
+            for i in 0..session_enumerator.GetCount()? {
+                let Ok(session_control) = session_enumerator.GetSession(i) else {
+                    continue;
+                };
+                let Ok(session_control2) = session_control.cast::<IAudioSessionControl2>() else {
+                    continue;
+                };
+
+                let process_id = session_control2.GetProcessId().unwrap_or(0);
+                let is_system_sounds = is_system_sounds(&session_control2);
+                let process_name = self
+                    .session_app_info(&session_control2, process_id)
+                    .map(|(name, _)| name);
+                let process_gone = !is_system_sounds && !process_running(process_id);
+
+                let name = process_name
+                    .or_else(|| {
+                        let display_name = session_control.GetDisplayName().ok()?;
+                        let name = display_name.to_string().ok();
+                        CoTaskMemFree(Some(display_name.0 as *const _));
+                        name.filter(|name| !name.is_empty())
+                    })
+                    .unwrap_or_else(|| format!("PID {}", process_id));
+
+                sessions.push(SessionDiagnostic {
+                    name,
+                    process_id,
+                    state: session_control
+                        .GetState()
+                        .unwrap_or(AudioSessionStateExpired),
+                    is_system_sounds,
+                    process_gone,
+                });
+            }
+
+            Ok(sessions)
+        }
+    }
+
+    /// Friendly names of the active output devices, each with whether it is
+    /// the default for the configured role
+    pub fn list_render_devices(&self) -> Result<Vec<(String, bool)>> {
+        unsafe {
+            let default_id = self.default_device().ok().and_then(|d| device_id(&d));
+            let devices = self
+                .device_enumerator()
+                .EnumAudioEndpoints(eRender, DEVICE_STATE_ACTIVE)?;
+
+            let mut result = Vec::new();
+            for i in 0..devices.GetCount()? {
+                let Ok(device) = devices.Item(i) else {
+                    continue;
+                };
+                let Some(name) = device_friendly_name(&device) else {
+                    continue;
+                };
+                let is_default = default_id.is_some() && device_id(&device) == default_id;
+                result.push((name, is_default));
+            }
+
+            Ok(result)
+        }
+    }
+
+    /// Friendly name of the default output device for the configured role,
+    /// the one whose sessions and master volume are adjusted
+    pub fn default_device_name(&self) -> Option<String> {
+        self.default_device()
+            .ok()
+            .and_then(|device| device_friendly_name(&device))
+    }
+
+    /// Make the active output device called `name` the default one (for the
+    /// console and multimedia roles, like the Windows sound settings).
+    /// Returns false if no such device is active anymore.
+    pub fn set_default_render_device(&self, name: &str) -> Result<bool> {
+        unsafe {
+            let devices = self
+                .device_enumerator()
+                .EnumAudioEndpoints(eRender, DEVICE_STATE_ACTIVE)?;
+
+            for i in 0..devices.GetCount()? {
+                let Ok(device) = devices.Item(i) else {
+                    continue;
+                };
+                if device_friendly_name(&device).as_deref() != Some(name) {
+                    continue;
+                }
+
+                let id = device.GetId()?;
+                let policy_config: IPolicyConfig =
+                    CoCreateInstance(&POLICY_CONFIG_CLIENT, None, CLSCTX_ALL)?;
+                let result = policy_config
+                    .SetDefaultEndpoint(PCWSTR(id.0), eConsole)
+                    .ok()
+                    .and_then(|()| {
+                        policy_config
+                            .SetDefaultEndpoint(PCWSTR(id.0), eMultimedia)
+                            .ok()
+                    });
+                CoTaskMemFree(Some(id.0 as *const _));
+                result?;
+
+                self.watch_session_creation();
+                return Ok(true);
+            }
+
+            Ok(false)
+        }
+    }
+
+    fn device_enumerator(&self) -> IMMDeviceEnumerator {
+        self.device_enumerator.lock().clone()
+    }
+
+    /// Start over with a new device enumerator after the system resumes from
+    /// sleep: the audio service may have restarted, leaving the old one and
+    /// the sessions obtained through it unusable. Returns the name of the
+    /// default device, which is resolved again to check the new enumerator.
+    pub fn reset_after_resume(&self) -> Result<Option<String>> {
+        let device_enumerator: IMMDeviceEnumerator =
+            unsafe { CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)? };
+        *self.device_enumerator.lock() = device_enumerator;
+
+        self.ramps.lock().clear();
+        self.session_devices.lock().clear();
+        self.session_watcher.clear();
+        self.consecutive_failures.store(0, Ordering::SeqCst);
+        self.watch_session_creation();
+
+        let device = self.default_device()?;
+        Ok(device_friendly_name(&device))
+    }
+
+    /// Forget everything remembered about apps: the last adjusted, targeted
+    /// and heard ones, the mutes, whispers and solos to undo, ramps and
+    /// routed devices. Volumes already set are left as they are.
+    pub fn reset_app_state(&self) {
+        *self.last_app.lock() = None;
+        *self.last_two_targets.lock() = [None, None];
+        *self.active_target.lock() = None;
+        self.recently_audible.lock().clear();
+        self.muted_apps.lock().clear();
+        self.whispering.lock().clear();
+        self.solo_muted.lock().clear();
+        self.ramps.lock().clear();
+        self.session_devices.lock().clear();
+        *self.temporary_armed.lock() = None;
+        self.temporary.lock().clear();
+    }
+
+    /// Default render device for the configured role
+    fn default_device(&self) -> Result<IMMDevice> {
+        let role = match self.config.read().device_role {
+            DeviceRole::Console => eConsole,
+            DeviceRole::Multimedia => eMultimedia,
+            DeviceRole::Communications => eCommunications,
+        };
+        unsafe {
+            self.device_enumerator()
+                .GetDefaultAudioEndpoint(eRender, role)
+        }
+    }
+
+    /// Find the audio session of a process: on the device it was last found
+    /// on if that wasn't the default, then on the default render device and
+    /// then on the other active ones (an app may be routed to a non-default
+    /// output)
+    fn find_session(
+        &self,
+        process_id: Option<u32>,
+        process_name: &str,
+    ) -> Result<Option<FoundSession>> {
+        let key = app_key(process_name);
+        if let Some(found) = self.find_session_on_cached_device(&key, process_id, process_name) {
+            return Ok(Some(found));
+        }
+
+        unsafe {
+            // Obtener dispositivo de audio predeterminado
+            let device = self.default_device()?;
+
+            if let Some((control, match_kind)) =
+                self.find_session_on_device(&device, process_id, process_name)?
+            {
+                self.session_devices.lock().remove(&key);
+                return Ok(Some(FoundSession {
+                    control,
+                    match_kind,
+                    device_name: None,
+                }));
+            }
+
+            let default_id = device_id(&device);
+            let devices = self
+                .device_enumerator()
+                .EnumAudioEndpoints(eRender, DEVICE_STATE_ACTIVE)?;
+
+            for i in 0..devices.GetCount()? {
+                let Ok(other_device) = devices.Item(i) else {
+                    continue;
+                };
+                if device_id(&other_device) == default_id {
+                    continue;
+                }
+
+                match self.find_session_on_device(&other_device, process_id, process_name) {
+                    Ok(Some((control, match_kind))) => {
+                        let device_name = device_friendly_name(&other_device);
+                        log_event!(
+                            "[DEBUG] Found session for '{}' on non-default device {}",
+                            process_name,
+                            device_name.as_deref().unwrap_or("(unknown)")
+                        );
+                        if let Some(id) = device_id(&other_device) {
+                            self.session_devices.lock().insert(key, id);
+                        }
+                        return Ok(Some(FoundSession {
+                            control,
+                            match_kind,
+                            device_name,
+                        }));
+                    }
+                    Ok(None) => {}
+                    Err(e) => log_event!("[DEBUG] Skipping device {}: {}", i, e),
+                }
+            }
+
+            self.session_devices.lock().remove(&key);
+            Ok(None)
+        }
+    }
+
+    /// Look for the session on the device `key` was last found on, if any.
+    /// A device that is gone, or no longer has the session, is forgotten.
+    fn find_session_on_cached_device(
+        &self,
+        key: &str,
+        process_id: Option<u32>,
+        process_name: &str,
+    ) -> Option<FoundSession> {
+        let id = self.session_devices.lock().get(key).cloned()?;
+
+        let found = unsafe {
+            self.device_enumerator()
+                .GetDevice(&HSTRING::from(id.as_str()))
+        }
+        .ok()
+        .filter(|device| unsafe { device.GetState() } == Ok(DEVICE_STATE_ACTIVE))
+        .and_then(|device| {
+            self.find_session_on_device(&device, process_id, process_name)
+                .ok()
+                .flatten()
+                .map(|(control, match_kind)| FoundSession {
+                    control,
+                    match_kind,
+                    device_name: device_friendly_name(&device),
+                })
+        });
+
+        if found.is_none() {
+            self.session_devices.lock().remove(key);
+        }
+        found
+    }
+
+    /// Find the audio session of a process on `device`, first by exact PID
+    /// (when given) and then by process name
+    fn find_session_on_device(
+        &self,
+        device: &IMMDevice,
+        process_id: Option<u32>,
+        process_name: &str,
+    ) -> Result<Option<(IAudioSessionControl2, MatchKind)>> {
+        unsafe {
+            // Obtener sesión de audio
+            let session_manager: IAudioSessionManager2 = device.Activate(CLSCTX_ALL, None)?;
+            let session_enumerator = session_manager.GetSessionEnumerator()?;
+
+            let count = session_enumerator.GetCount()?;
+
+            Ok(match_session(
+                sessions(&session_enumerator, count),
+                process_id,
+                process_name,
+                |session, pid| self.session_app_info(session, pid).map(|(name, _)| name),
+            ))
+        }
+    }
+
+    fn adjust_session_volume(
+        &self,
+        session_control2: IAudioSessionControl2,
+        adjustment: Adjustment,
+        process_name: String,
+        exe_path: Option<String>,
+    ) -> Result<VolumeInfo> {
+        unsafe {
+            let simple_audio = session_control2.cast::<ISimpleAudioVolume>()?;
+
+            // Volumen actual (el objetivo si hay una rampa en curso)
+            let current_volume = match self.ramp_target(&process_name) {
+                Some(target) => target,
+                None => simple_audio.GetMasterVolume()?,
+            };
+
+            let new_volume;
+            let is_muted;
+            let mut cap_reached = false;
+            let mut floor_reached = false;
+            let mut limit_reached = false;
+            let mut quiet_hours = false;
+            let mut restore_in = None;
+
+            if let Adjustment::Mute = adjustment {
+                // Toggle mute
+                let current_mute = simple_audio.GetMute()?.as_bool();
+                simple_audio.SetMute(!current_mute, std::ptr::null())?;
+                new_volume = current_volume;
+                is_muted = !current_mute;
+                self.record_mute(&process_name, is_muted);
+            } else if let Adjustment::Read = adjustment {
+                new_volume = current_volume;
+                is_muted = simple_audio.GetMute()?.as_bool();
+            } else {
+                // Ajustar volumen
+                let (volume_step, volume_up, volume_down) = self.resolve_step(adjustment);
+                let max_volume;
+                (max_volume, quiet_hours) = self.config.read().effective_max_volume(&process_name);
+
+                let min_volume = self
+                    .config
+                    .read()
+                    .effective_min_volume(&process_name, max_volume);
+
+                let stepped = compute_new_volume(
+                    current_volume,
+                    volume_step,
+                    volume_up,
+                    volume_down,
+                    max_volume,
+                    self.proportional_ratio(adjustment),
+                );
+                let stepped = self.snap_step(adjustment, current_volume, stepped, max_volume);
+                // Volume Down stops at the floor, but never raises an app
+                // that is already below it
+                new_volume = if volume_down {
+                    stepped.max(min_volume.min(current_volume))
+                } else {
+                    stepped
+                };
+                cap_reached = volume_up && max_volume < 1.0 && new_volume >= max_volume;
+                floor_reached = volume_down && min_volume > 0.0 && new_volume <= min_volume;
+                limit_reached = at_limit(new_volume, volume_up, volume_down);
+
+                // Already at 0% or 100% (or the cap): nothing to set, the
+                // toast still confirms the level
+                if new_volume != current_volume {
+                    let ramp_ms = self.config.read().volume_ramp_ms;
+                    if ramp_ms > 0 {
+                        self.start_ramp(&process_name, simple_audio.clone(), new_volume, ramp_ms)?;
+                    } else {
+                        simple_audio.SetMasterVolume(new_volume, std::ptr::null())?;
+                    }
+                }
+
+                // Volume Up/Down on a muted app: unmute it too when configured
+                let was_muted = simple_audio.GetMute()?.as_bool();
+                is_muted = muted_after_step(was_muted, self.config.read().input.unmute_on_adjust);
+                if was_muted && !is_muted {
+                    simple_audio.SetMute(false, std::ptr::null())?;
+                    self.record_mute(&process_name, false);
+                }
+
+                restore_in = self.track_temporary(adjustment, &process_name, current_volume);
+            }
+
+            if !matches!(adjustment, Adjustment::Read) {
+                *self.last_app.lock() = Some(process_name.clone());
+            }
+            self.session_watcher.watch(&session_control2, &process_name);
+
+            Ok(VolumeInfo {
+                app_name: process_name,
+                exe_path,
+                volume: new_volume,
+                previous_volume: current_volume,
+                is_muted,
+                cap_reached,
+                floor_reached,
+                limit_reached,
+                device_name: None,
+                is_no_session: false,
+                quiet_hours,
+                restore_in,
+                match_kind: MatchKind::ByPid,
+            })
+        }
+    }
+
+    /// Target of the ramp in progress for `app_name`, if any
+    fn ramp_target(&self, app_name: &str) -> Option<f32> {
+        self.ramps
+            .lock()
+            .get(&app_key(app_name))
+            .map(|ramp| ramp.to)
+    }
+
+    /// Start moving `volume` to `target` over `ramp_ms`. A ramp already in
+    /// progress for the app is retargeted from its current level.
+    fn start_ramp(
+        &self,
+        app_name: &str,
+        volume: ISimpleAudioVolume,
+        target: f32,
+        ramp_ms: u64,
+    ) -> Result<()> {
+        let mut ramps = self.ramps.lock();
+        let from = match ramps.get(&app_key(app_name)) {
+            Some(ramp) => ramp.current().0,
+            None => unsafe { volume.GetMasterVolume()? },
+        };
+
+        ramps.insert(
+            app_key(app_name),
+            Ramp {
+                volume,
+                from,
+                to: target,
+                started: Instant::now(),
+                duration: Duration::from_millis(ramp_ms),
+            },
+        );
+        Ok(())
+    }
+
+    /// Advance the ramps in progress; called from the main loop
+    pub fn tick_ramps(&self) {
+        let mut ramps = self.ramps.lock();
+        if ramps.is_empty() {
+            return;
+        }
+
+        ramps.retain(|app_name, ramp| {
+            let (level, finished) = ramp.current();
+            if let Err(e) = unsafe { ramp.volume.SetMasterVolume(level, std::ptr::null()) } {
+                log_event!("Error ramping volume of '{}': {}", app_name, e);
+                return false;
+            }
+            !finished
+        });
+    }
+
+    /// Called from the main loop: when quiet hours begin and
+    /// `lower_at_start` is set, turn down the apps above the night cap
+    pub fn tick_quiet_hours(&self) {
+        let config = self.config.read();
+        let active = config.quiet_hours.is_active();
+        let lower = config.quiet_hours.lower_at_start;
+        let quiet_max = config.quiet_hours.max_volume;
+        drop(config);
+
+        let was_active = self.quiet_hours_active.swap(active, Ordering::SeqCst);
+        if !active || was_active {
+            return;
+        }
+
+        log_event!("Quiet hours started");
+        if !lower {
+            return;
+        }
+
+        let sessions = match self.list_sessions() {
+            Ok(sessions) => sessions,
+            Err(e) => {
+                log_event!("Error listing sessions for quiet hours: {}", e);
+                return;
+            }
+        };
+
+        for session in sessions.iter().filter(|session| session.volume > quiet_max) {
+            match self.set_app_volume_by_name(&session.app_name, quiet_max) {
+                Ok(info) => log_event!(
+                    "Quiet hours: lowered '{}' to {}%",
+                    session.app_name,
+                    (info.volume * 100.0).round() as i32
+                ),
+                Err(e) => log_event!("Quiet hours: error lowering '{}': {}", session.app_name, e),
+            }
+        }
+    }
+
+    /// Called from the main loop: with `TargetStrategy::RecentlyAudible`,
+    /// checks the meters every `RECENTLY_AUDIBLE_POLL_INTERVAL` and moves
+    /// the apps playing to the front of `recently_audible`. Windows' system
+    /// sounds are left out, so Volime's own beeps don't take over the keys.
+    pub fn tick_recently_audible(&self) {
+        if self.config.read().target_strategy != TargetStrategy::RecentlyAudible {
+            self.recently_audible.lock().clear();
+            return;
+        }
+
+        {
+            let mut checked_at = self.audible_checked_at.lock();
+            if checked_at.is_some_and(|at| at.elapsed() < RECENTLY_AUDIBLE_POLL_INTERVAL) {
+                return;
+            }
+            *checked_at = Some(Instant::now());
+        }
+
+        let sessions = match self.list_sessions() {
+            Ok(sessions) => sessions,
+            Err(e) => {
+                log_event!("[DEBUG] Error listing sessions for recently audible: {}", e);
+                return;
+            }
+        };
+
+        let mut recently_audible = self.recently_audible.lock();
+        for session in sessions.into_iter().filter(|session| {
+            !session.is_system_sounds && !session.is_muted && session.peak > AUDIBLE_PEAK_THRESHOLD
+        }) {
+            recently_audible
+                .retain(|(target, _)| !target.process_name.eq_ignore_ascii_case(&session.app_name));
+            let target = Target {
+                process_id: session.process_id,
+                process_name: session.app_name,
+                exe_path: session.exe_path,
+                window_class: None,
+            };
+            recently_audible.push_front((target, Instant::now()));
+        }
+        recently_audible.truncate(RECENTLY_AUDIBLE_CAPACITY);
+    }
+
+    /// Listen for new sessions on the current default device, replacing the
+    /// registration with the previous one
+    fn watch_session_creation(&self) {
+        let mut session_creation = self.session_creation.lock();
+        *session_creation = None;
+        match self
+            .default_device()
+            .and_then(|device| SessionCreationWatcher::register(&device))
+        {
+            Ok(watcher) => *session_creation = Some(watcher),
+            Err(e) => log_event!("Error watching for new audio sessions: {}", e),
+        }
+    }
+
+    /// Arm `temporary_key`: the next app volume step within
+    /// `TEMPORARY_ARM_WINDOW` reverts after `temporary_restore_secs`.
+    /// Returns that delay.
+    pub fn arm_temporary(&self) -> Duration {
+        *self.temporary_armed.lock() = Some(Instant::now());
+        self.config.read().temporary_restore()
+    }
+
+    /// Record a volume step of `app_name` from `previous_volume`. An armed
+    /// step becomes temporary (a further one keeps the first level to return
+    /// to and restarts the delay); any other step cancels a pending restore.
+    /// Returns how long until the app reverts, for the toast.
+    fn track_temporary(
+        &self,
+        adjustment: Adjustment,
+        app_name: &str,
+        previous_volume: f32,
+    ) -> Option<Duration> {
+        let key = app_key(app_name);
+        let armed = matches!(adjustment, Adjustment::Step { .. })
+            && self
+                .temporary_armed
+                .lock()
+                .take()
+                .is_some_and(|at| at.elapsed() <= TEMPORARY_ARM_WINDOW);
+        if !armed {
+            if self.temporary.lock().remove(&key).is_some() {
+                log_event!("Temporary change of '{}' kept", app_name);
+            }
+            return None;
+        }
+
+        let restore_after = self.config.read().temporary_restore();
+        let restore_at = Instant::now() + restore_after;
+        self.temporary
+            .lock()
+            .entry(key)
+            .and_modify(|change| change.restore_at = restore_at)
+            .or_insert_with(|| TemporaryChange {
+                app_name: app_name.to_string(),
+                volume: previous_volume,
+                restore_at,
+            });
+        log_event!(
+            "Temporary change of '{}', reverting in {} s",
+            app_name,
+            restore_after.as_secs()
+        );
+        Some(restore_after)
+    }
+
+    /// Called from the main loop: returns the apps whose temporary change is
+    /// due to the volume they had before it
+    pub fn tick_temporary_volumes(&self) {
+        let due: Vec<TemporaryChange> = {
+            let mut temporary = self.temporary.lock();
+            if temporary.is_empty() {
+                return;
+            }
+            let now = Instant::now();
+            let keys: Vec<String> = temporary
+                .iter()
+                .filter(|(_, change)| change.restore_at <= now)
+                .map(|(key, _)| key.clone())
+                .collect();
+            keys.iter()
+                .filter_map(|key| temporary.remove(key))
+                .collect()
+        };
+
+        for change in due {
+            match self.set_app_volume_by_name(&change.app_name, change.volume) {
+                Ok(volume_info) if volume_info.is_no_session => {
+                    log_event!(
+                        "'{}' is gone, its temporary change isn't reverted",
+                        change.app_name
+                    );
+                }
+                Ok(volume_info) => log_event!(
+                    "Temporary change of '{}' reverted to {}%",
+                    change.app_name,
+                    (volume_info.volume * 100.0).round() as i32
+                ),
+                Err(e) => log_event!(
+                    "Error reverting the temporary change of '{}': {}",
+                    change.app_name,
+                    e
+                ),
+            }
+        }
+    }
+
+    /// Called from the main loop: sets the `startup_volume` of the apps whose
+    /// session appeared `STARTUP_VOLUME_DELAY` ago or more. Each new session
+    /// is set once, so the app can be raised again afterwards.
+    pub fn tick_startup_volumes(&self) {
+        let created = match &*self.session_creation.lock() {
+            Some(watcher) => watcher.take_created(STARTUP_VOLUME_DELAY),
+            None => return,
+        };
+
+        for (instance_id, session) in created {
+            let Ok(process_id) = (unsafe { session.GetProcessId() }) else {
+                continue;
+            };
+            if process_id == 0 || is_system_sounds(&session) {
+                continue;
+            }
+            let Ok((process_name, _)) = self.get_process_info(process_id) else {
+                continue;
+            };
+
+            let volume = startup_volume_once(
+                &mut self.startup_handled.lock(),
+                &instance_id,
+                &process_name,
+                &self.config.read(),
+            );
+            let Some(volume) = volume else {
+                continue;
+            };
+
+            let result = session
+                .cast::<ISimpleAudioVolume>()
+                .and_then(|simple_audio| unsafe {
+                    simple_audio.SetMasterVolume(volume, std::ptr::null())
+                });
+            match result {
+                Ok(()) => log_event!(
+                    "'{}' started playing, set to its startup volume {}%",
+                    process_name,
+                    (volume * 100.0).round() as i32
+                ),
+                Err(e) => log_event!(
+                    "Error setting the startup volume of '{}': {}",
+                    process_name,
+                    e
+                ),
+            }
+        }
+    }
+
+    /// Turn the `[ducking]` target apps down while the priority app is
+    /// audible, and back up once it has been silent (or gone) for
+    /// `release_ms`. Checks the meters every `DUCKING_POLL_INTERVAL`.
+    pub fn tick_ducking(&self) {
+        let ducking = self.config.read().ducking.clone();
+        if !ducking.is_configured() {
+            // Turned off while ducked
+            self.restore_ducked();
+            return;
+        }
+
+        {
+            let mut checked_at = self.ducking_checked_at.lock();
+            if checked_at.is_some_and(|at| at.elapsed() < DUCKING_POLL_INTERVAL) {
+                return;
+            }
+            *checked_at = Some(Instant::now());
+        }
+
+        let sessions = match self.list_sessions() {
+            Ok(sessions) => sessions,
+            Err(e) => {
+                log_event!("[DEBUG] Error listing sessions for ducking: {}", e);
+                return;
+            }
+        };
+
+        let priority = app_key(&ducking.priority_app);
+        let audible = sessions.iter().any(|session| {
+            app_key(&session.app_name) == priority
+                && !session.is_muted
+                && session.peak > AUDIBLE_PEAK_THRESHOLD
+        });
+
+        let mut state = self.ducking.lock();
+        if let Some(state) = state.as_mut().filter(|_| audible) {
+            state.last_heard = Instant::now();
+            return;
+        }
+
+        if audible {
+            let mut volumes = HashMap::new();
+            for session in sessions.iter().filter(|session| {
+                let key = app_key(&session.app_name);
+                ducking.target_apps.iter().any(|app| app_key(app) == key)
+            }) {
+                let ducked = session.volume * ducking.volume.clamp(0.0, 1.0);
+                match self.set_app_volume_by_name(&session.app_name, ducked) {
+                    Ok(_) => {
+                        volumes.insert(session.app_name.clone(), session.volume);
+                    }
+                    Err(e) => log_event!("Ducking: error lowering '{}': {}", session.app_name, e),
+                }
+            }
+            log_event!(
+                "Ducking: '{}' is playing, lowered {} app(s)",
+                ducking.priority_app,
+                volumes.len()
+            );
+            *state = Some(Ducking {
+                volumes,
+                last_heard: Instant::now(),
+            });
+        } else if state.as_ref().is_some_and(|state| {
+            state.last_heard.elapsed() >= Duration::from_millis(ducking.release_ms)
+        }) {
+            drop(state);
+            log_event!("Ducking: '{}' went quiet", ducking.priority_app);
+            self.restore_ducked();
+        }
+    }
+
+    /// Give the apps lowered by ducking their previous volume back. Called
+    /// when the priority app goes quiet and before exiting.
+    pub fn restore_ducked(&self) {
+        let Some(ducking) = self.ducking.lock().take() else {
+            return;
+        };
+
+        let sessions = self.list_sessions().unwrap_or_default();
+        for (app_name, volume) in ducking.volumes {
+            // Keep a mute set meanwhile; the app may also have exited
+            let Some(session) = sessions
+                .iter()
+                .find(|session| session.app_name.eq_ignore_ascii_case(&app_name))
+            else {
+                continue;
+            };
+            let muted = session.is_muted;
+
+            match self.set_app_volume(&app_name, volume, muted) {
+                Ok(_) => log_event!(
+                    "Ducking: restored '{}' to {}%",
+                    app_name,
+                    (volume * 100.0).round() as i32
+                ),
+                Err(e) => log_event!("Ducking: error restoring '{}': {}", app_name, e),
+            }
+        }
+    }
+
+    /// Step size and direction of a volume change; `Mute` moves nothing
+    fn resolve_step(&self, adjustment: Adjustment) -> (f32, bool, bool) {
+        adjustment.resolve(|| self.next_step())
+    }
+
+    /// `snap_volume` with the configured `input.snap_points`, for steps of
+    /// the keys; explicit amounts are applied as given. Never goes past `max`.
+    fn snap_step(&self, adjustment: Adjustment, current: f32, new_volume: f32, max: f32) -> f32 {
+        let config = self.config.read();
+        if !matches!(adjustment, Adjustment::Step { .. }) || config.input.snap_points.is_empty() {
+            return new_volume;
+        }
+        snap_volume(
+            current,
+            new_volume,
+            &config.input.snap_points,
+            config.input.snap_threshold(),
+        )
+        .min(max)
+        .clamp(0.0, 1.0)
+    }
+
+    /// `input.proportional_ratio` for steps of the keys; explicit amounts
+    /// are applied as given
+    fn proportional_ratio(&self, adjustment: Adjustment) -> Option<f32> {
+        match adjustment {
+            Adjustment::Step { .. } => self.config.read().input.proportional_ratio(),
+            _ => None,
+        }
+    }
+
+    /// Size of the next volume step: the configured step, multiplied while presses
+    /// keep arriving quickly when acceleration is enabled
+    fn next_step(&self) -> f32 {
+        let now = Instant::now();
+        let mut press_streak = self.press_streak.lock();
+        let streak = match *press_streak {
+            Some((last, streak)) if now.duration_since(last) <= ACCELERATION_WINDOW => streak + 1,
+            _ => 0,
+        };
+        *press_streak = Some((now, streak));
+
+        let config = self.config.read();
+        if config.input.acceleration {
+            config.volume_step() * (1 + streak / 5).min(MAX_ACCELERATION) as f32
+        } else {
+            config.volume_step()
+        }
+    }
+
+    /// Name and executable to show for a session: its process', or
+    /// `SYSTEM_SOUNDS` with the Volume Mixer's icon for the system sounds
+    fn session_app_info(
+        &self,
+        session: &IAudioSessionControl2,
+        process_id: u32,
+    ) -> Option<(String, Option<String>)> {
+        if is_system_sounds(session) {
+            let icon_path = std::env::var("SystemRoot")
+                .ok()
+                .map(|root| format!("{}\\System32\\SndVol.exe", root));
+            return Some((SYSTEM_SOUNDS.to_string(), icon_path));
+        }
+        self.get_process_info(process_id).ok()
+    }
+
+    fn get_process_info(&self, process_id: u32) -> Result<(String, Option<String>)> {
+        unsafe {
+            let process_handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id)?;
+
+            if process_handle.is_invalid() {
+                return Ok((format!("PID {}", process_id), None));
+            }
+
+            let path = image_path(process_handle);
+            let _ = CloseHandle(process_handle);
+
+            match path {
+                Some(path_str) => Ok((file_name(&path_str).to_string(), Some(path_str))),
+                None => Ok((format!("PID {}", process_id), None)),
+            }
+        }
+    }
+}
+
+fn is_system_sounds(session: &IAudioSessionControl2) -> bool {
+    unsafe { session.IsSystemSoundsSession() == S_OK }
+}
+
+/// Whether a process with this ID is still running
+fn process_running(process_id: u32) -> bool {
+    unsafe {
+        let Ok(process_handle) = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id)
+        else {
+            return false;
+        };
+        let mut exit_code = 0;
+        let running = GetExitCodeProcess(process_handle, &mut exit_code).is_ok()
+            && exit_code == STILL_ACTIVE.0 as u32;
+        let _ = CloseHandle(process_handle);
+        running
+    }
+}
+
+/// Full path of the executable of an open process
+unsafe fn image_path(process_handle: HANDLE) -> Option<String> {
+    let mut buffer = [0u16; 260];
+    let mut size = buffer.len() as u32;
+
+    let pwstr = PWSTR::from_raw(buffer.as_mut_ptr());
+    QueryFullProcessImageNameW(process_handle, PROCESS_NAME_WIN32, pwstr, &mut size).ok()?;
+
+    let path = OsString::from_wide(&buffer[..size as usize]);
+    Some(path.to_string_lossy().to_string())
+}
+
+/// Extraer solo el nombre del archivo
+fn file_name(path: &str) -> &str {
+    path.split('\\').last().unwrap_or(path)
+}
+
+/// A visible top-level window of a process running `app_name`, to bring the
+/// app to the front. Owned and tool windows (dialogs, tray helpers) are
+/// skipped; `None` when the app has no such window, e.g. a background player.
+pub fn app_main_window(app_name: &str) -> Option<HWND> {
+    struct Search<'a> {
+        app_name: &'a str,
+        found: Option<HWND>,
+    }
+
+    unsafe extern "system" fn visit(hwnd: HWND, lparam: LPARAM) -> BOOL {
+        let search = &mut *(lparam.0 as *mut Search);
+
+        let tool = GetWindowLongW(hwnd, GWL_EXSTYLE) as u32 & WS_EX_TOOLWINDOW.0 != 0;
+        let owned = GetWindow(hwnd, GW_OWNER).is_ok_and(|owner| !owner.is_invalid());
+        if !IsWindowVisible(hwnd).as_bool() || tool || owned {
+            return TRUE;
+        }
+
+        let Ok(process_id) = window_process_id(hwnd) else {
+            return TRUE;
+        };
+        let Ok(process_handle) = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id)
+        else {
+            return TRUE;
+        };
+        let path = image_path(process_handle);
+        let _ = CloseHandle(process_handle);
+
+        if path.is_some_and(|path| file_name(&path).eq_ignore_ascii_case(search.app_name)) {
+            search.found = Some(hwnd);
+            return FALSE;
+        }
+        TRUE
+    }
+
+    let mut search = Search {
+        app_name,
+        found: None,
+    };
+    unsafe {
+        let _ = EnumWindows(Some(visit), LPARAM(&mut search as *mut Search as isize));
+    }
+    search.found
+}
+
+/// Remote Desktop redirects audio to the client, so the local endpoints and
+/// app sessions may be missing or inaccessible
+pub fn is_remote_session() -> bool {
+    unsafe { GetSystemMetrics(SM_REMOTESESSION) != 0 }
+}
+
+/// Class name of a window, e.g. "Chrome_WidgetWin_1"
+fn window_class(hwnd: HWND) -> Option<String> {
+    unsafe {
+        if hwnd.is_invalid() {
+            return None;
+        }
+
+        let mut buffer = [0u16; 256];
+        let len = GetClassNameW(hwnd, &mut buffer);
+        (len > 0).then(|| String::from_utf16_lossy(&buffer[..len as usize]))
+    }
+}
+
+/// Top-level window under the mouse cursor
+unsafe fn window_under_cursor() -> HWND {
+    let mut point = POINT::default();
+    if GetCursorPos(&mut point).is_err() {
+        return HWND::default();
+    }
+
+    let hwnd = WindowFromPoint(point);
+    if hwnd.is_invalid() {
+        return hwnd;
+    }
+    GetAncestor(hwnd, GA_ROOT)
+}
+
+/// The foreground window if it is on the monitor under the cursor; otherwise
+/// the app window highest in the z-order there, i.e. the one most recently
+/// active on that monitor
+unsafe fn foreground_on_cursor_monitor() -> HWND {
+    let foreground = GetForegroundWindow();
+    let mut point = POINT::default();
+    if GetCursorPos(&mut point).is_err() {
+        return foreground;
+    }
+
+    let monitor = MonitorFromPoint(point, MONITOR_DEFAULTTONEAREST);
+    if !foreground.is_invalid()
+        && MonitorFromWindow(foreground, MONITOR_DEFAULTTONEAREST) == monitor
+    {
+        return foreground;
+    }
+
+    let mut candidate = GetTopWindow(None).unwrap_or_default();
+    while !candidate.is_invalid() {
+        let ex_style = GetWindowLongW(candidate, GWL_EXSTYLE) as u32;
+        if IsWindowVisible(candidate).as_bool()
+            && !IsIconic(candidate).as_bool()
+            && ex_style & WS_EX_TOOLWINDOW.0 == 0
+            && GetWindow(candidate, GW_OWNER).is_err()
+            && MonitorFromWindow(candidate, MONITOR_DEFAULTTONEAREST) == monitor
+        {
+            return candidate;
+        }
+        candidate = GetWindow(candidate, GW_HWNDNEXT).unwrap_or_default();
+    }
+
+    foreground
+}
+
+/// Process ID of a window
+fn window_process_id(hwnd: HWND) -> Result<u32> {
+    unsafe {
+        if hwnd.is_invalid() {
+            return Err(Error::from(E_FAIL));
+        }
+
+        // Obtener PID de la ventana
+        let mut process_id: u32 = 0;
+        GetWindowThreadProcessId(hwnd, Some(&mut process_id));
+
+        if process_id == 0 {
+            return Err(Error::from(E_FAIL));
+        }
+
+        Ok(process_id)
+    }
+}
+
+/// The first `count` sessions of the list with their index and process ID.
+/// A session whose process exits while the list is walked can fail; it is
+/// logged and skipped so the rest of the list is still searched.
+fn sessions(
+    enumerator: &IAudioSessionEnumerator,
+    count: i32,
+) -> impl Iterator<Item = (i32, (IAudioSessionControl2, u32))> + Clone + '_ {
+    skip_failed_sessions((0..count).map(move |index| (index, session_at(enumerator, index))))
+}
+
+fn session_at(
+    enumerator: &IAudioSessionEnumerator,
+    index: i32,
+) -> Result<(IAudioSessionControl2, u32)> {
+    unsafe {
+        let session = enumerator
+            .GetSession(index)?
+            .cast::<IAudioSessionControl2>()?;
+        let pid = session.GetProcessId()?;
+        Ok((session, pid))
+    }
+}
+
+/// The session of `process_id` among `sessions`, else the first whose app
+/// name (per `app_name`) is `process_name`. The name search covers apps like
+/// Chrome, whose audio plays from a child process.
+fn match_session<S>(
+    sessions: impl Iterator<Item = (i32, (S, u32))> + Clone,
+    process_id: Option<u32>,
+    process_name: &str,
+    mut app_name: impl FnMut(&S, u32) -> Option<String>,
+) -> Option<(S, MatchKind)> {
+    // Primero intentar buscar por PID exacto
+    if let Some(process_id) = process_id {
+        for (_, (session, session_pid)) in sessions.clone() {
+            if session_pid == process_id {
+                log_event!(
+                    "[DEBUG] Matched session for '{}' by PID {}",
+                    process_name,
+                    session_pid
+                );
+                return Some((session, MatchKind::ByPid));
+            }
+        }
+
+        log_event!(
+            "[DEBUG] Session with PID {} not found. Searching by name: {}",
+            process_id,
+            process_name
+        );
+    }
+
+    for (i, (session, session_pid)) in sessions {
+        // Try to get process info, but continue if it fails
+        let Some(session_process_name) = app_name(&session, session_pid) else {
+            log_event!(
+                "[DEBUG] Session {}: PID {} - Could not get process name",
+                i,
+                session_pid
+            );
+            continue;
+        };
+
+        log_event!(
+            "[DEBUG] Session {}: PID {} - {}",
+            i,
+            session_pid,
+            session_process_name
+        );
+
+        // Compare process names (case-insensitive)
+        if session_process_name.to_lowercase() == process_name.to_lowercase() {
+            log_event!(
+                "[DEBUG] Matched session for '{}' by name (session PID {})",
+                process_name,
+                session_pid
+            );
+            return Some((session, MatchKind::ByName));
+        }
+    }
+
+    None
+}
+
+/// The sessions that could be read, by index, logging the ones that failed
+fn skip_failed_sessions<T, E: std::fmt::Display>(
+    sessions: impl Iterator<Item = (i32, std::result::Result<T, E>)> + Clone,
+) -> impl Iterator<Item = (i32, T)> + Clone {
+    sessions.filter_map(|(index, session)| match session {
+        Ok(session) => Some((index, session)),
+        Err(e) => {
+            log_event!("[DEBUG] Session {}: skipped ({})", index, e);
+            None
+        }
+    })
+}
+
+fn device_id(device: &IMMDevice) -> Option<String> {
+    unsafe {
+        let id = device.GetId().ok()?;
+        let result = id.to_string().ok();
+        CoTaskMemFree(Some(id.0 as *const _));
+        result
+    }
+}
+
+/// Human readable name of an endpoint, e.g. "Speakers (Realtek Audio)"
+pub fn device_friendly_name(device: &IMMDevice) -> Option<String> {
+    unsafe {
+        let store = device.OpenPropertyStore(STGM_READ).ok()?;
+        let value = store.GetValue(&PKEY_Device_FriendlyName).ok()?;
+        let name = PropVariantToStringAlloc(&value).ok()?;
+        let result = name.to_string().ok();
+        CoTaskMemFree(Some(name.0 as *const _));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_volume(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn steps_are_clamped_to_the_cap() {
+        // (current, step, up, down, max, expected)
+        let cases = [
+            // Below the cap a step moves freely
+            (0.5, 0.02, true, false, 0.8, 0.52),
+            // A step past the cap stops at it
+            (0.79, 0.05, true, false, 0.8, 0.8),
+            (0.8, 0.02, true, false, 0.8, 0.8),
+            // Already above a cap that was lowered: any step pulls it down to it
+            (0.95, 0.02, true, false, 0.8, 0.8),
+            (0.95, 0.02, false, true, 0.8, 0.8),
+            // Down from the cap moves away from it
+            (0.8, 0.02, false, true, 0.8, 0.78),
+            // A 0% cap keeps the app silent
+            (0.0, 0.02, true, false, 0.0, 0.0),
+            // Caps outside 0 - 100% still leave a valid volume
+            (0.99, 0.05, true, false, 1.5, 1.0),
+            (0.5, 0.02, true, false, -0.5, 0.0),
+        ];
+
+        for (current, step, up, down, max, expected) in cases {
+            assert_volume(
+                compute_new_volume(current, step, up, down, max, None),
+                expected,
+            );
+        }
+    }
+
+    #[test]
+    fn steps_stop_at_the_limits() {
+        // (current, step, up, down, max, expected)
+        let cases = [
+            (1.0, 0.02, true, false, 1.0, 1.0),
+            (0.99, 0.02, true, false, 1.0, 1.0),
+            (0.0, 0.02, false, true, 1.0, 0.0),
+            (0.01, 0.02, false, true, 1.0, 0.0),
+            (0.8, 0.02, true, false, 0.8, 0.8),
+            (0.79, 0.02, true, false, 0.8, 0.8),
+            // Above a cap set later: any step brings it down to the cap
+            (0.9, 0.02, false, true, 0.8, 0.8),
+            // Mute leaves the level alone
+            (0.5, 0.02, false, false, 1.0, 0.5),
+            (0.0, 0.02, false, false, 1.0, 0.0),
+        ];
+
+        for (current, step, up, down, max, expected) in cases {
+            assert_volume(
+                compute_new_volume(current, step, up, down, max, None),
+                expected,
+            );
+        }
+    }
+
+    #[test]
+    fn proportional_steps() {
+        // (current, step, up, down, max, ratio, expected)
+        let cases = [
+            // A fraction of the level, never less than the step
+            (0.5, 0.01, true, false, 1.0, 0.1, 0.55),
+            (0.5, 0.01, false, true, 1.0, 0.1, 0.45),
+            (0.05, 0.01, true, false, 1.0, 0.1, 0.06),
+            (0.05, 0.01, false, true, 1.0, 0.1, 0.04),
+            // Near and at 0% the step keeps it moving
+            (0.0, 0.01, true, false, 1.0, 0.1, 0.01),
+            (0.005, 0.01, false, true, 1.0, 0.1, 0.0),
+            (0.0, 0.01, false, true, 1.0, 0.1, 0.0),
+            // The cap still applies
+            (0.75, 0.01, true, false, 0.8, 0.1, 0.8),
+            (0.8, 0.01, true, false, 0.8, 0.1, 0.8),
+            (0.95, 0.01, true, false, 1.0, 0.1, 1.0),
+            // Mute moves nothing
+            (0.5, 0.01, false, false, 1.0, 0.1, 0.5),
+        ];
+
+        for (current, step, up, down, max, ratio, expected) in cases {
+            assert_volume(
+                compute_new_volume(current, step, up, down, max, Some(ratio)),
+                expected,
+            );
+        }
+    }
+
+    #[test]
+    fn steps_snap_to_points_ahead() {
+        let points = [0.25, 0.5, 0.75];
+        // (current, new_volume, expected)
+        let cases = [
+            // Up: within the threshold of a point ahead
+            (0.46, 0.48, 0.5),
+            (0.47, 0.52, 0.5),
+            (0.40, 0.45, 0.45),
+            // Down
+            (0.54, 0.52, 0.5),
+            (0.53, 0.48, 0.5),
+            (0.60, 0.55, 0.55),
+            // Never back onto the point it starts from
+            (0.5, 0.51, 0.51),
+            (0.5, 0.49, 0.49),
+            // No step, no snap
+            (0.49, 0.49, 0.49),
+        ];
+
+        for (current, new_volume, expected) in cases {
+            assert_volume(snap_volume(current, new_volume, &points, 0.02), expected);
+        }
+    }
+
+    #[test]
+    fn steps_snap_to_the_nearest_point() {
+        assert_volume(snap_volume(0.3, 0.34, &[0.33, 0.36], 0.02), 0.33);
+        assert_volume(snap_volume(0.3, 0.35, &[0.33, 0.36], 0.02), 0.36);
+    }
+
+    #[test]
+    fn no_snap_points_leaves_the_step_alone() {
+        assert_volume(snap_volume(0.46, 0.48, &[], 0.02), 0.48);
+    }
+
+    #[test]
+    fn snap_points_outside_the_range_are_ignored() {
+        assert_volume(snap_volume(0.97, 0.99, &[1.01], 0.05), 0.99);
+        assert_volume(snap_volume(0.03, 0.01, &[-0.01], 0.05), 0.01);
+        assert_volume(snap_volume(0.97, 0.99, &[1.0, 1.01], 0.05), 1.0);
+    }
+
+    #[test]
+    fn limit_is_reported_only_in_the_step_direction() {
+        assert!(at_limit(1.0, true, false));
+        assert!(at_limit(0.0, false, true));
+        assert!(!at_limit(1.0, false, true));
+        assert!(!at_limit(0.0, true, false));
+        assert!(!at_limit(0.5, true, false));
+        assert!(!at_limit(0.0, false, false));
+    }
+
+    /// New level after `adjustment`, the way `adjust_session_volume` applies
+    /// explicit amounts (no proportional steps or snapping)
+    fn apply(adjustment: Adjustment, current: f32, max: f32) -> f32 {
+        let (step, up, down) =
+            adjustment.resolve(|| panic!("only key steps use the configured step"));
+        compute_new_volume(current, step, up, down, max, None)
+    }
+
+    #[test]
+    fn adjust_by_adds_the_amount_within_limits() {
+        // (current, delta, max, expected)
+        let cases = [
+            (0.5, 0.1, 1.0, 0.6),
+            (0.5, -0.1, 1.0, 0.4),
+            (0.2, -0.3, 1.0, 0.0),
+            (0.9, 0.3, 1.0, 1.0),
+            (0.7, 0.5, 0.8, 0.8),
+            (0.5, 0.0, 1.0, 0.5),
+        ];
+
+        for (current, delta, max, expected) in cases {
+            assert_volume(apply(Adjustment::By(delta), current, max), expected);
+        }
+    }
+
+    #[test]
+    fn adjust_by_ignores_the_configured_step() {
+        assert_eq!(Adjustment::By(0.25).resolve(|| 0.02), (0.25, true, false));
+        assert_eq!(Adjustment::By(-0.25).resolve(|| 0.02), (0.25, false, true));
+        assert_eq!(
+            Adjustment::Step {
+                up: true,
+                down: false
+            }
+            .resolve(|| 0.02),
+            (0.02, true, false)
+        );
+        assert_eq!(Adjustment::Mute.resolve(|| 0.02), (0.0, false, false));
+    }
+
+    #[test]
+    fn muted_apps_are_unmuted_by_a_step_only_when_configured() {
+        assert!(muted_after_step(true, false));
+        assert!(!muted_after_step(true, true));
+        assert!(!muted_after_step(false, false));
+        assert!(!muted_after_step(false, true));
+    }
+
+    /// Sessions as `sessions` lists them: (index, (name, process ID)), the
+    /// name standing in for the session and `None` when it can't be read
+    fn listed(sessions: &[(Option<&'static str>, u32)]) -> Vec<(i32, (Option<&'static str>, u32))> {
+        sessions
+            .iter()
+            .enumerate()
+            .map(|(index, session)| (index as i32, *session))
+            .collect()
+    }
+
+    fn find(
+        sessions: &[(Option<&'static str>, u32)],
+        process_id: Option<u32>,
+        process_name: &str,
+    ) -> Option<(Option<&'static str>, MatchKind)> {
+        match_session(
+            listed(sessions).into_iter(),
+            process_id,
+            process_name,
+            |name, _| name.map(str::to_string),
+        )
+    }
+
+    #[test]
+    fn focused_app_session_is_found_by_pid_first() {
+        // Another session of the same name comes first in the list
+        let sessions = [(Some("chrome.exe"), 10), (Some("renderer"), 20)];
+        assert_eq!(
+            find(&sessions, Some(20), "chrome.exe"),
+            Some((Some("renderer"), MatchKind::ByPid))
+        );
+    }
+
+    #[test]
+    fn focused_app_session_falls_back_to_the_process_name() {
+        // Chrome plays from a child process, not the one owning the window
+        let sessions = [(Some("spotify.exe"), 5), (Some("Chrome.exe"), 42)];
+        assert_eq!(
+            find(&sessions, Some(7), "chrome.exe"),
+            Some((Some("Chrome.exe"), MatchKind::ByName))
+        );
+        assert_eq!(
+            find(&sessions, None, "SPOTIFY.EXE"),
+            Some((Some("spotify.exe"), MatchKind::ByName))
+        );
+    }
+
+    #[test]
+    fn focused_app_without_a_session() {
+        assert_eq!(find(&[], Some(1), "game.exe"), None);
+
+        // Sessions whose name can't be read are skipped, not matched
+        let sessions = [(None, 3), (Some("spotify.exe"), 5)];
+        assert_eq!(find(&sessions, Some(7), "game.exe"), None);
+        assert_eq!(
+            find(&sessions, Some(3), "game.exe"),
+            Some((None, MatchKind::ByPid))
+        );
+    }
+
+    #[test]
+    fn app_set_by_name_matches_the_process_name_in_any_case() {
+        // `set_app_volume_by_name` searches by name only
+        let sessions = [(Some("chrome.exe"), 10), (Some("Spotify.exe"), 20)];
+        assert_eq!(
+            find(&sessions, None, "spotify.exe"),
+            Some((Some("Spotify.exe"), MatchKind::ByName))
+        );
+        assert_eq!(find(&sessions, None, "spotify"), None);
+        assert_eq!(find(&sessions, None, "potify.exe"), None);
+    }
+
+    fn startup_config() -> Config {
+        let mut config = Config::default();
+        config.startup_volume.insert(app_key("spotify.exe"), 0.4);
+        config
+    }
+
+    #[test]
+    fn new_session_gets_its_startup_volume() {
+        let mut handled = HashSet::new();
+        let config = startup_config();
+        assert_eq!(
+            startup_volume_once(&mut handled, "{session-1}", "spotify.exe", &config),
+            Some(0.4)
+        );
+        // Every new session of the app, e.g. after a restart
+        assert_eq!(
+            startup_volume_once(&mut handled, "{session-2}", "spotify.exe", &config),
+            Some(0.4)
+        );
+    }
+
+    #[test]
+    fn startup_volume_is_set_once_per_session() {
+        let mut handled = HashSet::new();
+        let config = startup_config();
+        startup_volume_once(&mut handled, "{session-1}", "spotify.exe", &config);
+        assert_eq!(
+            startup_volume_once(&mut handled, "{session-1}", "spotify.exe", &config),
+            None
+        );
+    }
+
+    #[test]
+    fn apps_without_a_startup_volume_are_left_alone() {
+        let mut handled = HashSet::new();
+        let config = startup_config();
+        assert_eq!(
+            startup_volume_once(&mut handled, "{session-1}", "chrome.exe", &config),
+            None
+        );
+    }
+
+    #[test]
+    fn startup_volume_matches_app_names_in_any_case() {
+        let mut handled = HashSet::new();
+        let config = startup_config();
+        assert_eq!(
+            startup_volume_once(&mut handled, "{session-1}", "Spotify.EXE", &config),
+            Some(0.4)
+        );
+    }
+
+    #[test]
+    fn failed_sessions_are_skipped() {
+        let listed = vec![(0, Ok("a")), (1, Err("process exited")), (2, Ok("c"))];
+        let found: Vec<_> = skip_failed_sessions(listed.into_iter()).collect();
+        assert_eq!(found, vec![(0, "a"), (2, "c")]);
+    }
+
+    #[test]
+    fn search_continues_after_a_failed_session() {
+        let listed = vec![(0, Err("gone")), (1, Err("gone")), (2, Ok(4242u32))];
+        let found = skip_failed_sessions(listed.into_iter()).find(|(_, pid)| *pid == 4242);
+        assert_eq!(found, Some((2, 4242)));
+    }
+
+    #[test]
+    fn all_sessions_failing_finds_nothing() {
+        let listed = vec![(0, Err::<u32, _>("gone")), (1, Err("gone"))];
+        assert_eq!(skip_failed_sessions(listed.into_iter()).count(), 0);
     }
 }
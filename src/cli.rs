@@ -0,0 +1,167 @@
+use std::path::PathBuf;
+use windows::core::*;
+use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+use windows::Win32::UI::WindowsAndMessaging::*;
+
+use volime::config::{Config, Overrides};
+use volime::log_event;
+use volime::system_tray::ModifierKey;
+
+/// Command line options, applied on top of the loaded config file
+#[derive(Debug, Default)]
+pub struct CliArgs {
+    /// `--no-tray`: run without the notification area icon
+    pub no_tray: bool,
+    /// `--modifier <shift|ctrl|alt|win>`
+    pub modifier: Option<ModifierKey>,
+    /// `--selftest`: check audio access, the hook and the tray, then exit
+    pub selftest: bool,
+    /// `--export <file>`: write the config file's settings to a file, then exit
+    pub export: Option<PathBuf>,
+    /// `--import <file>`: load settings written by `--export`, then exit
+    pub import: Option<PathBuf>,
+    /// `--merge`: `--import` keeps the settings the file doesn't have
+    pub merge: bool,
+}
+
+impl CliArgs {
+    pub fn parse() -> Self {
+        let mut args = CliArgs::default();
+        let mut iter = std::env::args().skip(1);
+
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--no-tray" => args.no_tray = true,
+                "--selftest" => args.selftest = true,
+                "--merge" => args.merge = true,
+                "--export" | "--import" => match iter.next() {
+                    Some(path) if arg == "--export" => args.export = Some(PathBuf::from(path)),
+                    Some(path) => args.import = Some(PathBuf::from(path)),
+                    None => eprintln!("{}: missing file name", arg),
+                },
+                "--modifier" => match iter.next().unwrap_or_default().parse::<ModifierKey>() {
+                    Ok(modifier) => args.modifier = Some(modifier),
+                    Err(e) => eprintln!("--modifier: {}", e),
+                },
+                _ => eprintln!("Unknown argument: {}", arg),
+            }
+        }
+
+        args
+    }
+
+    /// Add the options that replace settings to `overrides`, for
+    /// `Config::override_with`
+    pub fn apply(&self, overrides: &mut Overrides) {
+        if self.no_tray {
+            overrides.show_tray = Some(false);
+        }
+        if let Some(modifier) = self.modifier {
+            overrides.modifier = Some(modifier);
+        }
+    }
+}
+
+/// Show the outcome of a one-shot command: on the console Volime was
+/// started from, or in a message box otherwise
+pub fn report(title: &str, text: &str, success: bool) {
+    // Con subsistema "windows" solo hay consola si se lanzó desde una
+    if unsafe { AttachConsole(ATTACH_PARENT_PROCESS) }.is_ok() {
+        println!("\n{}", text);
+    } else {
+        let icon = if success {
+            MB_ICONINFORMATION
+        } else {
+            MB_ICONWARNING
+        };
+        unsafe {
+            MessageBoxW(
+                None,
+                &HSTRING::from(text),
+                &HSTRING::from(title),
+                MB_OK | icon,
+            );
+        }
+    }
+}
+
+/// Settings from `VOLIME_*` environment variables, for portable setups that
+/// configure Volime from a launcher script. Applied over the config file and
+/// under the command line.
+#[derive(Debug, Default)]
+pub struct EnvVars {
+    /// `VOLIME_MODIFIER=<shift|ctrl|alt|win>`
+    pub modifier: Option<ModifierKey>,
+    /// `VOLIME_STEP=<percent>`, e.g. `2` for 2% per press
+    pub step: Option<f32>,
+    /// `VOLIME_HIDE_DELAY=<milliseconds>`
+    pub hide_delay_ms: Option<u64>,
+}
+
+impl EnvVars {
+    pub fn read() -> Self {
+        EnvVars {
+            modifier: env_value("VOLIME_MODIFIER"),
+            step: env_value::<f32>("VOLIME_STEP").map(|percent| percent / 100.0),
+            hide_delay_ms: env_value("VOLIME_HIDE_DELAY"),
+        }
+    }
+
+    /// Add the variables that are set to `overrides`, for
+    /// `Config::override_with`
+    pub fn apply(&self, overrides: &mut Overrides) {
+        if let Some(modifier) = self.modifier {
+            overrides.modifier = Some(modifier);
+        }
+        if let Some(step) = self.step {
+            overrides.volume_step = Some(step);
+        }
+        if let Some(hide_delay_ms) = self.hide_delay_ms {
+            overrides.toast_hide_delay_ms = Some(hide_delay_ms);
+        }
+    }
+}
+
+/// Parsed value of an environment variable; unset, empty or invalid values
+/// are ignored (with a warning for invalid ones)
+fn env_value<T: std::str::FromStr>(name: &str) -> Option<T> {
+    let value = std::env::var(name)
+        .ok()
+        .filter(|value| !value.trim().is_empty())?;
+    match value.trim().parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            eprintln!("Ignoring invalid {}: {}", name, value);
+            None
+        }
+    }
+}
+
+/// Log where the modifier, step and toast delay in effect came from
+pub fn log_sources(cli_args: &CliArgs, env_vars: &EnvVars, config: &Config) {
+    let source = |cli: bool, env: bool| {
+        if cli {
+            "command line"
+        } else if env {
+            "environment"
+        } else {
+            "config file or default"
+        }
+    };
+
+    log_event!(
+        "Modifier: {} (from {})",
+        config.modifier,
+        source(cli_args.modifier.is_some(), env_vars.modifier.is_some())
+    );
+    log_event!(
+        "Volume step: {}% (from {})",
+        config.volume_step() * 100.0,
+        source(false, env_vars.step.is_some())
+    );
+    log_event!(
+        "Toast hide delay: {} ms (from {})",
+        config.toast_hide_delay().as_millis(),
+        source(false, env_vars.hide_delay_ms.is_some())
+    );
+}
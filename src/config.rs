@@ -0,0 +1,1505 @@
+use parking_lot::RwLock;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use windows::Win32::System::SystemInformation::GetLocalTime;
+
+use crate::system_tray::ModifierKey;
+
+const CONFIG_DIR: &str = "Volime";
+const CONFIG_FILE: &str = "config.toml";
+/// Copy of the config file kept by `--import` before overwriting it
+const BACKUP_FILE: &str = "config.toml.bak";
+
+/// `version` of the files written by `--export`, raised if their layout changes
+pub const EXPORT_VERSION: i64 = 1;
+/// How often the config file is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Allowed range of `volume_step`
+const MIN_VOLUME_STEP: f32 = 0.001;
+const MAX_VOLUME_STEP: f32 = 0.25;
+/// Allowed range of `toast_hide_delay_ms`
+const MIN_HIDE_DELAY_MS: u64 = 500;
+const MAX_HIDE_DELAY_MS: u64 = 30_000;
+/// Allowed range of `input.proportional_ratio`
+const MIN_PROPORTIONAL_RATIO: f32 = 0.01;
+const MAX_PROPORTIONAL_RATIO: f32 = 0.5;
+/// Lowest `toast_opacity`, so the text and bar stay legible
+const MIN_TOAST_OPACITY: u8 = 64;
+/// Allowed range of `toast_font_size`, so the text fits the toast
+const MIN_FONT_SIZE: u32 = 8;
+const MAX_FONT_SIZE: u32 = 24;
+/// Allowed range of `toast_context_max_apps`
+const MIN_CONTEXT_APPS: usize = 2;
+const MAX_CONTEXT_APPS: usize = 12;
+/// Allowed range of `input.repeat_interval_ms` (when not 0)
+const MIN_REPEAT_INTERVAL_MS: u64 = 20;
+const MAX_REPEAT_INTERVAL_MS: u64 = 1_000;
+/// Allowed range of `temporary_restore_secs`
+const MIN_TEMPORARY_RESTORE_SECS: u64 = 1;
+const MAX_TEMPORARY_RESTORE_SECS: u64 = 3_600;
+/// Allowed range of `input.snap_threshold`
+const MIN_SNAP_THRESHOLD: f32 = 0.005;
+const MAX_SNAP_THRESHOLD: f32 = 0.1;
+/// Allowed range of `recently_audible_window_ms`
+const MIN_AUDIBLE_WINDOW_MS: u64 = 1_000;
+const MAX_AUDIBLE_WINDOW_MS: u64 = 600_000;
+
+/// Built-in display names for common apps, overridable with `display_names`
+const DEFAULT_DISPLAY_NAMES: [(&str, &str); 12] = [
+    ("chrome.exe", "Chrome"),
+    ("msedge.exe", "Edge"),
+    ("msedgewebview2.exe", "Edge WebView"),
+    ("firefox.exe", "Firefox"),
+    ("brave.exe", "Brave"),
+    ("spotify.exe", "Spotify"),
+    ("discord.exe", "Discord"),
+    ("teams.exe", "Teams"),
+    ("ms-teams.exe", "Teams"),
+    ("vlc.exe", "VLC"),
+    ("steam.exe", "Steam"),
+    ("zoom.exe", "Zoom"),
+];
+
+/// User settings, persisted as TOML under `%APPDATA%\Volime\config.toml`.
+/// Missing fields fall back to their defaults so old files keep loading.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Key that switches the media keys to per-app control
+    pub modifier: ModifierKey,
+    /// Show the notification area icon (disable for kiosk/headless use)
+    pub show_tray: bool,
+    /// Give focus back to the previously focused app when the tray menu
+    /// closes, so the next volume keys still target it
+    pub restore_focus: bool,
+    /// Toast corner radius in pixels at 100% scaling (0 for square corners)
+    pub toast_corner_radius: u32,
+    /// Toast border width in pixels at 100% scaling (0 for no border)
+    pub toast_border_width: u32,
+    /// Toast border color as "#RRGGBB"
+    pub toast_border_color: String,
+    /// Backdrop behind the toast (Windows 11 22H2 or later, solid elsewhere)
+    pub toast_backdrop: ToastBackdrop,
+    /// Opacity of the whole toast while shown, from 64 (faint) to 255
+    /// (opaque); lower values are raised to 64
+    pub toast_opacity: u8,
+    /// Direction of the volume bar; vertical toasts sit by the right edge
+    pub toast_orientation: ToastOrientation,
+    /// Font family of the toast text (Segoe UI when it isn't installed)
+    pub toast_font: String,
+    /// Toast text size in pixels at 100% scaling
+    pub toast_font_size: u32,
+    /// Show the app icon in the toast (a generic one when it has none)
+    pub toast_show_icons: bool,
+    /// Keep the icon while the same app is adjusted again, instead of
+    /// extracting it from the executable on every step
+    pub toast_cache_icon: bool,
+    /// Wait until the volume has been stable this long before showing the
+    /// toast, in milliseconds (0 shows it immediately)
+    pub toast_show_delay_ms: u64,
+    /// Mark the volume before the change on the toast bar
+    pub toast_show_previous: bool,
+    /// Slide the bar fill to the new volume instead of jumping to it
+    pub toast_animate_bar: bool,
+    /// Feedback when a volume step lands exactly on 0% or 100%
+    pub limit_feedback: LimitFeedback,
+    /// Show every playing app in a compact row, highlighting the adjusted one
+    pub toast_context: bool,
+    /// Most apps in the compact row; the rest are counted as "+N"
+    pub toast_context_max_apps: usize,
+    /// How long the volume toast stays up, in milliseconds
+    pub toast_hide_delay_ms: u64,
+    /// Keep the toast up while the cursor is over it; the hide delay starts
+    /// over when the cursor leaves
+    pub toast_pause_on_hover: bool,
+    /// Draw - and + buttons around the toast's bar that step the shown app's
+    /// volume when clicked
+    pub toast_buttons: bool,
+    /// Which volume changes show the toast
+    pub toast_scope: ToastScope,
+    /// Don't show the toast while presenting or a full-screen app is in
+    /// front; volume changes still apply
+    pub toast_hide_when_presenting: bool,
+    /// Keep the toast out of screenshots, recordings and screen sharing
+    pub toast_exclude_from_capture: bool,
+    /// Volume change per key press (0.01 = 1%)
+    pub volume_step: f32,
+    /// Modifier + Plus/Minus changes `volume_step` by 1%
+    pub step_keys: bool,
+    /// Per-app volume cap (0.0 - 1.0), keyed by lowercase process name
+    pub max_volume: HashMap<String, f32>,
+    /// Per-app volume floor (0.0 - 1.0), keyed by lowercase process name.
+    /// Volume Down stops there; never above the app's cap.
+    pub min_volume: HashMap<String, f32>,
+    /// Volume (0.0 - 1.0) set once whenever a session of the app appears,
+    /// keyed by lowercase process name, for apps that start at 100% every
+    /// launch
+    pub startup_volume: HashMap<String, f32>,
+    /// Process name whose session is adjusted when the foreground window has
+    /// this class name, for hosts whose windows play through another process.
+    /// Checked after the PID match and before the executable name.
+    pub window_class_rules: HashMap<String, String>,
+    /// Names shown for apps instead of their process name, keyed by
+    /// lowercase process name; added to `DEFAULT_DISPLAY_NAMES`
+    pub display_names: HashMap<String, String>,
+    /// Spread each volume change over this many milliseconds (0 = instant)
+    pub volume_ramp_ms: u64,
+    /// Which window's app the volume keys control
+    pub target_strategy: TargetStrategy,
+    /// How far back `TargetStrategy::RecentlyAudible` looks for an app
+    /// that played, in milliseconds
+    pub recently_audible_window_ms: u64,
+    /// Adjust the system volume when the focused app has no audio session
+    pub fallback_to_master: bool,
+    /// When the focused app has no session but the device is playing (an
+    /// app in exclusive mode), adjust the device volume instead
+    pub exclusive_fallback: bool,
+    /// Which default output device sessions are looked up on first
+    pub device_role: DeviceRole,
+    /// How volume percentages are shown for apps with a cap
+    pub volume_display: VolumeDisplay,
+    /// Write events to `%LOCALAPPDATA%\Volime\volime.log`
+    pub log_to_file: bool,
+    /// Wait before installing the keyboard hook at startup, in milliseconds
+    pub hook_install_delay_ms: u64,
+    /// Tapping the modifier arms it so the next volume keys control the app
+    pub sticky_modifier: bool,
+    /// How long a tapped modifier stays armed, in milliseconds
+    pub sticky_window_ms: u64,
+    /// Key (e.g. "F13", "Pause") whose tap makes the next volume key, within
+    /// `sticky_window_ms`, control the app without the modifier
+    pub prefix_key: Option<String>,
+    /// Key (named like `prefix_key`) that raises the focused app to
+    /// `boost_volume` while held, restoring its volume on release
+    pub boost_key: Option<String>,
+    /// Volume of the app while `boost_key` is held (0.0 - 1.0)
+    pub boost_volume: f32,
+    /// Key (named like `prefix_key`) that, pressed with the modifier, swaps
+    /// the app volume keys between the two most recently targeted apps
+    pub swap_target_key: Option<String>,
+    /// Key (named like `prefix_key`) that, pressed with the modifier, shows
+    /// the focused app's volume without changing it
+    pub peek_key: Option<String>,
+    /// Key (named like `prefix_key`) that, pressed with the modifier, makes
+    /// the next app volume change temporary: it reverts after
+    /// `temporary_restore_secs`
+    pub temporary_key: Option<String>,
+    /// Seconds until a temporary change reverts
+    pub temporary_restore_secs: u64,
+    /// Mode toggled by double-tapping the modifier
+    pub double_tap_action: DoubleTapAction,
+    /// Most time between the two taps of a double-tap, in milliseconds
+    pub double_tap_window_ms: u64,
+    /// Holding the modifier alone opens an overlay listing the audio sessions
+    pub mixer_overlay: bool,
+    /// How long the modifier must be held before the overlay opens, in milliseconds
+    pub mixer_overlay_delay_ms: u64,
+    /// What Modifier + each volume key does
+    pub key_actions: KeyActions,
+    /// Further modifiers with their own actions, e.g. Control for the system
+    /// volume next to Shift for the app. An entry for `modifier` itself is
+    /// ignored, since `key_actions` applies to it.
+    pub modifier_actions: HashMap<ModifierKey, KeyActions>,
+    /// What Modifier + Mute does
+    pub mute_key_action: MuteKeyAction,
+    /// Mute on its own (no modifier) acts like Modifier + Mute, on the
+    /// focused app instead of the system; Volume Up/Down are unaffected
+    pub mute_key_app_default: bool,
+    /// Volume app commands from headsets and remotes that also run the
+    /// volume key actions. Windows changes the system volume for them either
+    /// way, so each such press changes both.
+    pub app_commands_also_adjust: AppCommands,
+    /// Solo mutes the system sounds too, not only the other apps
+    pub solo_system_sounds: bool,
+    /// Modifier + Mute presses this soon after the last one are ignored, in
+    /// milliseconds (0 = off)
+    pub mute_cooldown_ms: u64,
+    /// How the volume keys are interpreted
+    pub input: InputConfig,
+    /// Volume control from mouse buttons and the wheel
+    pub mouse: MouseConfig,
+    /// Cap on every app's volume during night hours
+    pub quiet_hours: QuietHoursConfig,
+    /// Lower some apps while another one is playing
+    pub ducking: DuckingConfig,
+    /// Named sequences of volume actions, run from a hotkey or the tray
+    pub macros: Vec<MacroConfig>,
+    /// Overrides in effect, see `override_with`
+    #[serde(skip)]
+    overrides: Overrides,
+    /// Values of the config file that `overrides` replaced, which `save`
+    /// writes in their place
+    #[serde(skip)]
+    file_values: Overrides,
+}
+
+/// Settings replaced for this run only, from the command line or the
+/// environment. They apply over the config file but are never written to it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Overrides {
+    pub show_tray: Option<bool>,
+    pub modifier: Option<ModifierKey>,
+    pub volume_step: Option<f32>,
+    pub toast_hide_delay_ms: Option<u64>,
+}
+
+/// Windows keeps separate default devices per role, e.g. a headset as the
+/// communications default and speakers for everything else
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceRole {
+    /// Games, system sounds and most apps (Windows' "Default Device")
+    Console,
+    /// Music and video playback
+    Multimedia,
+    /// Voice chat (Windows' "Default Communication Device")
+    Communications,
+}
+
+/// Mode a double-tap of the modifier switches Modifier + volume keys to;
+/// another double-tap switches back to the focused app
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DoubleTapAction {
+    /// Double-taps are not detected
+    None,
+    /// Control the system volume
+    Master,
+    /// Control the last adjusted app, wherever the focus is
+    LastApp,
+}
+
+/// Feedback when a volume step reaches 0% or 100%
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitFeedback {
+    None,
+    /// Flash the toast border
+    Flash,
+    /// Play the default system beep
+    Beep,
+    /// Flash and beep
+    Both,
+}
+
+impl LimitFeedback {
+    pub fn flashes(self) -> bool {
+        matches!(self, LimitFeedback::Flash | LimitFeedback::Both)
+    }
+
+    pub fn beeps(self) -> bool {
+        matches!(self, LimitFeedback::Beep | LimitFeedback::Both)
+    }
+}
+
+/// Background of the toast
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToastBackdrop {
+    /// Opaque dark gray
+    Solid,
+    /// Blurred desktop behind the toast, like Windows 11 flyouts
+    Acrylic,
+    /// Tinted with the wallpaper, like Windows 11 app windows
+    Mica,
+}
+
+/// Layout of the toast's volume bar. Messages and the compact row of
+/// `toast_context` are always horizontal.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToastOrientation {
+    /// Wide toast at the bottom of the screen, the bar filling left to right
+    Horizontal,
+    /// Tall toast by the right edge, the bar filling bottom to top with the
+    /// icon above it and the number below
+    Vertical,
+}
+
+/// Which volume app commands (`WM_APPCOMMAND`, sent by some Bluetooth
+/// headsets and remotes instead of key presses) Volime also acts on. They
+/// can't be taken from the system, which still applies them.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AppCommands {
+    /// None; only the system handles them
+    Off,
+    /// Those arriving while the modifier is held, like the volume keys
+    Modifier,
+    /// All of them, as if the modifier were held
+    Always,
+}
+
+/// Volume changes that show the toast
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToastScope {
+    /// Only changes of an app's volume
+    AppOnly,
+    /// Changes of an app's volume and of the system volume
+    AppAndSystem,
+    /// Only changes of the system volume (master mode, fallback, master
+    /// key actions)
+    SystemOnly,
+}
+
+impl ToastScope {
+    /// Whether a change of the system volume (`system`) or of an app shows
+    /// the toast
+    pub fn shows(self, system: bool) -> bool {
+        match self {
+            ToastScope::AppOnly => !system,
+            ToastScope::AppAndSystem => true,
+            ToastScope::SystemOnly => system,
+        }
+    }
+}
+
+/// Which window's app the volume keys control
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetStrategy {
+    /// The foreground window
+    Foreground,
+    /// The window under the mouse cursor, focused or not
+    CursorWindow,
+    /// The most recently active window on the monitor under the cursor
+    CursorMonitorForeground,
+    /// The app that played audio most recently, even if it went quiet
+    /// (within `recently_audible_window_ms`); the foreground window when
+    /// none did
+    RecentlyAudible,
+}
+
+/// Action a volume key performs while the modifier is held, under
+/// `[key_actions]` in the config file
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyAction {
+    /// The key's own action: Volume Up raises the app, and so on
+    #[default]
+    Default,
+    AppUp,
+    AppDown,
+    /// Mute the focused app, or whatever `mute_key_action` says
+    AppMute,
+    MasterUp,
+    MasterDown,
+    MasterMute,
+    /// Mute every other app; pressing again unmutes them
+    Solo,
+}
+
+impl KeyAction {
+    pub fn as_str(&self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(_, action)| action == self)
+            .map_or("default", |(name, _)| name)
+    }
+
+    const NAMES: [(&'static str, KeyAction); 8] = [
+        ("default", KeyAction::Default),
+        ("appup", KeyAction::AppUp),
+        ("appdown", KeyAction::AppDown),
+        ("appmute", KeyAction::AppMute),
+        ("masterup", KeyAction::MasterUp),
+        ("masterdown", KeyAction::MasterDown),
+        ("mastermute", KeyAction::MasterMute),
+        ("solo", KeyAction::Solo),
+    ];
+}
+
+/// Unknown action names fall back to the key's default instead of
+/// rejecting the whole config file
+impl<'de> Deserialize<'de> for KeyAction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        let action = KeyAction::NAMES
+            .iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(&name))
+            .map(|(_, action)| *action);
+
+        Ok(action.unwrap_or_else(|| {
+            log_event!("Unknown key action '{}', using the key's default", name);
+            KeyAction::Default
+        }))
+    }
+}
+
+/// Action of each volume key while the modifier is held. Keys left out keep
+/// their default action.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyActions {
+    pub volume_up: KeyAction,
+    pub volume_down: KeyAction,
+    pub mute: KeyAction,
+}
+
+impl KeyActions {
+    /// Action of Volume Up, Volume Down or, when neither is set, Mute
+    pub fn for_key(&self, volume_up: bool, volume_down: bool) -> KeyAction {
+        let (action, default) = if volume_up {
+            (self.volume_up, KeyAction::AppUp)
+        } else if volume_down {
+            (self.volume_down, KeyAction::AppDown)
+        } else {
+            (self.mute, KeyAction::AppMute)
+        };
+
+        match action {
+            KeyAction::Default => default,
+            action => action,
+        }
+    }
+}
+
+/// Action of the mute key while the modifier is held
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MuteKeyAction {
+    /// Mute or unmute the focused app
+    Toggle,
+    /// Turn the focused app down to this volume (0.0 - 1.0); pressing again
+    /// restores the previous volume
+    Whisper(f32),
+    /// Mute every other app; pressing again unmutes them
+    Solo,
+}
+
+/// Interpretation of the percentage shown in the toast and mixer
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumeDisplay {
+    /// The session's real volume (a cap of 80% tops out at "80")
+    Absolute,
+    /// Relative to the app's cap, so reaching the cap shows "100"
+    Relative,
+}
+
+/// Input behavior, under `[input]` in the config file. The defaults keep the
+/// original handling of the volume keys.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputConfig {
+    /// Swap Volume Up and Volume Down (for volume knobs that turn backwards)
+    pub invert_scroll: bool,
+    /// Grow the step while presses keep arriving quickly (key held down)
+    pub acceleration: bool,
+    /// One step per physical press, ignoring the key's auto-repeat
+    pub discrete_presses: bool,
+    /// Swallow handled volume keys so the system volume doesn't change too
+    pub block_system_key: bool,
+    /// Volume Up/Down on a muted app unmutes it as well as changing the level
+    pub unmute_on_adjust: bool,
+    /// Steps are a fraction of the current volume (`proportional_ratio`),
+    /// finer when quiet and coarser when loud; `volume_step` is the minimum
+    pub proportional_step: bool,
+    /// Fraction of the current volume each step moves with `proportional_step`
+    pub proportional_ratio: f32,
+    /// Step every this many milliseconds while a volume key is held, instead
+    /// of at the system's key repeat rate (0 = system rate). Off with
+    /// `discrete_presses`.
+    pub repeat_interval_ms: u64,
+    /// Volumes (0.0 - 1.0) a step snaps to when it lands within
+    /// `snap_threshold` of one ahead of it; empty for no snapping
+    pub snap_points: Vec<f32>,
+    /// How close to a snap point a step has to land to snap to it
+    pub snap_threshold: f32,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        InputConfig {
+            invert_scroll: false,
+            acceleration: false,
+            discrete_presses: false,
+            block_system_key: true,
+            unmute_on_adjust: false,
+            proportional_step: false,
+            proportional_ratio: 0.1,
+            repeat_interval_ms: 0,
+            snap_points: Vec::new(),
+            snap_threshold: 0.02,
+        }
+    }
+}
+
+impl InputConfig {
+    /// `repeat_interval_ms` kept within a usable range, if Volime repeats
+    /// held keys itself
+    pub fn repeat_interval(&self) -> Option<Duration> {
+        (self.repeat_interval_ms > 0 && !self.discrete_presses).then(|| {
+            Duration::from_millis(
+                self.repeat_interval_ms
+                    .clamp(MIN_REPEAT_INTERVAL_MS, MAX_REPEAT_INTERVAL_MS),
+            )
+        })
+    }
+
+    /// `proportional_ratio` kept within a usable range, if `proportional_step` is on
+    pub fn proportional_ratio(&self) -> Option<f32> {
+        self.proportional_step.then(|| {
+            self.proportional_ratio
+                .clamp(MIN_PROPORTIONAL_RATIO, MAX_PROPORTIONAL_RATIO)
+        })
+    }
+
+    /// `snap_threshold` kept within a usable range
+    pub fn snap_threshold(&self) -> f32 {
+        self.snap_threshold
+            .clamp(MIN_SNAP_THRESHOLD, MAX_SNAP_THRESHOLD)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            modifier: ModifierKey::Shift,
+            show_tray: true,
+            restore_focus: true,
+            toast_corner_radius: 12,
+            toast_border_width: 1,
+            toast_border_color: "#404040".to_string(),
+            toast_backdrop: ToastBackdrop::Solid,
+            toast_opacity: 255,
+            toast_orientation: ToastOrientation::Horizontal,
+            toast_font: "Segoe UI Variable".to_string(),
+            toast_font_size: 15,
+            toast_show_icons: true,
+            toast_cache_icon: true,
+            toast_show_delay_ms: 0,
+            toast_show_previous: false,
+            limit_feedback: LimitFeedback::None,
+            toast_animate_bar: false,
+            toast_context: false,
+            toast_context_max_apps: 6,
+            toast_hide_delay_ms: 2500,
+            toast_pause_on_hover: true,
+            toast_buttons: false,
+            toast_scope: ToastScope::AppAndSystem,
+            toast_hide_when_presenting: false,
+            toast_exclude_from_capture: false,
+            volume_step: 0.01,
+            step_keys: false,
+            max_volume: HashMap::new(),
+            min_volume: HashMap::new(),
+            startup_volume: HashMap::new(),
+            window_class_rules: HashMap::new(),
+            display_names: HashMap::new(),
+            volume_ramp_ms: 0,
+            target_strategy: TargetStrategy::Foreground,
+            recently_audible_window_ms: 30_000,
+            fallback_to_master: false,
+            exclusive_fallback: false,
+            device_role: DeviceRole::Console,
+            volume_display: VolumeDisplay::Absolute,
+            log_to_file: false,
+            hook_install_delay_ms: 1000,
+            sticky_modifier: false,
+            sticky_window_ms: 2000,
+            prefix_key: None,
+            boost_key: None,
+            boost_volume: 1.0,
+            swap_target_key: None,
+            peek_key: None,
+            temporary_key: None,
+            temporary_restore_secs: 30,
+            double_tap_action: DoubleTapAction::None,
+            double_tap_window_ms: 400,
+            mixer_overlay: false,
+            mixer_overlay_delay_ms: 400,
+            key_actions: KeyActions::default(),
+            modifier_actions: HashMap::new(),
+            mute_key_action: MuteKeyAction::Toggle,
+            mute_key_app_default: false,
+            app_commands_also_adjust: AppCommands::Off,
+            solo_system_sounds: false,
+            mute_cooldown_ms: 0,
+            input: InputConfig::default(),
+            mouse: MouseConfig::default(),
+            quiet_hours: QuietHoursConfig::default(),
+            ducking: DuckingConfig::default(),
+            macros: Vec::new(),
+            overrides: Overrides::default(),
+            file_values: Overrides::default(),
+        }
+    }
+}
+
+/// Mouse buttons that can be mapped to volume actions
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MouseButton {
+    Middle,
+    X1,
+    X2,
+}
+
+/// Mouse control, under `[mouse]` in the config file. Read at startup: the
+/// mouse hook is only installed when `enabled` is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MouseConfig {
+    pub enabled: bool,
+    /// Only act while the modifier is held (otherwise every click counts)
+    pub require_modifier: bool,
+    /// The wheel raises/lowers the volume (only while the modifier is held)
+    pub wheel: bool,
+    pub volume_up: Option<MouseButton>,
+    pub volume_down: Option<MouseButton>,
+    pub mute: Option<MouseButton>,
+}
+
+impl Default for MouseConfig {
+    fn default() -> Self {
+        MouseConfig {
+            enabled: false,
+            require_modifier: true,
+            wheel: true,
+            volume_up: Some(MouseButton::X2),
+            volume_down: Some(MouseButton::X1),
+            mute: Some(MouseButton::Middle),
+        }
+    }
+}
+
+/// Night-time volume cap, under `[quiet_hours]` in the config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuietHoursConfig {
+    pub enabled: bool,
+    /// Local time quiet hours begin, "HH:MM"
+    pub start: String,
+    /// Local time quiet hours end, "HH:MM"; may be past midnight
+    pub end: String,
+    /// Highest volume any app can be raised to meanwhile (0.0 - 1.0)
+    pub max_volume: f32,
+    /// Turn down the apps already above `max_volume` when quiet hours begin
+    pub lower_at_start: bool,
+}
+
+impl Default for QuietHoursConfig {
+    fn default() -> Self {
+        QuietHoursConfig {
+            enabled: false,
+            start: "22:00".to_string(),
+            end: "07:00".to_string(),
+            max_volume: 0.3,
+            lower_at_start: false,
+        }
+    }
+}
+
+/// Automatic lowering of background apps while a priority app (e.g. a voice
+/// chat) is audible, under `[ducking]` in the config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DuckingConfig {
+    pub enabled: bool,
+    /// Process name of the app that triggers ducking, e.g. "discord.exe"
+    pub priority_app: String,
+    /// Process names of the apps turned down meanwhile
+    pub target_apps: Vec<String>,
+    /// Share of their volume the target apps keep while ducked (0.0 - 1.0)
+    pub volume: f32,
+    /// How long the priority app must stay silent before the targets are
+    /// restored, in milliseconds, so pauses between words don't count
+    pub release_ms: u64,
+}
+
+impl Default for DuckingConfig {
+    fn default() -> Self {
+        DuckingConfig {
+            enabled: false,
+            priority_app: String::new(),
+            target_apps: Vec::new(),
+            volume: 0.3,
+            release_ms: 1500,
+        }
+    }
+}
+
+impl DuckingConfig {
+    /// Whether ducking is on and has apps to act on
+    pub fn is_configured(&self) -> bool {
+        self.enabled && !self.priority_app.is_empty() && !self.target_apps.is_empty()
+    }
+}
+
+/// Named sequence of volume actions, under `[[macros]]` in the config file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MacroConfig {
+    /// Shown in the tray menu and the toast
+    pub name: String,
+    /// Key (named like `prefix_key`) that runs the macro with the modifier
+    pub key: Option<String>,
+    /// Run in order; a step whose app isn't running is skipped
+    pub steps: Vec<MacroStep>,
+}
+
+impl MacroConfig {
+    /// Virtual-key code of `key`, if it is set to a known key name
+    pub fn vk(&self) -> Option<u32> {
+        self.key.as_deref().and_then(key_code)
+    }
+}
+
+/// One step of a macro: set the volume, the mute state or both of an app
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MacroStep {
+    /// Process name, any case, e.g. "discord.exe"
+    pub app: String,
+    /// Volume to set (0.0 - 1.0); the app's cap and floor still apply
+    #[serde(default)]
+    pub volume: Option<f32>,
+    #[serde(default)]
+    pub mute: Option<bool>,
+}
+
+impl QuietHoursConfig {
+    /// Whether quiet hours are in effect at the current local time
+    pub fn is_active(&self) -> bool {
+        if !self.enabled {
+            return false;
+        }
+
+        let (Some(start), Some(end)) = (parse_time(&self.start), parse_time(&self.end)) else {
+            return false;
+        };
+        let now = unsafe { GetLocalTime() };
+        let now = u32::from(now.wHour) * 60 + u32::from(now.wMinute);
+
+        if start <= end {
+            start <= now && now < end
+        } else {
+            // Wraps around midnight, e.g. 22:00 - 07:00
+            now >= start || now < end
+        }
+    }
+}
+
+/// Copy `overlay` into `base`, merging tables present in both
+fn merge_table(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => {
+                merge_table(base, overlay)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Minutes since midnight of an "HH:MM" time
+fn parse_time(value: &str) -> Option<u32> {
+    let (hours, minutes) = value.trim().split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        let app_data = std::env::var_os("APPDATA")?;
+        Some(PathBuf::from(app_data).join(CONFIG_DIR).join(CONFIG_FILE))
+    }
+
+    /// Load the config file, falling back to defaults if it is missing or invalid
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+
+        if !path.exists() {
+            return Self::default();
+        }
+
+        match Self::read(&path) {
+            Ok(config) => {
+                log_event!("Loaded config from {}", path.display());
+                config
+            }
+            Err(e) => {
+                log_event!("Error parsing config {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    fn read(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut config: Self = toml::from_str(&contents).map_err(|e| e.to_string())?;
+
+        for (setting, volumes) in [
+            ("max_volume", &mut config.max_volume),
+            ("min_volume", &mut config.min_volume),
+            ("startup_volume", &mut config.startup_volume),
+        ] {
+            volumes.retain(|app, volume| {
+                if !volume.is_finite() {
+                    log_event!(
+                        "Ignoring {} of {}: {} is not a number",
+                        setting,
+                        app,
+                        volume
+                    );
+                }
+                volume.is_finite()
+            });
+        }
+        for (app, min) in &config.min_volume {
+            if let Some(max) = config.max_volume.get(app).filter(|max| *max < min) {
+                log_event!(
+                    "min_volume of {} ({}) is above its max_volume ({}); the cap wins",
+                    app,
+                    min,
+                    max
+                );
+            }
+        }
+        if config.modifier_actions.contains_key(&config.modifier) {
+            log_event!(
+                "Ignoring modifier_actions entry for {}: it is the main modifier, use key_actions",
+                config.modifier
+            );
+        }
+        if config.toast_opacity < MIN_TOAST_OPACITY {
+            log_event!(
+                "toast_opacity {} is below {}, the least that stays legible; using {}",
+                config.toast_opacity,
+                MIN_TOAST_OPACITY,
+                MIN_TOAST_OPACITY
+            );
+        }
+        for problem in crate::macros::problems(&config.macros) {
+            log_event!("{}", problem);
+        }
+        Ok(config)
+    }
+
+    /// Write the settings to the config file. Overridden settings are
+    /// written with the file's own values, unless they were changed since.
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = Self::path().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "APPDATA is not set")
+        })?;
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let contents = toml::to_string_pretty(&self.file_view())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        std::fs::write(&path, contents)
+    }
+
+    /// Replace settings for this run only, e.g. from the command line.
+    /// `save` keeps writing the values they replaced.
+    pub fn override_with(&mut self, overrides: Overrides) {
+        if let Some(show_tray) = overrides.show_tray {
+            self.file_values.show_tray = Some(self.show_tray);
+            self.show_tray = show_tray;
+        }
+        if let Some(modifier) = overrides.modifier {
+            self.file_values.modifier = Some(self.modifier);
+            self.modifier = modifier;
+        }
+        if let Some(volume_step) = overrides.volume_step {
+            self.file_values.volume_step = Some(self.volume_step);
+            self.volume_step = volume_step;
+        }
+        if let Some(toast_hide_delay_ms) = overrides.toast_hide_delay_ms {
+            self.file_values.toast_hide_delay_ms = Some(self.toast_hide_delay_ms);
+            self.toast_hide_delay_ms = toast_hide_delay_ms;
+        }
+        self.overrides = overrides;
+    }
+
+    /// The overrides still in effect. A setting changed since it was
+    /// overridden, e.g. the modifier picked in the tray, belongs to the
+    /// config file again.
+    pub fn live_overrides(&self) -> Overrides {
+        Overrides {
+            show_tray: self
+                .overrides
+                .show_tray
+                .filter(|show_tray| *show_tray == self.show_tray),
+            modifier: self
+                .overrides
+                .modifier
+                .filter(|modifier| *modifier == self.modifier),
+            volume_step: self
+                .overrides
+                .volume_step
+                .filter(|volume_step| *volume_step == self.volume_step),
+            toast_hide_delay_ms: self
+                .overrides
+                .toast_hide_delay_ms
+                .filter(|delay_ms| *delay_ms == self.toast_hide_delay_ms),
+        }
+    }
+
+    /// These settings as they belong in the config file: the live overrides
+    /// swapped back for the values they replaced
+    fn file_view(&self) -> Config {
+        let live = self.live_overrides();
+        let mut config = self.clone();
+        if let (Some(_), Some(show_tray)) = (live.show_tray, self.file_values.show_tray) {
+            config.show_tray = show_tray;
+        }
+        if let (Some(_), Some(modifier)) = (live.modifier, self.file_values.modifier) {
+            config.modifier = modifier;
+        }
+        if let (Some(_), Some(volume_step)) = (live.volume_step, self.file_values.volume_step) {
+            config.volume_step = volume_step;
+        }
+        if let (Some(_), Some(delay_ms)) = (
+            live.toast_hide_delay_ms,
+            self.file_values.toast_hide_delay_ms,
+        ) {
+            config.toast_hide_delay_ms = delay_ms;
+        }
+        config
+    }
+
+    /// Write the settings of the config file to `path`, with a version so
+    /// `import` can tell it apart from other files
+    pub fn export(path: &Path) -> Result<(), String> {
+        let mut file = toml::Table::new();
+        file.insert("version".to_string(), toml::Value::Integer(EXPORT_VERSION));
+        file.insert(
+            "config".to_string(),
+            toml::Value::try_from(Self::load()).map_err(|e| e.to_string())?,
+        );
+
+        let contents = toml::to_string_pretty(&file).map_err(|e| e.to_string())?;
+        std::fs::write(path, contents).map_err(|e| e.to_string())
+    }
+
+    /// Load settings exported to `path` into the config file: replacing it,
+    /// or with `merge` applying only the settings the export has on top of
+    /// the current ones. The current file is backed up first.
+    pub fn import(path: &Path, merge: bool) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut file: toml::Table = toml::from_str(&contents).map_err(|e| e.to_string())?;
+
+        match file.get("version").and_then(toml::Value::as_integer) {
+            Some(EXPORT_VERSION) => {}
+            Some(version) => {
+                return Err(format!(
+                    "unsupported export version {} (expected {})",
+                    version, EXPORT_VERSION
+                ))
+            }
+            None => return Err("not a Volime export: version is missing".to_string()),
+        }
+        let Some(toml::Value::Table(imported)) = file.remove("config") else {
+            return Err("not a Volime export: [config] is missing".to_string());
+        };
+
+        let target = Self::path().ok_or("APPDATA is not set")?;
+        let mut settings = toml::Table::new();
+        if merge && target.exists() {
+            let current = std::fs::read_to_string(&target).map_err(|e| e.to_string())?;
+            settings = toml::from_str(&current)
+                .map_err(|e| format!("current config is invalid, can't merge: {}", e))?;
+        }
+        merge_table(&mut settings, imported);
+        let config: Self = toml::Value::Table(settings)
+            .try_into()
+            .map_err(|e: toml::de::Error| e.to_string())?;
+
+        if target.exists() {
+            std::fs::copy(&target, target.with_file_name(BACKUP_FILE))
+                .map_err(|e| format!("backing up the current config: {}", e))?;
+        }
+        config.save().map_err(|e| e.to_string())?;
+        Ok(config)
+    }
+
+    /// `volume_step`, kept within a usable range
+    pub fn volume_step(&self) -> f32 {
+        self.volume_step.clamp(MIN_VOLUME_STEP, MAX_VOLUME_STEP)
+    }
+
+    /// Raise or lower `volume_step` by one percentage point, between 1% and
+    /// the largest step allowed, returning the new step
+    pub fn nudge_volume_step(&mut self, increase: bool) -> f32 {
+        let percent = (self.volume_step() * 100.0).round() as i32;
+        let percent = if increase { percent + 1 } else { percent - 1 };
+        self.volume_step = percent.clamp(1, (MAX_VOLUME_STEP * 100.0) as i32) as f32 / 100.0;
+        self.volume_step
+    }
+
+    /// Actions for the modifier held right now: `modifier` first, then the
+    /// `modifier_actions` entries in the order Shift, Control, Alt, Win
+    pub fn held_key_actions(&self) -> Option<KeyActions> {
+        if self.modifier.is_pressed() {
+            return Some(self.key_actions);
+        }
+
+        ModifierKey::ALL
+            .into_iter()
+            .filter(|modifier| *modifier != self.modifier && modifier.is_pressed())
+            .find_map(|modifier| self.key_actions_for(modifier))
+    }
+
+    /// Actions of the volume keys with `modifier` held: `key_actions` for the
+    /// main modifier, its `modifier_actions` entry for another one (None
+    /// when it has none, as it then does nothing)
+    pub fn key_actions_for(&self, modifier: ModifierKey) -> Option<KeyActions> {
+        if modifier == self.modifier {
+            Some(self.key_actions)
+        } else {
+            self.modifier_actions.get(&modifier).copied()
+        }
+    }
+
+    /// Virtual-key code of `prefix_key`, if it is set to a known key name
+    pub fn prefix_vk(&self) -> Option<u32> {
+        self.prefix_key.as_deref().and_then(key_code)
+    }
+
+    /// Virtual-key code of `boost_key`, if it is set to a known key name
+    pub fn boost_vk(&self) -> Option<u32> {
+        self.boost_key.as_deref().and_then(key_code)
+    }
+
+    /// Virtual-key code of `swap_target_key`, if it is set to a known key name
+    pub fn swap_target_vk(&self) -> Option<u32> {
+        self.swap_target_key.as_deref().and_then(key_code)
+    }
+
+    /// Virtual-key code of `peek_key`, if it is set to a known key name
+    pub fn peek_vk(&self) -> Option<u32> {
+        self.peek_key.as_deref().and_then(key_code)
+    }
+
+    /// Virtual-key code of `temporary_key`, if it is set to a known key name
+    pub fn temporary_vk(&self) -> Option<u32> {
+        self.temporary_key.as_deref().and_then(key_code)
+    }
+
+    /// `temporary_restore_secs`, kept within a usable range
+    pub fn temporary_restore(&self) -> Duration {
+        Duration::from_secs(
+            self.temporary_restore_secs
+                .clamp(MIN_TEMPORARY_RESTORE_SECS, MAX_TEMPORARY_RESTORE_SECS),
+        )
+    }
+
+    /// `toast_hide_delay_ms`, kept within a usable range
+    pub fn toast_hide_delay(&self) -> Duration {
+        Duration::from_millis(
+            self.toast_hide_delay_ms
+                .clamp(MIN_HIDE_DELAY_MS, MAX_HIDE_DELAY_MS),
+        )
+    }
+
+    /// `toast_opacity`, kept high enough to read the toast
+    pub fn toast_opacity(&self) -> u8 {
+        self.toast_opacity.max(MIN_TOAST_OPACITY)
+    }
+
+    /// `toast_font_size`, kept within a usable range
+    pub fn toast_font_size(&self) -> u32 {
+        self.toast_font_size.clamp(MIN_FONT_SIZE, MAX_FONT_SIZE)
+    }
+
+    /// `recently_audible_window_ms`, kept within a usable range
+    pub fn recently_audible_window(&self) -> Duration {
+        Duration::from_millis(
+            self.recently_audible_window_ms
+                .clamp(MIN_AUDIBLE_WINDOW_MS, MAX_AUDIBLE_WINDOW_MS),
+        )
+    }
+
+    /// `toast_context_max_apps`, kept within a usable range
+    pub fn toast_context_max_apps(&self) -> usize {
+        self.toast_context_max_apps
+            .clamp(MIN_CONTEXT_APPS, MAX_CONTEXT_APPS)
+    }
+
+    pub fn max_volume_for(&self, app_name: &str) -> Option<f32> {
+        self.max_volume.get(&app_key(app_name)).copied()
+    }
+
+    /// Highest volume `app_name` may be set to right now: its own cap,
+    /// lowered further during quiet hours. Also tells whether quiet hours
+    /// are in effect.
+    pub fn effective_max_volume(&self, app_name: &str) -> (f32, bool) {
+        let max_volume = self.max_volume_for(app_name).unwrap_or(1.0);
+        if self.quiet_hours.is_active() {
+            let quiet_max = self.quiet_hours.max_volume.clamp(0.0, 1.0);
+            (max_volume.min(quiet_max), true)
+        } else {
+            (max_volume, false)
+        }
+    }
+
+    pub fn min_volume_for(&self, app_name: &str) -> Option<f32> {
+        self.min_volume.get(&app_key(app_name)).copied()
+    }
+
+    /// Volume `app_name` starts at, if it has one
+    pub fn startup_volume_for(&self, app_name: &str) -> Option<f32> {
+        self.startup_volume.get(&app_key(app_name)).copied()
+    }
+
+    /// Volume `app_name` starts at, if it has one, within its floor and cap
+    /// right now
+    pub fn effective_startup_volume(&self, app_name: &str) -> Option<f32> {
+        let volume = self.startup_volume_for(app_name)?;
+        Some(self.limit_volume(app_name, volume).0)
+    }
+
+    /// Lowest volume Volime turns `app_name` down to: its floor, but never
+    /// above `max_volume` (the result of `effective_max_volume`)
+    pub fn effective_min_volume(&self, app_name: &str, max_volume: f32) -> f32 {
+        self.min_volume_for(app_name)
+            .unwrap_or(0.0)
+            .clamp(0.0, 1.0)
+            .min(max_volume)
+    }
+
+    /// `volume` kept within `app_name`'s floor and cap right now, and within
+    /// 0 - 100%. Also tells whether quiet hours lowered the cap.
+    pub fn limit_volume(&self, app_name: &str, volume: f32) -> (f32, bool) {
+        let (max_volume, quiet_hours) = self.effective_max_volume(app_name);
+        let min_volume = self.effective_min_volume(app_name, max_volume);
+        (
+            volume.max(min_volume).min(max_volume).clamp(0.0, 1.0),
+            quiet_hours,
+        )
+    }
+
+    /// Name to show for `app_name`. Settings stay keyed by the process name;
+    /// this only changes what is displayed.
+    pub fn display_name(&self, app_name: &str) -> String {
+        let key = app_key(app_name);
+        self.display_names
+            .get(&key)
+            .cloned()
+            .or_else(|| {
+                DEFAULT_DISPLAY_NAMES
+                    .iter()
+                    .find(|(exe, _)| *exe == key)
+                    .map(|(_, name)| name.to_string())
+            })
+            .unwrap_or_else(|| app_name.to_string())
+    }
+
+    /// Percentage to display for `volume` of `app_name`, following
+    /// `volume_display`
+    pub fn display_percent(&self, app_name: &str, volume: f32) -> i32 {
+        let volume = match (self.volume_display, self.max_volume_for(app_name)) {
+            (VolumeDisplay::Relative, Some(max)) if max > 0.0 => (volume / max).min(1.0),
+            _ => volume,
+        };
+        (volume * 100.0).round() as i32
+    }
+
+    pub fn set_max_volume(&mut self, app_name: &str, max: Option<f32>) {
+        match max {
+            Some(max) => {
+                self.max_volume
+                    .insert(app_key(app_name), max.clamp(0.0, 1.0));
+            }
+            None => {
+                self.max_volume.remove(&app_key(app_name));
+            }
+        }
+    }
+
+    pub fn set_min_volume(&mut self, app_name: &str, min: Option<f32>) {
+        match min {
+            Some(min) => {
+                self.min_volume
+                    .insert(app_key(app_name), min.clamp(0.0, 1.0));
+            }
+            None => {
+                self.min_volume.remove(&app_key(app_name));
+            }
+        }
+    }
+}
+
+/// Virtual-key code of a key name: a letter, a digit, F1 - F24 or one of a
+/// few keys rarely used for typing. Case-insensitive.
+fn key_code(name: &str) -> Option<u32> {
+    let name = name.trim().to_lowercase();
+
+    if let [c] = name.as_bytes() {
+        return c
+            .is_ascii_alphanumeric()
+            .then(|| c.to_ascii_uppercase() as u32);
+    }
+    if let Some(number) = name.strip_prefix('f').and_then(|n| n.parse::<u32>().ok()) {
+        return (1..=24).contains(&number).then(|| 0x6F + number);
+    }
+
+    match name.as_str() {
+        "pause" => Some(0x13),
+        "capslock" => Some(0x14),
+        "pageup" => Some(0x21),
+        "pagedown" => Some(0x22),
+        "end" => Some(0x23),
+        "home" => Some(0x24),
+        "insert" => Some(0x2D),
+        "apps" | "menu" => Some(0x5D),
+        "scrolllock" => Some(0x91),
+        _ => None,
+    }
+}
+
+/// Reload the config file into `config` whenever it changes on disk, so
+/// edits take effect without a restart. The `Overrides` still in effect are
+/// reapplied after every reload. Writes are debounced until the file has
+/// been stable for one check, and invalid files are ignored, keeping the
+/// last good config.
+pub fn watch(config: Arc<RwLock<Config>>) {
+    let Some(path) = Config::path() else {
+        return;
+    };
+
+    std::thread::spawn(move || {
+        let mut seen = modified_time(&path);
+        let mut applied = seen;
+
+        loop {
+            std::thread::sleep(WATCH_INTERVAL);
+
+            let current = modified_time(&path);
+            if current != seen {
+                // Still being written, wait for it to settle
+                seen = current;
+                continue;
+            }
+            if current == applied || current.is_none() {
+                continue;
+            }
+            applied = current;
+
+            match Config::read(&path) {
+                Ok(mut new_config) => {
+                    let live = config.read().live_overrides();
+                    new_config.override_with(live);
+                    *config.write() = new_config;
+                    log_event!("Reloaded config from {}", path.display());
+                }
+                Err(e) => log_event!("Ignoring invalid config {}: {}", path.display(), e),
+            }
+        }
+    });
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Per-app settings are keyed by lowercase process name (e.g. "chrome.exe")
+pub fn app_key(app_name: &str) -> String {
+    app_name.to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modifier_round_trips_through_the_config_file() {
+        for modifier in ModifierKey::ALL {
+            let config = Config {
+                modifier,
+                ..Config::default()
+            };
+            let contents = toml::to_string_pretty(&config).unwrap();
+            let parsed: Config = toml::from_str(&contents).unwrap();
+            assert_eq!(parsed.modifier, modifier);
+        }
+    }
+
+    fn with_limits(max: &[(&str, f32)], min: &[(&str, f32)]) -> Config {
+        let mut config = Config::default();
+        for (app, volume) in max {
+            config.max_volume.insert(app_key(app), *volume);
+        }
+        for (app, volume) in min {
+            config.min_volume.insert(app_key(app), *volume);
+        }
+        config
+    }
+
+    #[test]
+    fn floor_never_goes_above_the_cap() {
+        let config = with_limits(&[("spotify.exe", 0.3)], &[("spotify.exe", 0.5)]);
+        assert_eq!(config.effective_min_volume("spotify.exe", 0.3), 0.3);
+        // A lower cap in effect (quiet hours) lowers the floor too
+        assert_eq!(config.effective_min_volume("spotify.exe", 0.1), 0.1);
+        assert_eq!(config.limit_volume("spotify.exe", 0.0), (0.3, false));
+    }
+
+    #[test]
+    fn floor_is_per_app() {
+        let config = with_limits(&[], &[("spotify.exe", 0.2), ("chrome.exe", 0.1)]);
+        assert_eq!(config.effective_min_volume("Spotify.exe", 1.0), 0.2);
+        assert_eq!(config.effective_min_volume("chrome.exe", 1.0), 0.1);
+        assert_eq!(config.effective_min_volume("discord.exe", 1.0), 0.0);
+        // Out of range floors are kept within 0 - 100%
+        let config = with_limits(&[], &[("spotify.exe", 1.5)]);
+        assert_eq!(config.effective_min_volume("spotify.exe", 1.0), 1.0);
+    }
+
+    #[test]
+    fn non_finite_caps_and_floors_are_ignored() {
+        let path = std::env::temp_dir().join("volime-test-non-finite.toml");
+        std::fs::write(
+            &path,
+            "[max_volume]\n\"spotify.exe\" = nan\n\"chrome.exe\" = 0.5\n\n\
+             [min_volume]\n\"spotify.exe\" = inf\n",
+        )
+        .unwrap();
+        let config = Config::read(&path);
+        let _ = std::fs::remove_file(&path);
+        let config = config.unwrap();
+
+        assert_eq!(config.max_volume_for("spotify.exe"), None);
+        assert_eq!(config.max_volume_for("chrome.exe"), Some(0.5));
+        assert_eq!(config.min_volume_for("spotify.exe"), None);
+    }
+
+    #[test]
+    fn nan_cap_does_not_panic() {
+        let config = with_limits(&[("spotify.exe", f32::NAN)], &[("spotify.exe", f32::NAN)]);
+        let (volume, _) = config.limit_volume("spotify.exe", 0.5);
+        assert!((0.0..=1.0).contains(&volume));
+    }
+
+    #[test]
+    fn startup_volume_keeps_to_the_cap_and_floor() {
+        let mut config = with_limits(&[("spotify.exe", 0.6)], &[("discord.exe", 0.3)]);
+        config.startup_volume.insert(app_key("spotify.exe"), 0.9);
+        config.startup_volume.insert(app_key("discord.exe"), 0.1);
+        config.startup_volume.insert(app_key("chrome.exe"), 0.4);
+
+        assert_eq!(config.effective_startup_volume("Spotify.exe"), Some(0.6));
+        assert_eq!(config.effective_startup_volume("discord.exe"), Some(0.3));
+        assert_eq!(config.effective_startup_volume("chrome.exe"), Some(0.4));
+        assert_eq!(config.effective_startup_volume("game.exe"), None);
+    }
+
+    #[test]
+    fn nan_startup_volume_does_not_panic() {
+        let mut config = with_limits(&[("spotify.exe", f32::NAN)], &[]);
+        config
+            .startup_volume
+            .insert(app_key("spotify.exe"), f32::NAN);
+        config.startup_volume.insert(app_key("chrome.exe"), 0.5);
+        assert!(config.effective_startup_volume("spotify.exe").is_some());
+        assert_eq!(config.effective_startup_volume("chrome.exe"), Some(0.5));
+    }
+
+    #[test]
+    fn app_volume_set_by_name_keeps_to_the_cap_and_floor() {
+        let config = with_limits(&[("spotify.exe", 0.8)], &[("spotify.exe", 0.2)]);
+
+        // (level, expected)
+        let cases = [(0.5, 0.5), (1.0, 0.8), (0.8, 0.8), (0.05, 0.2), (0.0, 0.2)];
+        for (level, expected) in cases {
+            assert_eq!(config.limit_volume("spotify.exe", level), (expected, false));
+        }
+    }
+
+    #[test]
+    fn app_volume_limits_match_names_in_any_case() {
+        let config = with_limits(&[("spotify.exe", 0.8)], &[("spotify.exe", 0.2)]);
+        assert_eq!(config.limit_volume("Spotify.exe", 1.0), (0.8, false));
+        assert_eq!(config.limit_volume("SPOTIFY.EXE", 0.0), (0.2, false));
+        // Other apps have no limits
+        assert_eq!(config.limit_volume("chrome.exe", 1.0), (1.0, false));
+        assert_eq!(config.limit_volume("chrome.exe", 0.0), (0.0, false));
+    }
+
+    #[test]
+    fn app_volume_set_by_name_stays_within_0_and_100() {
+        let config = Config::default();
+        assert_eq!(config.limit_volume("spotify.exe", 1.5), (1.0, false));
+        assert_eq!(config.limit_volume("spotify.exe", -0.5), (0.0, false));
+    }
+
+    #[test]
+    fn toast_opacity_has_a_floor() {
+        // (configured, used)
+        let cases = [(0, 64), (63, 64), (64, 64), (200, 200), (255, 255)];
+        for (configured, used) in cases {
+            let config = Config {
+                toast_opacity: configured,
+                ..Config::default()
+            };
+            assert_eq!(config.toast_opacity(), used);
+        }
+    }
+
+    #[test]
+    fn overrides_are_not_saved() {
+        let mut config = Config::default();
+        config.override_with(Overrides {
+            show_tray: Some(false),
+            modifier: Some(ModifierKey::Alt),
+            ..Overrides::default()
+        });
+
+        assert!(!config.show_tray);
+        assert_eq!(config.modifier, ModifierKey::Alt);
+        let file = config.file_view();
+        assert!(file.show_tray);
+        assert_eq!(file.modifier, Config::default().modifier);
+    }
+
+    #[test]
+    fn environment_overrides_are_not_saved() {
+        let mut config = Config::default();
+        config.override_with(Overrides {
+            volume_step: Some(0.05),
+            toast_hide_delay_ms: Some(5000),
+            ..Overrides::default()
+        });
+
+        assert_eq!(config.volume_step, 0.05);
+        let file = config.file_view();
+        assert_eq!(file.volume_step, Config::default().volume_step);
+        assert_eq!(
+            file.toast_hide_delay_ms,
+            Config::default().toast_hide_delay_ms
+        );
+    }
+
+    #[test]
+    fn settings_changed_after_an_override_are_saved() {
+        let mut config = Config::default();
+        config.override_with(Overrides {
+            show_tray: Some(false),
+            modifier: Some(ModifierKey::Alt),
+            ..Overrides::default()
+        });
+        config.modifier = ModifierKey::Win;
+
+        assert_eq!(config.file_view().modifier, ModifierKey::Win);
+        assert_eq!(
+            config.live_overrides(),
+            Overrides {
+                show_tray: Some(false),
+                ..Overrides::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,270 @@
+//! User-visible strings in the OS UI language, falling back to English.
+//!
+//! Adding a language means adding a table of translations below and an
+//! entry for it in `TRANSLATIONS`; strings missing from a table show in
+//! English. Log messages stay in English.
+
+use std::sync::OnceLock;
+use windows::Win32::Globalization::GetUserDefaultUILanguage;
+
+/// Identifier of each translatable string. `{}` in a string is a
+/// placeholder filled with `tr_with`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Text {
+    // Tray menu
+    CurrentDevice,
+    NoDefaultDevice,
+    ModifierKey,
+    OutputDevice,
+    MuteApp,
+    Troubleshoot,
+    NoSessions,
+    SessionActive,
+    SessionInactive,
+    SessionExpired,
+    SystemSounds,
+    ProcessGone,
+    Input,
+    InvertVolumeKeys,
+    AccelerateHeldKeys,
+    OneStepPerPress,
+    UnmuteOnVolumeChange,
+    ProportionalSteps,
+    VolumeCap,
+    NoCap,
+    VolumeFloor,
+    NoFloor,
+    AppSettings,
+    SaveSnapshot,
+    RestoreSnapshot,
+    ResetDefaults,
+    ResetConfirm,
+    Macros,
+    Suspend,
+    Exit,
+    // Tray icon
+    Tooltip,
+    TooltipSuspended,
+    TooltipMuted,
+    FailureTitle,
+    FailureText,
+    // Toast
+    MutedShort,
+    SystemVolumeNoAudio,
+    DeviceVolumeExclusive,
+    QuietHours,
+    ModifierArmed,
+    FocusedAppMode,
+    SystemVolumeMode,
+    LastAppMode,
+    NothingToMute,
+    RemoteSession,
+    VolumeStep,
+    TargetSwapped,
+    NoOtherTarget,
+    MacroApplied,
+    MacroIncomplete,
+    TemporaryArmed,
+    TemporaryRestoreIn,
+    // App Settings window
+    AppSettingsTitle,
+    CapLabel,
+    DisplayNameLabel,
+    Save,
+    Delete,
+    CapOutOfRange,
+    // Console
+    InitialModifier,
+    RightClickTray,
+    // Panic dialog
+    CrashTitle,
+    CrashText,
+    CrashLogged,
+}
+
+fn english(text: Text) -> &'static str {
+    match text {
+        Text::CurrentDevice => "Output: {}",
+        Text::NoDefaultDevice => "No output device",
+        Text::ModifierKey => "Modifier Key",
+        Text::OutputDevice => "Output Device",
+        Text::MuteApp => "Mute App",
+        Text::Troubleshoot => "Troubleshoot",
+        Text::NoSessions => "No audio sessions",
+        Text::SessionActive => "active",
+        Text::SessionInactive => "inactive",
+        Text::SessionExpired => "expired",
+        Text::SystemSounds => "system sounds",
+        Text::ProcessGone => "process gone",
+        Text::Input => "Input",
+        Text::InvertVolumeKeys => "Invert Volume Keys",
+        Text::AccelerateHeldKeys => "Accelerate Held Keys",
+        Text::OneStepPerPress => "One Step per Press",
+        Text::UnmuteOnVolumeChange => "Unmute on Volume Change",
+        Text::ProportionalSteps => "Proportional Steps",
+        Text::VolumeCap => "Volume Cap",
+        Text::NoCap => "No cap",
+        Text::VolumeFloor => "Volume Floor",
+        Text::NoFloor => "No floor",
+        Text::AppSettings => "App Settings...",
+        Text::SaveSnapshot => "Save Volume Snapshot...",
+        Text::RestoreSnapshot => "Restore Volume Snapshot...",
+        Text::ResetDefaults => "Reset to Defaults...",
+        Text::ResetConfirm => {
+            "Forget all per-app settings (volume caps, display names and window rules) \
+             and restore every other setting to its default?"
+        }
+        Text::Macros => "Macros",
+        Text::Suspend => "Suspend",
+        Text::Exit => "Exit",
+        Text::Tooltip => "Volime - Volume Control",
+        Text::TooltipSuspended => "Volime - Suspended",
+        Text::TooltipMuted => "Volime - Muted:",
+        Text::FailureTitle => "Volime can't change the volume",
+        Text::FailureText => {
+            "Adjusting the volume failed several times in a row. \
+             Check that an output device is connected, or restart Volime after changing audio devices."
+        }
+        Text::MutedShort => "M",
+        Text::SystemVolumeNoAudio => "System volume (app has no audio)",
+        Text::DeviceVolumeExclusive => "Device volume (app in exclusive mode)",
+        Text::QuietHours => "Quiet hours",
+        Text::ModifierArmed => "{} armed",
+        Text::FocusedAppMode => "Focused app mode",
+        Text::SystemVolumeMode => "System volume mode",
+        Text::LastAppMode => "Last app mode",
+        Text::NothingToMute => "Nothing to mute",
+        Text::RemoteSession => "Unavailable in remote session",
+        Text::VolumeStep => "Step: {}",
+        Text::TargetSwapped => "Adjusting {}",
+        Text::NoOtherTarget => "No other app to swap to",
+        Text::MacroApplied => "{} applied",
+        Text::MacroIncomplete => "{} partly applied",
+        Text::TemporaryArmed => "Next change reverts in {} s",
+        Text::TemporaryRestoreIn => "Reverts in {} s",
+        Text::AppSettingsTitle => "Volime - App Settings",
+        Text::CapLabel => "Cap (%):",
+        Text::DisplayNameLabel => "Display name:",
+        Text::Save => "Save",
+        Text::Delete => "Delete",
+        Text::CapOutOfRange => "The cap must be a percentage from 0 to 100.",
+        Text::InitialModifier => "Initial modifier key: {}",
+        Text::RightClickTray => "Right-click the tray icon to change settings",
+        Text::CrashTitle => "Volime error",
+        Text::CrashText => "Volime ran into an unexpected error:\n\n{}",
+        Text::CrashLogged => "The details were written to the log file:\n{}",
+    }
+}
+
+const SPANISH: &[(Text, &str)] = &[
+    (Text::CurrentDevice, "Salida: {}"),
+    (Text::NoDefaultDevice, "Sin dispositivo de salida"),
+    (Text::ModifierKey, "Tecla modificadora"),
+    (Text::OutputDevice, "Dispositivo de salida"),
+    (Text::MuteApp, "Silenciar aplicación"),
+    (Text::Troubleshoot, "Diagnóstico"),
+    (Text::NoSessions, "No hay sesiones de audio"),
+    (Text::SessionActive, "activa"),
+    (Text::SessionInactive, "inactiva"),
+    (Text::SessionExpired, "caducada"),
+    (Text::SystemSounds, "sonidos del sistema"),
+    (Text::ProcessGone, "el proceso ya no existe"),
+    (Text::Input, "Entrada"),
+    (Text::InvertVolumeKeys, "Invertir teclas de volumen"),
+    (Text::AccelerateHeldKeys, "Acelerar teclas mantenidas"),
+    (Text::OneStepPerPress, "Un paso por pulsación"),
+    (Text::UnmuteOnVolumeChange, "Quitar silencio al cambiar el volumen"),
+    (Text::ProportionalSteps, "Pasos proporcionales"),
+    (Text::VolumeCap, "Límite de volumen"),
+    (Text::NoCap, "Sin límite"),
+    (Text::VolumeFloor, "Volumen mínimo"),
+    (Text::NoFloor, "Sin mínimo"),
+    (Text::AppSettings, "Ajustes de aplicaciones..."),
+    (Text::SaveSnapshot, "Guardar instantánea de volumen..."),
+    (Text::RestoreSnapshot, "Restaurar instantánea de volumen..."),
+    (Text::ResetDefaults, "Restablecer valores predeterminados..."),
+    (
+        Text::ResetConfirm,
+        "¿Olvidar todos los ajustes de aplicaciones (límites de volumen, nombres y reglas de ventana) \
+         y restablecer el resto de ajustes a sus valores predeterminados?",
+    ),
+    (Text::Macros, "Macros"),
+    (Text::Suspend, "Suspender"),
+    (Text::Exit, "Salir"),
+    (Text::Tooltip, "Volime - Control de volumen"),
+    (Text::TooltipSuspended, "Volime - Suspendido"),
+    (Text::TooltipMuted, "Volime - Silenciadas:"),
+    (Text::FailureTitle, "Volime no puede cambiar el volumen"),
+    (
+        Text::FailureText,
+        "Ajustar el volumen ha fallado varias veces seguidas. \
+         Comprueba que haya un dispositivo de salida conectado, o reinicia Volime tras cambiar de dispositivo de audio.",
+    ),
+    (Text::MutedShort, "S"),
+    (Text::SystemVolumeNoAudio, "Volumen del sistema (la aplicación no tiene audio)"),
+    (
+        Text::DeviceVolumeExclusive,
+        "Volumen del dispositivo (aplicación en modo exclusivo)",
+    ),
+    (Text::QuietHours, "Horas de silencio"),
+    (Text::ModifierArmed, "{} activado"),
+    (Text::FocusedAppMode, "Modo aplicación activa"),
+    (Text::SystemVolumeMode, "Modo volumen del sistema"),
+    (Text::LastAppMode, "Modo última aplicación"),
+    (Text::NothingToMute, "Nada que silenciar"),
+    (Text::RemoteSession, "No disponible en sesión remota"),
+    (Text::VolumeStep, "Paso: {}"),
+    (Text::TargetSwapped, "Ajustando {}"),
+    (Text::NoOtherTarget, "No hay otra aplicación a la que cambiar"),
+    (Text::MacroApplied, "{} aplicada"),
+    (Text::MacroIncomplete, "{} aplicada en parte"),
+    (Text::TemporaryArmed, "El próximo cambio se revierte en {} s"),
+    (Text::TemporaryRestoreIn, "Se revierte en {} s"),
+    (Text::AppSettingsTitle, "Volime - Ajustes de aplicaciones"),
+    (Text::CapLabel, "Límite (%):"),
+    (Text::DisplayNameLabel, "Nombre:"),
+    (Text::Save, "Guardar"),
+    (Text::Delete, "Eliminar"),
+    (Text::CapOutOfRange, "El límite debe ser un porcentaje de 0 a 100."),
+    (Text::InitialModifier, "Tecla modificadora inicial: {}"),
+    (
+        Text::RightClickTray,
+        "Haz clic derecho en el icono de la bandeja para cambiar los ajustes",
+    ),
+    (Text::CrashTitle, "Error de Volime"),
+    (
+        Text::CrashText,
+        "Volime ha encontrado un error inesperado:\n\n{}",
+    ),
+    (
+        Text::CrashLogged,
+        "Los detalles se han guardado en el archivo de registro:\n{}",
+    ),
+];
+
+/// Translation tables by primary language ID (the low 10 bits of a LANGID)
+const TRANSLATIONS: &[(u16, &[(Text, &str)])] = &[(0x0A, SPANISH)];
+
+/// Table for the OS UI language, looked up once
+fn table() -> Option<&'static [(Text, &'static str)]> {
+    static TABLE: OnceLock<Option<&'static [(Text, &'static str)]>> = OnceLock::new();
+    *TABLE.get_or_init(|| {
+        let primary_language = unsafe { GetUserDefaultUILanguage() } & 0x3FF;
+        TRANSLATIONS
+            .iter()
+            .find(|(language, _)| *language == primary_language)
+            .map(|(_, table)| *table)
+    })
+}
+
+/// `text` in the OS UI language
+pub fn tr(text: Text) -> &'static str {
+    table()
+        .and_then(|table| table.iter().find(|(key, _)| *key == text))
+        .map_or_else(|| english(text), |(_, translation)| *translation)
+}
+
+/// `text` with its `{}` placeholder replaced by `value`
+pub fn tr_with(text: Text, value: &str) -> String {
+    tr(text).replacen("{}", value, 1)
+}
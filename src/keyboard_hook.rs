@@ -1,17 +1,72 @@
 use parking_lot::RwLock;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use windows::core::*;
 use windows::Win32::Foundation::*;
 use windows::Win32::UI::Input::KeyboardAndMouse::*;
 use windows::Win32::UI::WindowsAndMessaging::*;
 
-use crate::audio_control::AudioController;
+use crate::audio_control::{is_remote_session, AudioController, VolumeInfo};
+use crate::config::{AppCommands, Config, DoubleTapAction, KeyAction, MuteKeyAction};
+use crate::i18n::{tr, tr_with, Text};
+use crate::macros;
+use crate::mixer_overlay::MixerOverlay;
 use crate::system_tray::ModifierKey;
-use crate::toast_ui::ToastUI;
+use crate::toast_ui::{ToastUI, HIDE_DELAY_MS};
 
-static mut AUDIO_CONTROLLER: Option<Arc<AudioController>> = None;
-static mut TOAST_UI: Option<Arc<ToastUI>> = None;
-static mut MODIFIER_KEY: Option<Arc<RwLock<ModifierKey>>> = None;
+/// Everything `keyboard_proc` needs. The LL hook runs on the thread that
+/// installed it, so per-keystroke state can live in plain `Cell`s.
+struct HookContext {
+    audio_controller: Arc<AudioController>,
+    toast_ui: Arc<ToastUI>,
+    mixer_overlay: Rc<MixerOverlay>,
+    config: Arc<RwLock<Config>>,
+    /// Set from the tray to hand every key back to the system
+    suspended: Arc<AtomicBool>,
+    /// When the modifier went down, while it is held
+    modifier_down_at: Cell<Option<Instant>>,
+    /// Another key was pressed while the modifier was held (not a tap)
+    modifier_used: Cell<bool>,
+    /// When the sticky modifier was last armed by a tap
+    sticky_armed_at: Cell<Option<Instant>>,
+    /// When `prefix_key` was last tapped, until a volume key consumes it
+    prefix_armed_at: Cell<Option<Instant>>,
+    /// Volume key currently held down, to tell auto-repeat from new presses
+    held_volume_key: Cell<Option<u32>>,
+    /// When the last tap of the modifier was released, for double-taps
+    last_tap_at: Cell<Option<Instant>>,
+    /// A double-tap switched the volume keys to `double_tap_action`
+    double_tap_mode: Cell<bool>,
+    /// When Modifier + Mute last acted, for `mute_cooldown_ms`
+    last_mute_at: Cell<Option<Instant>>,
+    /// Held volume key Volime repeats itself, for `input.repeat_interval_ms`
+    key_repeat: Cell<Option<KeyRepeat>>,
+}
+
+/// A volume step repeated at `input.repeat_interval_ms` while its key is held
+#[derive(Clone, Copy)]
+struct KeyRepeat {
+    vk_code: u32,
+    action: KeyAction,
+    next_at: Instant,
+}
+
+/// Delay before a held volume key starts repeating at the configured
+/// interval, so a single press stays a single step
+const REPEAT_START_DELAY: Duration = Duration::from_millis(300);
+
+/// Posted to the main thread (not to a window) when the hook changed the
+/// config, so the message loop saves it outside the hook's time limit
+pub const WM_SAVE_CONFIG: u32 = WM_APP + 5;
+
+static mut HOOK_CONTEXT: Option<HookContext> = None;
+
+fn context() -> Option<&'static HookContext> {
+    unsafe { (*std::ptr::addr_of!(HOOK_CONTEXT)).as_ref() }
+}
 
 pub struct KeyboardHook {
     hook: HHOOK,
@@ -21,22 +76,71 @@ impl KeyboardHook {
     pub fn install(
         audio_controller: Arc<AudioController>,
         toast_ui: Arc<ToastUI>,
-        modifier_key: Arc<RwLock<ModifierKey>>,
+        mixer_overlay: Rc<MixerOverlay>,
+        config: Arc<RwLock<Config>>,
+        suspended: Arc<AtomicBool>,
     ) -> Result<Self> {
         unsafe {
-            AUDIO_CONTROLLER = Some(audio_controller);
-            TOAST_UI = Some(toast_ui);
-            MODIFIER_KEY = Some(modifier_key);
+            if context().is_some() {
+                return Err(Error::new(
+                    ERROR_ALREADY_EXISTS.to_hresult(),
+                    "Keyboard hook is already installed",
+                ));
+            }
 
-            let hook = SetWindowsHookExW(WH_KEYBOARD_LL, Some(keyboard_proc), None, 0)?;
+            HOOK_CONTEXT = Some(HookContext {
+                audio_controller,
+                toast_ui,
+                mixer_overlay,
+                config,
+                suspended,
+                modifier_down_at: Cell::new(None),
+                modifier_used: Cell::new(false),
+                sticky_armed_at: Cell::new(None),
+                prefix_armed_at: Cell::new(None),
+                held_volume_key: Cell::new(None),
+                last_tap_at: Cell::new(None),
+                double_tap_mode: Cell::new(false),
+                last_mute_at: Cell::new(None),
+                key_repeat: Cell::new(None),
+            });
+
+            let hook = match SetWindowsHookExW(WH_KEYBOARD_LL, Some(keyboard_proc), None, 0) {
+                Ok(hook) if !hook.is_invalid() => hook,
+                result => {
+                    HOOK_CONTEXT = None;
+                    return Err(result.err().unwrap_or_else(Error::from_win32));
+                }
+            };
 
+            log_event!("Keyboard hook installed successfully");
+
+            Ok(KeyboardHook { hook })
+        }
+    }
+
+    /// Replace the hook with a fresh one. Windows silently removes low-level
+    /// hooks that time out, which tends to happen around sleep and resume.
+    pub fn reinstall(&mut self) -> Result<()> {
+        unsafe {
+            let hook = SetWindowsHookExW(WH_KEYBOARD_LL, Some(keyboard_proc), None, 0)?;
             if hook.is_invalid() {
                 return Err(Error::from_win32());
             }
 
-            println!("Keyboard hook installed successfully");
+            let _ = UnhookWindowsHookEx(self.hook);
+            self.hook = hook;
+            Ok(())
+        }
+    }
 
-            Ok(KeyboardHook { hook })
+    /// Periodic work from the message loop: opens the mixer overlay once the
+    /// modifier has been held long enough and keeps its bars live, and
+    /// repeats held volume keys at the configured interval
+    pub fn tick(&self) {
+        if let Some(ctx) = context() {
+            ctx.tick_mixer_overlay();
+            ctx.tick_repeat();
         }
     }
 }
@@ -45,78 +149,748 @@ impl Drop for KeyboardHook {
     fn drop(&mut self) {
         unsafe {
             let _ = UnhookWindowsHookEx(self.hook);
-            AUDIO_CONTROLLER = None;
-            println!("Keyboard hook uninstalled");
+            HOOK_CONTEXT = None;
+            log_event!("Keyboard hook uninstalled");
+        }
+    }
+}
+
+impl HookContext {
+    /// Track taps of the modifier for sticky mode: a press and release with
+    /// no other key in between arms it for the configured window. Two quick
+    /// taps in a row toggle the double-tap mode instead.
+    fn track_modifier(&self, vk_code: u32, key_down: bool, modifier: ModifierKey) {
+        if modifier.matches_vk(vk_code) {
+            if key_down {
+                if self.modifier_down_at.get().is_none() {
+                    self.modifier_down_at.set(Some(Instant::now()));
+                    self.modifier_used.set(false);
+                }
+            } else {
+                let held = self
+                    .modifier_down_at
+                    .take()
+                    .map(|down_at| down_at.elapsed());
+                self.mixer_overlay.hide();
+
+                // Hold-and-press isn't a tap and breaks a double-tap in progress
+                if self.modifier_used.get() {
+                    self.last_tap_at.set(None);
+                } else if self.track_double_tap(held) {
+                    return;
+                }
+
+                let config = self.config.read();
+                if config.sticky_modifier && !self.modifier_used.get() {
+                    let window = Duration::from_millis(config.sticky_window_ms);
+                    drop(config);
+
+                    self.sticky_armed_at.set(Some(Instant::now()));
+                    self.toast_ui
+                        .show_message(tr_with(Text::ModifierArmed, &modifier.to_string()), window);
+                }
+            }
+        } else if key_down {
+            self.modifier_used.set(true);
+
+            if !is_volume_key(vk_code) && self.sticky_armed_at.take().is_some() {
+                self.toast_ui.hide();
+            }
+        }
+    }
+
+    /// Arm the one-shot prefix when `prefix_key` goes down, and disarm it on
+    /// any other key but the volume keys. The prefix key itself always
+    /// reaches the system.
+    fn track_prefix(&self, vk_code: u32, key_down: bool) {
+        if !key_down {
+            return;
+        }
+
+        let config = self.config.read();
+        if config.prefix_vk() == Some(vk_code) {
+            let name = config.prefix_key.clone().unwrap_or_default();
+            let window = Duration::from_millis(config.sticky_window_ms);
+            drop(config);
+
+            self.prefix_armed_at.set(Some(Instant::now()));
+            self.toast_ui
+                .show_message(tr_with(Text::ModifierArmed, &name), window);
+        } else if !is_volume_key(vk_code) && self.prefix_armed_at.take().is_some() {
+            self.toast_ui.hide();
+        }
+    }
+
+    /// Boost the focused app while `boost_key` is held and restore it on
+    /// release. Like the prefix key, the boost key still reaches the system.
+    fn track_boost(&self, vk_code: u32, key_down: bool) {
+        let config = self.config.read();
+        if config.boost_vk() != Some(vk_code) {
+            return;
+        }
+        let level = config.boost_volume;
+        drop(config);
+
+        let result = if key_down {
+            self.audio_controller.start_boost(level).map(Some)
+        } else {
+            self.audio_controller.end_boost()
+        };
+
+        match result {
+            Ok(Some(volume_info)) => self.toast_ui.show_volume(volume_info),
+            Ok(None) => {}
+            Err(e) => log_event!("Error boosting app volume: {}", e),
+        }
+    }
+
+    /// Modifier + `swap_target_key`: swap the app volume keys to the other
+    /// recent app. The key still reaches the system, like the boost key.
+    fn track_swap_target(&self, vk_code: u32, key_down: bool) {
+        let config = self.config.read();
+        if !key_down || config.swap_target_vk() != Some(vk_code) || !config.modifier.is_pressed() {
+            return;
+        }
+        drop(config);
+
+        let message = match self.audio_controller.swap_target() {
+            Some(app_name) => {
+                log_event!("Active target swapped to '{}'", app_name);
+                let display_name = self.config.read().display_name(&app_name);
+                tr_with(Text::TargetSwapped, &display_name)
+            }
+            None => tr(Text::NoOtherTarget).to_string(),
+        };
+        self.toast_ui
+            .show_message(message, Duration::from_millis(HIDE_DELAY_MS));
+    }
+
+    /// Modifier + `peek_key`: show the focused app's volume without changing
+    /// it. The key still reaches the system, like the boost key.
+    fn track_peek(&self, vk_code: u32, key_down: bool) {
+        let config = self.config.read();
+        if !key_down || config.peek_vk() != Some(vk_code) || !config.modifier.is_pressed() {
+            return;
+        }
+        drop(config);
+
+        match self.audio_controller.get_focused_app_volume() {
+            Ok(volume_info) => self.toast_ui.show_volume(volume_info),
+            Err(e) => log_event!("Error reading app volume: {}", e),
+        }
+    }
+
+    /// Modifier + `temporary_key`: make the next app volume change revert
+    /// after a while. The key still reaches the system, like the boost key.
+    fn track_temporary(&self, vk_code: u32, key_down: bool) {
+        let config = self.config.read();
+        if !key_down || config.temporary_vk() != Some(vk_code) || !config.modifier.is_pressed() {
+            return;
+        }
+        drop(config);
+
+        let restore_after = self.audio_controller.arm_temporary();
+        self.toast_ui.show_message(
+            tr_with(Text::TemporaryArmed, &restore_after.as_secs().to_string()),
+            Duration::from_millis(HIDE_DELAY_MS),
+        );
+    }
+
+    /// Modifier + the key of a macro: run it and show how it went. The key
+    /// still reaches the system, like the boost key.
+    fn track_macros(&self, vk_code: u32, key_down: bool) {
+        let config = self.config.read();
+        if !key_down || !config.modifier.is_pressed() {
+            return;
+        }
+        let Some(macro_config) = config
+            .macros
+            .iter()
+            .find(|macro_config| macro_config.vk() == Some(vk_code))
+            .cloned()
+        else {
+            return;
+        };
+        drop(config);
+
+        let outcome = macros::run(&self.audio_controller, &macro_config);
+        self.toast_ui.show_message(
+            macros::summary(&macro_config, &outcome),
+            Duration::from_millis(HIDE_DELAY_MS),
+        );
+    }
+
+    /// Record a tap of the modifier held for `held`. Returns true when it
+    /// completed a double-tap, which toggles the double-tap mode.
+    fn track_double_tap(&self, held: Option<Duration>) -> bool {
+        let config = self.config.read();
+        let action = config.double_tap_action;
+        let window = Duration::from_millis(config.double_tap_window_ms);
+        drop(config);
+
+        // A long hold is not a tap, so it can't start or finish a double-tap
+        if action == DoubleTapAction::None || held.is_none_or(|held| held > window) {
+            self.last_tap_at.set(None);
+            return false;
+        }
+
+        let previous = self.last_tap_at.replace(Some(Instant::now()));
+        if previous.is_none_or(|tap| tap.elapsed() > window) {
+            return false;
+        }
+
+        self.last_tap_at.set(None);
+        self.sticky_armed_at.set(None);
+
+        let enabled = !self.double_tap_mode.get();
+        self.double_tap_mode.set(enabled);
+        let (mode, message) = match (enabled, action) {
+            (true, DoubleTapAction::Master) => ("System volume", Text::SystemVolumeMode),
+            (true, DoubleTapAction::LastApp) => ("Last app", Text::LastAppMode),
+            _ => ("Focused app", Text::FocusedAppMode),
+        };
+        log_event!("Double-tap: {} mode", mode);
+        self.toast_ui.show_message(
+            tr(message).to_string(),
+            Duration::from_millis(HIDE_DELAY_MS),
+        );
+        true
+    }
+
+    fn tick_mixer_overlay(&self) {
+        // Keys aren't tracked while suspended, so forget the modifier state
+        if self.suspended.load(Ordering::SeqCst) {
+            self.modifier_down_at.set(None);
+            self.mixer_overlay.hide();
+            return;
+        }
+
+        if self.mixer_overlay.is_visible() {
+            if self.mixer_overlay.refresh_due() {
+                self.refresh_mixer_overlay();
+            }
+            return;
+        }
+
+        let config = self.config.read();
+        if !config.mixer_overlay {
+            return;
+        }
+        let delay = Duration::from_millis(config.mixer_overlay_delay_ms);
+        drop(config);
+
+        let Some(down_at) = self.modifier_down_at.get() else {
+            return;
+        };
+        if self.modifier_used.get() || down_at.elapsed() < delay {
+            return;
+        }
+
+        // A long hold is not a tap, and only one attempt is made per hold
+        self.modifier_used.set(true);
+
+        match self.audio_controller.list_sessions() {
+            Ok(sessions) if !sessions.is_empty() => {
+                self.toast_ui.hide();
+                let focused_app = self.audio_controller.focused_app_name();
+                self.mixer_overlay.show(sessions, focused_app.as_deref());
+            }
+            Ok(_) => {}
+            Err(e) => log_event!("Error listing audio sessions: {}", e),
+        }
+    }
+
+    fn refresh_mixer_overlay(&self) {
+        match self.audio_controller.list_sessions() {
+            Ok(sessions) => self.mixer_overlay.update(sessions),
+            Err(e) => log_event!("Error listing audio sessions: {}", e),
+        }
+    }
+
+    /// Volume keys while the mixer overlay is open: Mute moves the selection,
+    /// Up/Down adjust the selected session
+    fn handle_mixer_key(&self, volume_up: bool, volume_down: bool) {
+        if !volume_up && !volume_down {
+            self.mixer_overlay.select_next();
+            return;
+        }
+
+        let Some(app_name) = self.mixer_overlay.selected_app() else {
+            return;
+        };
+
+        match self
+            .audio_controller
+            .adjust_app_volume(&app_name, volume_up, volume_down, false)
+        {
+            Ok(volume_info) => {
+                log_event!(
+                    "Volume of '{}' from mixer: {}%",
+                    volume_info.app_name,
+                    (volume_info.volume * 100.0).round() as i32
+                );
+                self.refresh_mixer_overlay();
+            }
+            Err(e) => log_event!("Error adjusting app volume: {}", e),
+        }
+    }
+
+    /// Adjust the target of the volume keys: the focused app, or the one
+    /// picked by the double-tap mode
+    fn adjust_volume(&self, volume_up: bool, volume_down: bool, mute: bool) -> Result<VolumeInfo> {
+        let action = self.config.read().double_tap_action;
+        let last_app = self.audio_controller.last_app_name();
+
+        match (self.double_tap_mode.get(), action, last_app) {
+            (true, DoubleTapAction::Master, _) => {
+                self.audio_controller
+                    .adjust_master_volume(volume_up, volume_down, mute)
+            }
+            (true, DoubleTapAction::LastApp, Some(app_name)) => self
+                .audio_controller
+                .adjust_app_volume(&app_name, volume_up, volume_down, mute),
+            _ => self
+                .audio_controller
+                .adjust_focused_app_volume(volume_up, volume_down, mute),
+        }
+    }
+
+    /// Record a volume key press or release, returning whether a key down is
+    /// an auto-repeat of a key already held
+    fn track_repeat(&self, vk_code: u32, key_down: bool) -> bool {
+        if !is_volume_key(vk_code) {
+            return false;
+        }
+
+        if key_down {
+            self.held_volume_key.replace(Some(vk_code)) == Some(vk_code)
+        } else {
+            if self.held_volume_key.get() == Some(vk_code) {
+                self.held_volume_key.set(None);
+            }
+            if self
+                .key_repeat
+                .get()
+                .is_some_and(|repeat| repeat.vk_code == vk_code)
+            {
+                self.key_repeat.set(None);
+            }
+            false
+        }
+    }
+
+    /// Start repeating a volume key press at `input.repeat_interval_ms`, for
+    /// the actions that step a volume
+    fn start_repeat(&self, vk_code: u32, action: KeyAction) {
+        let steps = matches!(
+            action,
+            KeyAction::AppUp | KeyAction::AppDown | KeyAction::MasterUp | KeyAction::MasterDown
+        );
+        if steps && self.config.read().input.repeat_interval().is_some() {
+            self.key_repeat.set(Some(KeyRepeat {
+                vk_code,
+                action,
+                next_at: Instant::now() + REPEAT_START_DELAY,
+            }));
+        }
+    }
+
+    /// Step the held volume key once its next repeat is due. Stops when the
+    /// key is no longer down, in case its release was never seen.
+    fn tick_repeat(&self) {
+        let Some(repeat) = self.key_repeat.get() else {
+            return;
+        };
+
+        let interval = self.config.read().input.repeat_interval();
+        let Some(interval) = interval.filter(|_| key_held(repeat.vk_code as i32)) else {
+            self.key_repeat.set(None);
+            return;
+        };
+
+        let now = Instant::now();
+        if now < repeat.next_at {
+            return;
+        }
+
+        self.key_repeat.set(Some(KeyRepeat {
+            next_at: now + interval,
+            ..repeat
+        }));
+        self.run_key_action(repeat.action, false, false, false);
+    }
+
+    /// Run the action of a volume key and show the result. The app actions
+    /// follow the double-tap mode; the rest run the same way as for
+    /// `AudioController` callers.
+    fn run_key_action(
+        &self,
+        key_action: KeyAction,
+        volume_up: bool,
+        volume_down: bool,
+        mute: bool,
+    ) {
+        let mute_key_action = self.config.read().mute_key_action;
+        let (action, result) = match (key_action, mute_key_action) {
+            (KeyAction::AppUp, _) => ("increase", self.adjust_volume(true, false, false)),
+            (KeyAction::AppDown, _) => ("decrease", self.adjust_volume(false, true, false)),
+            (KeyAction::AppMute, MuteKeyAction::Toggle) => {
+                ("mute", self.adjust_volume(false, false, true))
+            }
+            // `for_key` already resolved it; the key's own action
+            (KeyAction::Default, _) => ("adjust", self.adjust_volume(volume_up, volume_down, mute)),
+            (action, _) => (
+                action.as_str(),
+                self.audio_controller.perform_key_action(action),
+            ),
+        };
+
+        match result {
+            Ok(volume_info) => {
+                log_event!(
+                    "Volume of '{}': {} -> {}%{} (matched {})",
+                    volume_info.app_name,
+                    action,
+                    (volume_info.volume * 100.0).round() as i32,
+                    if volume_info.is_muted { " (muted)" } else { "" },
+                    volume_info.match_kind.as_str()
+                );
+
+                // Show toast UI
+                self.toast_ui
+                    .show_volume_in_context(volume_info, &self.audio_controller);
+            }
+            Err(e) => {
+                log_event!("Error adjusting app volume: {}", e);
+                if is_remote_session() {
+                    self.toast_ui.show_message(
+                        tr(Text::RemoteSession).to_string(),
+                        Duration::from_millis(HIDE_DELAY_MS),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Result for a key Volime acted on: swallowed unless `block_system_key`
+    /// is off, in which case the system handles it as well
+    unsafe fn handled(&self, code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        if self.config.read().input.block_system_key {
+            LRESULT(1)
+        } else {
+            CallNextHookEx(None, code, wparam, lparam)
+        }
+    }
+
+    /// Change the volume step by 1%, have it saved and show the new step
+    fn nudge_volume_step(&self, increase: bool) {
+        let step = self.config.write().nudge_volume_step(increase);
+        log_event!("Volume step set to {}%", (step * 100.0).round() as i32);
+
+        unsafe {
+            let _ = PostMessageW(None, WM_SAVE_CONFIG, WPARAM(0), LPARAM(0));
+        }
+
+        self.toast_ui.show_message(
+            tr_with(
+                Text::VolumeStep,
+                &format!("{}%", (step * 100.0).round() as i32),
+            ),
+            Duration::from_millis(HIDE_DELAY_MS),
+        );
+    }
+
+    /// Whether a Modifier + Mute press comes too soon after the last one,
+    /// recording it as the last one otherwise
+    fn mute_cooling_down(&self) -> bool {
+        let cooldown = Duration::from_millis(self.config.read().mute_cooldown_ms);
+        if self
+            .last_mute_at
+            .get()
+            .is_some_and(|last| last.elapsed() < cooldown)
+        {
+            return true;
+        }
+
+        self.last_mute_at.set(Some(Instant::now()));
+        false
+    }
+
+    /// Whether the prefix key was tapped recently, disarming it either way
+    fn consume_prefix(&self) -> bool {
+        let window = Duration::from_millis(self.config.read().sticky_window_ms);
+        self.prefix_armed_at
+            .take()
+            .is_some_and(|armed_at| armed_at.elapsed() <= window)
+    }
+
+    /// Whether the sticky modifier is armed, refreshing the window when it is
+    fn consume_sticky(&self) -> bool {
+        let Some(armed_at) = self.sticky_armed_at.get() else {
+            return false;
+        };
+
+        let window = Duration::from_millis(self.config.read().sticky_window_ms);
+        if armed_at.elapsed() <= window {
+            self.sticky_armed_at.set(Some(Instant::now()));
+            true
+        } else {
+            self.sticky_armed_at.set(None);
+            false
         }
     }
 }
 
+fn key_held(vk: i32) -> bool {
+    unsafe { (GetAsyncKeyState(vk) as u16 & 0x8000) != 0 }
+}
+
+/// Second key that, together with the modifier and Mute, toggles mute of the
+/// last adjusted app: Control, or Shift when Control is the modifier
+fn last_app_mute_vk(modifier: ModifierKey) -> i32 {
+    match modifier {
+        ModifierKey::Control => VK_SHIFT.0 as i32,
+        _ => VK_CONTROL.0 as i32,
+    }
+}
+
+/// The only keys Volime may ever swallow. Every other key, the modifiers
+/// included, is observed and passed on unchanged. Plus and Minus are only
+/// swallowed together with the modifier when `step_keys` is on.
+const BLOCKABLE_KEYS: [VIRTUAL_KEY; 7] = [
+    VK_VOLUME_UP,
+    VK_VOLUME_DOWN,
+    VK_VOLUME_MUTE,
+    VK_OEM_PLUS,
+    VK_OEM_MINUS,
+    VK_ADD,
+    VK_SUBTRACT,
+];
+
+/// Keys that are part of reserved system sequences (Ctrl+Alt+Del, Win+L,
+/// Alt+Tab, Ctrl+Shift+Esc, Print Screen). These are always passed through,
+/// even if they are ever added to `BLOCKABLE_KEYS`.
+const RESERVED_KEYS: [VIRTUAL_KEY; 7] = [
+    VK_DELETE,
+    VK_ESCAPE,
+    VK_TAB,
+    VK_LWIN,
+    VK_RWIN,
+    VK_L,
+    VK_SNAPSHOT,
+];
+
+/// Whether `keyboard_proc` is allowed to swallow `vk_code`
+fn may_block(vk_code: u32) -> bool {
+    let listed = |keys: &[VIRTUAL_KEY]| keys.iter().any(|key| key.0 as u32 == vk_code);
+    listed(&BLOCKABLE_KEYS) && !listed(&RESERVED_KEYS)
+}
+
+/// Whether `vk_code` is a Plus (`Some(true)`) or Minus (`Some(false)`) key,
+/// on the main keyboard or the numeric keypad
+fn step_key(vk_code: u32) -> Option<bool> {
+    if vk_code == VK_OEM_PLUS.0 as u32 || vk_code == VK_ADD.0 as u32 {
+        Some(true)
+    } else if vk_code == VK_OEM_MINUS.0 as u32 || vk_code == VK_SUBTRACT.0 as u32 {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn is_volume_key(vk_code: u32) -> bool {
+    vk_code == VK_VOLUME_UP.0 as u32
+        || vk_code == VK_VOLUME_DOWN.0 as u32
+        || vk_code == VK_VOLUME_MUTE.0 as u32
+}
+
+/// Run a volume app command (see `app_commands_also_adjust`) through the
+/// same actions as the volume keys. Returns whether Volime acted on it;
+/// false when the hook isn't installed, is suspended or
+/// `app_commands_also_adjust` leaves it alone.
+pub(crate) fn run_app_command(volume_up: bool, volume_down: bool, mute: bool) -> bool {
+    let Some(ctx) = context().filter(|ctx| !ctx.suspended.load(Ordering::SeqCst)) else {
+        return false;
+    };
+
+    let config = ctx.config.read();
+    let mode = config.app_commands_also_adjust;
+    let held_actions = config.held_key_actions();
+    let default_actions = config.key_actions;
+    let (volume_up, volume_down) = if config.input.invert_scroll {
+        (volume_down, volume_up)
+    } else {
+        (volume_up, volume_down)
+    };
+    drop(config);
+
+    let key_actions = match (mode, held_actions) {
+        (AppCommands::Off, _) => return false,
+        (_, Some(key_actions)) => key_actions,
+        (AppCommands::Always, None) => default_actions,
+        (AppCommands::Modifier, None) => return false,
+    };
+    let key_action = key_actions.for_key(volume_up, volume_down);
+
+    let is_mute_action = matches!(
+        key_action,
+        KeyAction::AppMute | KeyAction::MasterMute | KeyAction::Solo
+    );
+    if is_mute_action && ctx.mute_cooling_down() {
+        log_event!("Mute ignored during cooldown");
+        return true;
+    }
+
+    ctx.run_key_action(key_action, volume_up, volume_down, mute);
+    true
+}
+
 unsafe extern "system" fn keyboard_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
     if code >= 0 {
-        let kb = *(lparam.0 as *const KBDLLHOOKSTRUCT);
-        let vk_code = kb.vkCode;
-
-        // Solo procesar eventos WM_KEYDOWN
-        if wparam.0 == WM_KEYDOWN as usize {
-            // Obtener tecla modificadora configurada
-            let modifier_vk = unsafe {
-                let ptr = std::ptr::addr_of!(MODIFIER_KEY);
-                if let Some(mod_key) = &*ptr {
-                    mod_key.read().to_vk()
-                } else {
-                    VK_SHIFT.0 as i32 // Por defecto Shift
+        if let Some(ctx) = context().filter(|ctx| !ctx.suspended.load(Ordering::SeqCst)) {
+            let kb = *(lparam.0 as *const KBDLLHOOKSTRUCT);
+            let vk_code = kb.vkCode;
+            let message = wparam.0 as u32;
+            let key_down = message == WM_KEYDOWN || message == WM_SYSKEYDOWN;
+            let key_up = message == WM_KEYUP || message == WM_SYSKEYUP;
+
+            // Obtener tecla modificadora y opciones de entrada configuradas
+            let config = ctx.config.read();
+            let modifier = config.modifier;
+            let invert = config.input.invert_scroll;
+            let discrete = config.input.discrete_presses;
+            let own_repeat = config.input.repeat_interval().is_some();
+            let step_keys = config.step_keys;
+            drop(config);
+
+            let mut is_repeat = false;
+            if key_down || key_up {
+                ctx.track_modifier(vk_code, key_down, modifier);
+                ctx.track_prefix(vk_code, key_down);
+                ctx.track_boost(vk_code, key_down);
+                ctx.track_swap_target(vk_code, key_down);
+                ctx.track_peek(vk_code, key_down);
+                ctx.track_temporary(vk_code, key_down);
+                ctx.track_macros(vk_code, key_down);
+                is_repeat = ctx.track_repeat(vk_code, key_down);
+            }
+
+            // Anything outside the allowlist is only observed
+            if !may_block(vk_code) {
+                return CallNextHookEx(None, code, wparam, lparam);
+            }
+
+            // Solo procesar eventos WM_KEYDOWN
+            if message == WM_KEYDOWN {
+                // Verificar si la tecla modificadora está presionada
+                let modifier_pressed = modifier.is_pressed();
+
+                // Modifier + Plus/Minus: change the volume step
+                if let Some(increase) = step_key(vk_code) {
+                    if !(step_keys && modifier_pressed) {
+                        return CallNextHookEx(None, code, wparam, lparam);
+                    }
+                    ctx.nudge_volume_step(increase);
+                    return LRESULT(1);
                 }
-            };
 
-            // Verificar si la tecla modificadora está presionada
-            let modifier_pressed = (GetAsyncKeyState(modifier_vk) as u16 & 0x8000) != 0;
-
-            // Teclas multimedia de volumen
-            let is_volume_up = vk_code == VK_VOLUME_UP.0 as u32;
-            let is_volume_down = vk_code == VK_VOLUME_DOWN.0 as u32;
-            let is_volume_mute = vk_code == VK_VOLUME_MUTE.0 as u32;
-
-            if modifier_pressed && (is_volume_up || is_volume_down || is_volume_mute) {
-                // Modifier + media key: control active app volume
-                let controller_ptr = std::ptr::addr_of!(AUDIO_CONTROLLER);
-                if let Some(controller) = &*controller_ptr {
-                    let action = if is_volume_up {
-                        "increase"
-                    } else if is_volume_down {
-                        "decrease"
-                    } else {
-                        "mute"
-                    };
+                // Teclas multimedia de volumen
+                let mut is_volume_up = vk_code == VK_VOLUME_UP.0 as u32;
+                let mut is_volume_down = vk_code == VK_VOLUME_DOWN.0 as u32;
+                if invert {
+                    std::mem::swap(&mut is_volume_up, &mut is_volume_down);
+                }
+                let is_volume_mute = vk_code == VK_VOLUME_MUTE.0 as u32;
 
-                    match controller.adjust_focused_app_volume(
-                        is_volume_up,
-                        is_volume_down,
-                        is_volume_mute,
-                    ) {
+                // Modifier + Ctrl + Mute: toggle mute of the last adjusted app
+                if is_volume_mute && modifier_pressed && key_held(last_app_mute_vk(modifier)) {
+                    match ctx.audio_controller.toggle_last_app_mute() {
+                        Ok(volume_info) if volume_info.is_no_session => {
+                            ctx.toast_ui.show_message(
+                                tr(Text::NothingToMute).to_string(),
+                                Duration::from_millis(HIDE_DELAY_MS),
+                            );
+                        }
                         Ok(volume_info) => {
-                            println!("Volume of '{}': {}", volume_info.app_name, action);
-
-                            // Show toast UI
-                            let toast_ptr = std::ptr::addr_of!(TOAST_UI);
-                            if let Some(toast) = &*toast_ptr {
-                                toast.show_volume(
-                                    volume_info.app_name,
-                                    volume_info.volume,
-                                    volume_info.is_muted,
-                                    volume_info.exe_path,
-                                );
-                            }
+                            log_event!(
+                                "Last app '{}' {}",
+                                volume_info.app_name,
+                                if volume_info.is_muted {
+                                    "muted"
+                                } else {
+                                    "unmuted"
+                                }
+                            );
+                            ctx.toast_ui.show_volume(volume_info);
                         }
                         Err(e) => {
-                            eprintln!("Error adjusting app volume: {}", e);
+                            log_event!("Error toggling last app mute: {}", e);
                         }
                     }
+
+                    return ctx.handled(code, wparam, lparam);
+                }
+
+                if is_volume_key(vk_code) && modifier_pressed && ctx.mixer_overlay.is_visible() {
+                    ctx.handle_mixer_key(is_volume_up, is_volume_down);
+                    return LRESULT(1);
+                }
+
+                // Auto-repeat of a key Volime is already repeating, even once
+                // a sticky or prefix press no longer applies
+                if is_repeat
+                    && ctx
+                        .key_repeat
+                        .get()
+                        .is_some_and(|repeat| repeat.vk_code == vk_code)
+                {
+                    return ctx.handled(code, wparam, lparam);
                 }
 
-                // Block key so it doesn't affect system volume
-                return LRESULT(1);
+                let config = ctx.config.read();
+                let held_actions = config.held_key_actions();
+                let mute_key_app_default = config.mute_key_app_default;
+                drop(config);
+
+                let modified = is_volume_key(vk_code)
+                    && (held_actions.is_some() || ctx.consume_sticky() || ctx.consume_prefix());
+                if modified || (is_volume_mute && mute_key_app_default) {
+                    // Auto-repeat of a held key, when only discrete presses
+                    // count or Volime repeats the key at its own interval
+                    if (discrete || own_repeat) && is_repeat {
+                        return ctx.handled(code, wparam, lparam);
+                    }
+
+                    // Modifier + media key: the action mapped to the key,
+                    // by default controlling the active app volume. Mute on
+                    // its own mutes the app with `mute_key_app_default`.
+                    let key_action = if modified {
+                        let key_actions = held_actions.unwrap_or(ctx.config.read().key_actions);
+                        key_actions.for_key(is_volume_up, is_volume_down)
+                    } else {
+                        KeyAction::AppMute
+                    };
+
+                    // A second mute right after the first is most likely a slip
+                    let is_mute_action = matches!(
+                        key_action,
+                        KeyAction::AppMute | KeyAction::MasterMute | KeyAction::Solo
+                    );
+                    if is_mute_action && ctx.mute_cooling_down() {
+                        log_event!("Mute ignored during cooldown");
+                        return ctx.handled(code, wparam, lparam);
+                    }
+
+                    ctx.run_key_action(key_action, is_volume_up, is_volume_down, is_volume_mute);
+                    if !is_repeat {
+                        ctx.start_repeat(vk_code, key_action);
+                    }
+
+                    // Block key so it doesn't affect system volume
+                    return ctx.handled(code, wparam, lparam);
+                }
+                // If no modifier key, let system handle the key normally
             }
-            // If no modifier key, let system handle the key normally
         }
     }
 
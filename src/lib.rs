@@ -0,0 +1,33 @@
+//! Per-application volume control for Windows.
+//!
+//! The `volime` binary is a thin wrapper around this crate; other Windows
+//! apps can embed the same pieces:
+//!
+//! - [`audio_control::AudioController`] finds the focused app's audio
+//!   session and changes its volume.
+//! - [`toast_ui::ToastUI`] shows the volume toast.
+//! - [`keyboard_hook::KeyboardHook`] and [`mouse_hook::MouseHook`] map the
+//!   media keys and mouse buttons to the controller.
+//! - [`config::Config`] holds the settings shared by all of them.
+//!
+//! COM must be initialized on the calling thread, and the hooks and windows
+//! need that thread to pump messages. Each hook can only be installed once
+//! per process at a time.
+
+#[macro_use]
+pub mod logger;
+
+pub mod app_commands;
+pub mod audio_control;
+pub mod config;
+pub mod i18n;
+pub mod keyboard_hook;
+pub mod macros;
+pub mod manager_ui;
+pub mod mixer_overlay;
+pub mod mouse_hook;
+mod policy_config;
+mod session_events;
+pub mod snapshot;
+pub mod system_tray;
+pub mod toast_ui;
@@ -0,0 +1,135 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Mutex, OnceLock};
+use windows::Win32::System::SystemInformation::GetLocalTime;
+
+const LOG_DIR: &str = "Volime";
+const LOG_FILE: &str = "volime.log";
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+
+/// Environment variable that enables file logging regardless of the config
+pub const LOG_ENV_VAR: &str = "VOLIME_LOG";
+
+static LOG_SENDER: OnceLock<Sender<String>> = OnceLock::new();
+
+/// Lines logged before `init` (e.g. while reading the config that enables
+/// the log file), handed to the writer once it starts. `None` after `init`.
+static PENDING: Mutex<Option<Vec<String>>> = Mutex::new(Some(Vec::new()));
+
+/// Print a line to the console and, when enabled, append it to the log file
+#[macro_export]
+macro_rules! log_event {
+    ($($arg:tt)*) => {
+        $crate::logger::write(format!($($arg)*))
+    };
+}
+
+pub fn env_enabled() -> bool {
+    std::env::var(LOG_ENV_VAR).is_ok_and(|value| !value.is_empty() && value != "0")
+}
+
+pub fn path() -> Option<PathBuf> {
+    let local_app_data = std::env::var_os("LOCALAPPDATA")?;
+    Some(PathBuf::from(local_app_data).join(LOG_DIR).join(LOG_FILE))
+}
+
+/// Start the background writer. Lines are handed over through a channel so
+/// logging from the keyboard hook never waits on disk I/O.
+pub fn init(enabled: bool) {
+    let pending = PENDING.lock().ok().and_then(|mut pending| pending.take());
+    if !enabled {
+        return;
+    }
+
+    let Some(path) = path() else {
+        eprintln!("Error enabling log file: LOCALAPPDATA is not set");
+        return;
+    };
+
+    if let Some(dir) = path.parent() {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            eprintln!("Error creating log directory {}: {}", dir.display(), e);
+            return;
+        }
+    }
+
+    println!("Logging to {}", path.display());
+
+    let (sender, receiver) = channel::<String>();
+    for line in pending.into_iter().flatten() {
+        let _ = sender.send(line);
+    }
+    if LOG_SENDER.set(sender).is_err() {
+        return;
+    }
+
+    std::thread::spawn(move || {
+        let mut file = open_log(&path);
+        let mut size = file
+            .as_ref()
+            .and_then(|f| f.metadata().ok())
+            .map_or(0, |m| m.len());
+
+        for line in receiver {
+            if size >= MAX_LOG_SIZE {
+                drop(file.take());
+                let _ = std::fs::rename(&path, path.with_extension("log.1"));
+                file = open_log(&path);
+                size = 0;
+            }
+
+            if let Some(f) = file.as_mut() {
+                if writeln!(f, "{}", line).is_ok() {
+                    size += line.len() as u64 + 1;
+                }
+            }
+        }
+    });
+}
+
+pub fn write(message: String) {
+    println!("{}", message);
+
+    let line = format!("{} {}", timestamp(), message);
+    if let Some(sender) = LOG_SENDER.get() {
+        let _ = sender.send(line);
+    } else if let Ok(mut pending) = PENDING.lock() {
+        if let Some(pending) = pending.as_mut() {
+            pending.push(line);
+        }
+    }
+}
+
+/// Append a line to the log file right away, bypassing the writer thread,
+/// for messages that must land before the process exits (e.g. a panic).
+/// Returns whether file logging is on.
+pub fn write_now(message: &str) -> bool {
+    if LOG_SENDER.get().is_none() {
+        return false;
+    }
+
+    if let Some(mut file) = path().as_deref().and_then(open_log) {
+        let _ = writeln!(file, "{} {}", timestamp(), message);
+    }
+    true
+}
+
+fn open_log(path: &Path) -> Option<File> {
+    match OpenOptions::new().create(true).append(true).open(path) {
+        Ok(file) => Some(file),
+        Err(e) => {
+            eprintln!("Error opening log file {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn timestamp() -> String {
+    let t = unsafe { GetLocalTime() };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+        t.wYear, t.wMonth, t.wDay, t.wHour, t.wMinute, t.wSecond, t.wMilliseconds
+    )
+}
@@ -0,0 +1,153 @@
+use windows::core::*;
+
+use crate::audio_control::AudioController;
+use crate::config::{MacroConfig, MacroStep};
+use crate::i18n::{tr_with, Text};
+
+/// What running a macro did
+#[derive(Debug, Default)]
+pub struct MacroOutcome {
+    /// Steps that were applied
+    pub applied: usize,
+    /// Steps with something to do, applied or not
+    pub total: usize,
+    /// Apps of the steps skipped because they had no audio session
+    pub not_running: Vec<String>,
+    /// Apps of the steps that failed
+    pub failed: Vec<String>,
+}
+
+impl MacroOutcome {
+    pub fn is_complete(&self) -> bool {
+        self.applied == self.total
+    }
+}
+
+/// Run the steps of `macro_config` in order. A step that fails or whose app
+/// isn't running is logged and skipped; the rest still run.
+pub fn run(audio_controller: &AudioController, macro_config: &MacroConfig) -> MacroOutcome {
+    let mut outcome = MacroOutcome::default();
+
+    for step in &macro_config.steps {
+        if step.volume.is_none() && step.mute.is_none() {
+            continue;
+        }
+        outcome.total += 1;
+
+        match run_step(audio_controller, step) {
+            Ok(true) => outcome.applied += 1,
+            Ok(false) => {
+                log_event!(
+                    "Macro '{}': '{}' is not running, skipped",
+                    macro_config.name,
+                    step.app
+                );
+                outcome.not_running.push(step.app.clone());
+            }
+            Err(e) => {
+                log_event!(
+                    "Macro '{}': error adjusting '{}': {}",
+                    macro_config.name,
+                    step.app,
+                    e
+                );
+                outcome.failed.push(step.app.clone());
+            }
+        }
+    }
+
+    log_event!(
+        "Macro '{}' applied {} of {} steps",
+        macro_config.name,
+        outcome.applied,
+        outcome.total
+    );
+    outcome
+}
+
+/// Apply one step through the by-name methods; false when the app has no
+/// audio session
+fn run_step(audio_controller: &AudioController, step: &MacroStep) -> Result<bool> {
+    match (step.volume, step.mute) {
+        (Some(volume), Some(muted)) => audio_controller
+            .set_app_volume(&step.app, volume, muted)
+            .map(|volume_info| !volume_info.is_no_session),
+        (Some(volume), None) => audio_controller
+            .set_app_volume_by_name(&step.app, volume)
+            .map(|volume_info| !volume_info.is_no_session),
+        (None, Some(muted)) => audio_controller.set_app_mute_by_name(&step.app, muted),
+        (None, None) => Ok(true),
+    }
+}
+
+/// Toast text for a macro that ran
+pub fn summary(macro_config: &MacroConfig, outcome: &MacroOutcome) -> String {
+    if outcome.is_complete() {
+        tr_with(Text::MacroApplied, &macro_config.name)
+    } else {
+        tr_with(
+            Text::MacroIncomplete,
+            &format!(
+                "{} ({}/{})",
+                macro_config.name, outcome.applied, outcome.total
+            ),
+        )
+    }
+}
+
+/// Mistakes in the configured macros, one message each, for reporting when
+/// the config is loaded. Whether the apps exist can only be told when a
+/// macro runs, as they may simply not be running yet.
+pub fn problems(macros: &[MacroConfig]) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    for (i, macro_config) in macros.iter().enumerate() {
+        let name = if macro_config.name.is_empty() {
+            problems.push(format!("Macro #{} has no name", i + 1));
+            format!("#{}", i + 1)
+        } else {
+            macro_config.name.clone()
+        };
+
+        if !macro_config.name.is_empty()
+            && macros[..i]
+                .iter()
+                .any(|other| other.name == macro_config.name)
+        {
+            problems.push(format!("Macro '{}' is defined more than once", name));
+        }
+        if let Some(key) = macro_config
+            .key
+            .as_deref()
+            .filter(|_| macro_config.vk().is_none())
+        {
+            problems.push(format!("Macro '{}': unknown key name '{}'", name, key));
+        }
+        if macro_config.steps.is_empty() {
+            problems.push(format!("Macro '{}' has no steps", name));
+        }
+
+        for (j, step) in macro_config.steps.iter().enumerate() {
+            if step.app.trim().is_empty() {
+                problems.push(format!("Macro '{}', step {}: no app", name, j + 1));
+            }
+            if step.volume.is_none() && step.mute.is_none() {
+                problems.push(format!(
+                    "Macro '{}', step {}: sets neither volume nor mute, ignored",
+                    name,
+                    j + 1
+                ));
+            }
+            if let Some(volume) = step.volume.filter(|volume| !(0.0..=1.0).contains(volume)) {
+                problems.push(format!(
+                    "Macro '{}', step {}: volume {} is outside 0.0 - 1.0, clamped",
+                    name,
+                    j + 1,
+                    volume
+                ));
+            }
+        }
+    }
+
+    problems
+}
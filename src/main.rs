@@ -1,24 +1,112 @@
 #![windows_subsystem = "windows"]
 
 use parking_lot::RwLock;
+use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use windows::core::*;
 use windows::Win32::System::Com::*;
 use windows::Win32::UI::HiDpi::*;
 use windows::Win32::UI::WindowsAndMessaging::*;
 
-mod audio_control;
-mod keyboard_hook;
-mod system_tray;
-mod toast_ui;
+mod cli;
+mod selftest;
 
-use audio_control::AudioController;
-use keyboard_hook::KeyboardHook;
-use system_tray::{ModifierKey, SystemTray};
-use toast_ui::ToastUI;
+use cli::{CliArgs, EnvVars};
+use volime::app_commands::AppCommandWindow;
+use volime::audio_control::{self, AudioController};
+use volime::config::{self, AppCommands, Config, Overrides};
+use volime::i18n::{tr, tr_with, Text};
+use volime::keyboard_hook::{KeyboardHook, WM_SAVE_CONFIG};
+use volime::log_event;
+use volime::logger;
+use volime::mixer_overlay::MixerOverlay;
+use volime::mouse_hook::MouseHook;
+use volime::system_tray::{SystemTray, WM_SYSTEM_RESUMED};
+use volime::toast_ui::ToastUI;
+
+/// Attempts at installing the keyboard hook, and the wait before the second
+/// one (doubled after each failure)
+const HOOK_INSTALL_ATTEMPTS: u32 = 4;
+const HOOK_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Run `install` until it succeeds, up to `HOOK_INSTALL_ATTEMPTS` times
+fn install_with_retry(install: impl Fn() -> Result<KeyboardHook>) -> Result<KeyboardHook> {
+    let mut delay = HOOK_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match install() {
+            Ok(hook) => return Ok(hook),
+            Err(e) if attempt < HOOK_INSTALL_ATTEMPTS => {
+                log_event!(
+                    "Installing the keyboard hook failed (attempt {} of {}): {}; retrying in {} ms",
+                    attempt,
+                    HOOK_INSTALL_ATTEMPTS,
+                    e,
+                    delay.as_millis()
+                );
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) => {
+                log_event!("Installing the keyboard hook failed, giving up: {}", e);
+                return Err(e);
+            }
+        }
+    }
+}
+
+/// Report panics in a message box, since with the "windows" subsystem
+/// there is no console to print them to. They are also written to the log
+/// file when it is enabled. Only plain Win32 calls are made here, so it is
+/// safe from any thread, COM apartment or hook callback.
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // Still print it for a console, with the backtrace if requested
+        default_hook(info);
+
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown error");
+        let location = info
+            .location()
+            .map(|location| format!(" at {}:{}", location.file(), location.line()))
+            .unwrap_or_default();
+        let details = format!(
+            "Panic in thread '{}'{}: {}",
+            std::thread::current().name().unwrap_or("unnamed"),
+            location,
+            message
+        );
+
+        let mut text = tr_with(Text::CrashText, &details);
+        if logger::write_now(&details) {
+            if let Some(path) = logger::path() {
+                text.push_str("\n\n");
+                text.push_str(&tr_with(Text::CrashLogged, &path.display().to_string()));
+            }
+        }
+
+        unsafe {
+            MessageBoxW(
+                None,
+                &HSTRING::from(text),
+                &HSTRING::from(tr(Text::CrashTitle)),
+                MB_OK | MB_ICONERROR | MB_TOPMOST,
+            );
+        }
+    }));
+}
 
 fn main() -> Result<()> {
+    install_panic_hook();
+
     // Enable DPI awareness for high-resolution displays
     unsafe {
         let _ = SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2);
@@ -32,15 +120,14 @@ fn main() -> Result<()> {
     println!("Press Ctrl+C to exit\n");
 
     // Initialize COM
-    unsafe {
-        let hr = CoInitializeEx(None, COINIT_MULTITHREADED);
-        if hr.is_err() {
-            eprintln!("Error initializing COM");
-        }
-    };
+    let com_initialized = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) }.is_ok();
+    if !com_initialized {
+        eprintln!("Error initializing COM");
+    }
 
     let running = Arc::new(AtomicBool::new(true));
     let running_clone = running.clone();
+    let suspended = Arc::new(AtomicBool::new(false));
 
     // Configure Ctrl+C handler
     ctrlc::set_handler(move || {
@@ -49,27 +136,133 @@ fn main() -> Result<()> {
     })
     .expect("Error configuring Ctrl+C handler");
 
-    // Create modifier key (default Shift)
-    let modifier_key = Arc::new(RwLock::new(ModifierKey::Shift));
+    // Load user settings. Precedence: command line > environment > config
+    // file > defaults
+    let cli_args = CliArgs::parse();
+    let env_vars = EnvVars::read();
+    let mut config = Config::load();
+    let mut overrides = Overrides::default();
+    env_vars.apply(&mut overrides);
+    cli_args.apply(&mut overrides);
+    config.override_with(overrides);
+    let config = Arc::new(RwLock::new(config));
 
-    // Create system tray
-    let _system_tray = SystemTray::new(modifier_key.clone(), running.clone())?;
+    // Start the log file writer if enabled in the config or environment
+    logger::init(config.read().log_to_file || logger::env_enabled());
+    cli::log_sources(&cli_args, &env_vars, &config.read());
+
+    if let Some(path) = &cli_args.export {
+        let (text, success) = match Config::export(path) {
+            Ok(()) => (format!("Exported settings to {}", path.display()), true),
+            Err(e) => (format!("Error exporting settings: {}", e), false),
+        };
+        cli::report("Volime export", &text, success);
+        unsafe { CoUninitialize() };
+        std::process::exit(if success { 0 } else { 1 });
+    }
+    if let Some(path) = &cli_args.import {
+        let (text, success) = match Config::import(path, cli_args.merge) {
+            Ok(_) => (format!("Imported settings from {}", path.display()), true),
+            Err(e) => (format!("Error importing settings: {}", e), false),
+        };
+        cli::report("Volime import", &text, success);
+        unsafe { CoUninitialize() };
+        std::process::exit(if success { 0 } else { 1 });
+    }
+
+    if cli_args.selftest {
+        let code = selftest::run(com_initialized, config);
+        unsafe { CoUninitialize() };
+        std::process::exit(code);
+    }
+
+    // Apply edits to the config file live, keeping the overrides on top
+    config::watch(config.clone());
+
+    if audio_control::is_remote_session() {
+        log_event!(
+            "Running in a Remote Desktop session: audio is redirected to the client, \
+             so per-app volume control may be unavailable"
+        );
+    }
 
     // Create audio controller
-    let audio_controller = Arc::new(AudioController::new()?);
+    let audio_controller = Arc::new(AudioController::new(config.clone())?);
+
+    // Create system tray (skipped in headless mode)
+    let system_tray = if config.read().show_tray {
+        Some(SystemTray::new(
+            running.clone(),
+            suspended.clone(),
+            config.clone(),
+            audio_controller.clone(),
+        )?)
+    } else {
+        println!("Running without tray icon");
+        None
+    };
 
     // Create toast UI
-    let toast_ui = Arc::new(ToastUI::new()?);
+    let toast_ui = Arc::new(ToastUI::new(config.clone())?);
+    audio_controller.watch_volume_changes(toast_ui.hwnd());
+    toast_ui.set_audio_controller(audio_controller.clone());
 
-    // Install keyboard hook
-    let hook = KeyboardHook::install(
-        audio_controller.clone(),
-        toast_ui.clone(),
-        modifier_key.clone(),
-    )?;
+    // Create mixer overlay (only opened when enabled in the config)
+    let mixer_overlay = Rc::new(MixerOverlay::new(config.clone())?);
 
-    println!("Initial modifier key: {}", modifier_key.read().to_string());
-    println!("Right-click the tray icon to change settings\n");
+    // Install keyboard hook, after a grace period: launched at logon, other
+    // software starting at the same time can make it fail or get dropped
+    let delay = Duration::from_millis(config.read().hook_install_delay_ms);
+    if !delay.is_zero() {
+        log_event!(
+            "Waiting {} ms before installing the keyboard hook",
+            delay.as_millis()
+        );
+        std::thread::sleep(delay);
+    }
+    let mut hook = install_with_retry(|| {
+        KeyboardHook::install(
+            audio_controller.clone(),
+            toast_ui.clone(),
+            mixer_overlay.clone(),
+            config.clone(),
+            suspended.clone(),
+        )
+    })?;
+
+    // Install mouse hook (opt-in)
+    let mut mouse_hook = if config.read().mouse.enabled {
+        Some(MouseHook::install(
+            audio_controller.clone(),
+            toast_ui.clone(),
+            config.clone(),
+            suspended.clone(),
+        )?)
+    } else {
+        None
+    };
+
+    // Receive volume app commands from headsets and remotes (opt-in)
+    let app_command_window = if config.read().app_commands_also_adjust != AppCommands::Off {
+        log_event!("App commands also adjust the app; Windows still changes the system volume");
+        match AppCommandWindow::create() {
+            Ok(window) => Some(window),
+            Err(e) => {
+                log_event!("Error creating the app command window: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    println!(
+        "{}",
+        tr_with(Text::InitialModifier, &config.read().modifier.to_string())
+    );
+    if config.read().show_tray {
+        println!("{}\n", tr(Text::RightClickTray));
+    }
 
     // Main loop
     unsafe {
@@ -81,6 +274,29 @@ fn main() -> Result<()> {
                     running.store(false, Ordering::SeqCst);
                     break;
                 }
+                if msg.message == WM_SAVE_CONFIG {
+                    // One write for a burst of changes
+                    while PeekMessageW(&mut msg, None, WM_SAVE_CONFIG, WM_SAVE_CONFIG, PM_REMOVE)
+                        .as_bool()
+                    {}
+                    if let Err(e) = config.read().save() {
+                        log_event!("Error saving config: {}", e);
+                    }
+                    continue;
+                }
+                if msg.message == WM_SYSTEM_RESUMED {
+                    match hook.reinstall() {
+                        Ok(()) => log_event!("Keyboard hook reinstalled after resume"),
+                        Err(e) => log_event!("Error reinstalling keyboard hook: {}", e),
+                    }
+                    if let Some(mouse_hook) = mouse_hook.as_mut() {
+                        match mouse_hook.reinstall() {
+                            Ok(()) => log_event!("Mouse hook reinstalled after resume"),
+                            Err(e) => log_event!("Error reinstalling mouse hook: {}", e),
+                        }
+                    }
+                    continue;
+                }
                 let _ = TranslateMessage(&msg);
                 DispatchMessageW(&msg);
             }
@@ -88,14 +304,62 @@ fn main() -> Result<()> {
             // Check if toast should be hidden
             toast_ui.check_hide();
 
+            // Animate the toast bar
+            toast_ui.tick();
+
+            // Apply gradual volume changes
+            audio_controller.tick_ramps();
+
+            // Enforce quiet hours as they begin
+            audio_controller.tick_quiet_hours();
+
+            // Lower background apps while the priority app plays
+            audio_controller.tick_ducking();
+
+            // Remember which apps played, for the "recentlyaudible" strategy
+            audio_controller.tick_recently_audible();
+
+            // Start newly playing apps at their configured volume
+            audio_controller.tick_startup_volumes();
+
+            // Revert temporary changes whose time is up
+            audio_controller.tick_temporary_volumes();
+
+            // Open or refresh the mixer overlay while the modifier is held
+            hook.tick();
+
             std::thread::sleep(std::time::Duration::from_millis(10));
         }
     }
 
-    // Uninstall hook
+    // Shut down in order: every COM object must be released before
+    // CoUninitialize, and no key may start a new adjustment meanwhile
+    log_event!("Shutting down: ignoring new volume keys");
+    suspended.store(true, Ordering::SeqCst);
+
+    // Uninstall hooks
+    drop(app_command_window);
+    drop(mouse_hook);
     drop(hook);
 
+    log_event!("Shutting down: closing windows");
+    drop(system_tray);
+    drop(toast_ui);
+
+    log_event!("Shutting down: releasing audio sessions");
+    audio_controller.restore_ducked();
+    if let Err(e) = audio_controller.end_boost() {
+        log_event!("Error ending boost: {}", e);
+    }
+    debug_assert_eq!(
+        Arc::strong_count(&audio_controller),
+        1,
+        "audio controller still referenced at shutdown"
+    );
+    drop(audio_controller);
+
     unsafe { CoUninitialize() };
+    log_event!("Shutting down: COM uninitialized");
 
     println!("Volime closed.");
     Ok(())
@@ -0,0 +1,376 @@
+use parking_lot::RwLock;
+use std::sync::{Arc, Mutex};
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::Graphics::Gdi::*;
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::HiDpi::*;
+use windows::Win32::UI::WindowsAndMessaging::*;
+
+use crate::config::Config;
+use crate::i18n::{tr, Text};
+
+const WINDOW_WIDTH: i32 = 440;
+const WINDOW_HEIGHT: i32 = 320;
+
+const IDC_LIST: i32 = 100;
+const IDC_CAP: i32 = 101;
+const IDC_NAME: i32 = 102;
+const IDC_SAVE: i32 = 103;
+const IDC_DELETE: i32 = 104;
+
+/// Window listing the per-app settings stored in the config (volume cap and
+/// display name) so they can be edited or deleted. Hidden, not destroyed,
+/// when closed.
+pub struct ManagerUI {
+    hwnd: HWND,
+    state: Arc<Mutex<ManagerState>>,
+}
+
+struct ManagerState {
+    config: Arc<RwLock<Config>>,
+    /// Process names in list order
+    apps: Vec<String>,
+}
+
+impl ManagerUI {
+    pub fn new(config: Arc<RwLock<Config>>) -> Result<Self> {
+        unsafe {
+            let instance = GetModuleHandleW(None)?;
+            let class_name = w!("VolimeManagerClass");
+
+            let wc = WNDCLASSW {
+                lpfnWndProc: Some(Self::wnd_proc),
+                hInstance: instance.into(),
+                hCursor: LoadCursorW(None, IDC_ARROW)?,
+                hbrBackground: HBRUSH((COLOR_BTNFACE.0 + 1) as isize as *mut _),
+                lpszClassName: class_name,
+                ..Default::default()
+            };
+
+            let atom = RegisterClassW(&wc);
+            if atom == 0 {
+                return Err(Error::from_win32());
+            }
+
+            // Sized and centered on every show
+            let hwnd = CreateWindowExW(
+                WINDOW_EX_STYLE(0),
+                class_name,
+                &HSTRING::from(tr(Text::AppSettingsTitle)),
+                WS_OVERLAPPED | WS_CAPTION | WS_SYSMENU | WS_MINIMIZEBOX,
+                0,
+                0,
+                WINDOW_WIDTH,
+                WINDOW_HEIGHT,
+                None,
+                None,
+                instance,
+                None,
+            )?;
+
+            Self::create_controls(hwnd, instance.into())?;
+
+            let state = Arc::new(Mutex::new(ManagerState {
+                config,
+                apps: Vec::new(),
+            }));
+
+            let state_ptr = Arc::into_raw(state.clone()) as isize;
+            SetWindowLongPtrW(hwnd, GWLP_USERDATA, state_ptr);
+
+            Ok(ManagerUI { hwnd, state })
+        }
+    }
+
+    unsafe fn create_controls(hwnd: HWND, instance: HINSTANCE) -> Result<()> {
+        let scale = GetDpiForWindow(hwnd) as f32 / 96.0;
+        let scaled = |value: i32| (value as f32 * scale) as i32;
+        let font = GetStockObject(DEFAULT_GUI_FONT);
+
+        let controls: [(PCWSTR, HSTRING, i32, i32, [i32; 4]); 7] = [
+            (
+                w!("LISTBOX"),
+                HSTRING::new(),
+                LBS_NOTIFY | WS_VSCROLL.0 as i32 | WS_BORDER.0 as i32,
+                IDC_LIST,
+                [10, 10, 400, 190],
+            ),
+            (
+                w!("STATIC"),
+                tr(Text::CapLabel).into(),
+                0,
+                0,
+                [10, 214, 60, 20],
+            ),
+            (
+                w!("EDIT"),
+                HSTRING::new(),
+                ES_NUMBER | WS_BORDER.0 as i32,
+                IDC_CAP,
+                [70, 210, 50, 22],
+            ),
+            (
+                w!("STATIC"),
+                tr(Text::DisplayNameLabel).into(),
+                0,
+                0,
+                [135, 214, 85, 20],
+            ),
+            (
+                w!("EDIT"),
+                HSTRING::new(),
+                ES_AUTOHSCROLL | WS_BORDER.0 as i32,
+                IDC_NAME,
+                [220, 210, 190, 22],
+            ),
+            (
+                w!("BUTTON"),
+                tr(Text::Save).into(),
+                BS_DEFPUSHBUTTON,
+                IDC_SAVE,
+                [230, 245, 85, 26],
+            ),
+            (
+                w!("BUTTON"),
+                tr(Text::Delete).into(),
+                BS_PUSHBUTTON,
+                IDC_DELETE,
+                [325, 245, 85, 26],
+            ),
+        ];
+
+        for (class, text, style, id, [x, y, width, height]) in controls {
+            let control = CreateWindowExW(
+                WINDOW_EX_STYLE(0),
+                class,
+                &text,
+                WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(style as u32),
+                scaled(x),
+                scaled(y),
+                scaled(width),
+                scaled(height),
+                hwnd,
+                HMENU(id as isize as *mut _),
+                instance,
+                None,
+            )?;
+            SendMessageW(control, WM_SETFONT, WPARAM(font.0 as usize), LPARAM(1));
+        }
+
+        Ok(())
+    }
+
+    pub fn show(&self) {
+        unsafe {
+            Self::refresh(self.hwnd, &self.state);
+
+            let scale = GetDpiForWindow(self.hwnd) as f32 / 96.0;
+            let width = (WINDOW_WIDTH as f32 * scale) as i32;
+            let height = (WINDOW_HEIGHT as f32 * scale) as i32;
+            let screen_width = GetSystemMetrics(SM_CXSCREEN);
+            let screen_height = GetSystemMetrics(SM_CYSCREEN);
+            let _ = SetWindowPos(
+                self.hwnd,
+                None,
+                (screen_width - width) / 2,
+                (screen_height - height) / 2,
+                width,
+                height,
+                SWP_NOZORDER,
+            );
+
+            let _ = ShowWindow(self.hwnd, SW_SHOW);
+            let _ = SetForegroundWindow(self.hwnd);
+        }
+    }
+
+    /// Rebuild the list from the current config, keeping the selection. The
+    /// config may have been edited externally since the last refresh.
+    unsafe fn refresh(hwnd: HWND, state: &Mutex<ManagerState>) {
+        let selected = Self::selected_app(hwnd, state);
+        let mut state = state.lock().unwrap();
+        let config = state.config.read().clone();
+
+        let mut apps: Vec<String> = config
+            .max_volume
+            .keys()
+            .chain(config.display_names.keys())
+            .cloned()
+            .collect();
+        apps.sort();
+        apps.dedup();
+
+        SendDlgItemMessageW(hwnd, IDC_LIST, LB_RESETCONTENT, WPARAM(0), LPARAM(0));
+        for app in &apps {
+            let mut details = Vec::new();
+            if let Some(max) = config.max_volume_for(app) {
+                details.push(format!("cap {}%", (max * 100.0).round() as i32));
+            }
+            if let Some(name) = config.display_names.get(app) {
+                details.push(format!("shown as \"{}\"", name));
+            }
+
+            let line = HSTRING::from(format!("{}  -  {}", app, details.join(", ")));
+            SendDlgItemMessageW(
+                hwnd,
+                IDC_LIST,
+                LB_ADDSTRING,
+                WPARAM(0),
+                LPARAM(line.as_ptr() as isize),
+            );
+        }
+
+        let index = selected.and_then(|selected| apps.iter().position(|app| *app == selected));
+        state.apps = apps;
+        drop(state);
+
+        match index {
+            Some(index) => {
+                SendDlgItemMessageW(hwnd, IDC_LIST, LB_SETCURSEL, WPARAM(index), LPARAM(0));
+            }
+            None => {
+                let _ = SetDlgItemTextW(hwnd, IDC_CAP, w!(""));
+                let _ = SetDlgItemTextW(hwnd, IDC_NAME, w!(""));
+            }
+        }
+    }
+
+    unsafe fn selected_app(hwnd: HWND, state: &Mutex<ManagerState>) -> Option<String> {
+        let index = SendDlgItemMessageW(hwnd, IDC_LIST, LB_GETCURSEL, WPARAM(0), LPARAM(0)).0;
+        if index < 0 {
+            return None;
+        }
+        state.lock().unwrap().apps.get(index as usize).cloned()
+    }
+
+    /// Fill the edit boxes with the settings of the selected app
+    unsafe fn load_selection(hwnd: HWND, state: &Mutex<ManagerState>) {
+        let Some(app) = Self::selected_app(hwnd, state) else {
+            return;
+        };
+
+        let state = state.lock().unwrap();
+        let config = state.config.read();
+        let cap = config
+            .max_volume_for(&app)
+            .map(|max| ((max * 100.0).round() as i32).to_string())
+            .unwrap_or_default();
+        let name = config.display_names.get(&app).cloned().unwrap_or_default();
+        drop(config);
+        drop(state);
+
+        let _ = SetDlgItemTextW(hwnd, IDC_CAP, &HSTRING::from(cap));
+        let _ = SetDlgItemTextW(hwnd, IDC_NAME, &HSTRING::from(name));
+    }
+
+    /// Apply the edit boxes to the selected app (an empty box removes that
+    /// setting), or remove all its settings when `delete` is set
+    unsafe fn apply(hwnd: HWND, state: &Mutex<ManagerState>, delete: bool) {
+        let Some(app) = Self::selected_app(hwnd, state) else {
+            return;
+        };
+
+        let cap = dialog_text(hwnd, IDC_CAP);
+        let name = dialog_text(hwnd, IDC_NAME);
+        let cap = match cap.trim() {
+            "" => None,
+            text => match text.parse::<u32>() {
+                Ok(percent) if percent <= 100 => Some(percent as f32 / 100.0),
+                _ => {
+                    MessageBoxW(
+                        hwnd,
+                        &HSTRING::from(tr(Text::CapOutOfRange)),
+                        w!("Volime"),
+                        MB_OK | MB_ICONWARNING,
+                    );
+                    return;
+                }
+            },
+        };
+
+        {
+            let state = state.lock().unwrap();
+            let mut config = state.config.write();
+            if delete {
+                config.set_max_volume(&app, None);
+                config.display_names.remove(&app);
+                log_event!("Removed the settings of '{}'", app);
+            } else {
+                config.set_max_volume(&app, cap);
+                match name.trim() {
+                    "" => config.display_names.remove(&app),
+                    name => config.display_names.insert(app.clone(), name.to_string()),
+                };
+                log_event!("Updated the settings of '{}'", app);
+            }
+
+            if let Err(e) = config.save() {
+                log_event!("Error saving config: {}", e);
+            }
+        }
+
+        Self::refresh(hwnd, state);
+    }
+
+    unsafe extern "system" fn wnd_proc(
+        hwnd: HWND,
+        msg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA);
+        if state_ptr == 0 {
+            return DefWindowProcW(hwnd, msg, wparam, lparam);
+        }
+        let state = Arc::from_raw(state_ptr as *const Mutex<ManagerState>);
+
+        let result = match msg {
+            WM_COMMAND => {
+                let id = (wparam.0 & 0xFFFF) as i32;
+                let notification = ((wparam.0 >> 16) & 0xFFFF) as u32;
+                match (id, notification) {
+                    (IDC_LIST, LBN_SELCHANGE) => Self::load_selection(hwnd, &state),
+                    (IDC_SAVE, BN_CLICKED) => Self::apply(hwnd, &state, false),
+                    (IDC_DELETE, BN_CLICKED) => Self::apply(hwnd, &state, true),
+                    _ => {}
+                }
+                LRESULT(0)
+            }
+            WM_ACTIVATE => {
+                // Pick up external edits of the config file
+                if (wparam.0 & 0xFFFF) as u32 != WA_INACTIVE {
+                    Self::refresh(hwnd, &state);
+                }
+                DefWindowProcW(hwnd, msg, wparam, lparam)
+            }
+            WM_CLOSE => {
+                let _ = ShowWindow(hwnd, SW_HIDE);
+                LRESULT(0)
+            }
+            _ => DefWindowProcW(hwnd, msg, wparam, lparam),
+        };
+
+        std::mem::forget(state); // No liberar el Arc
+        result
+    }
+}
+
+/// Text of a child control
+unsafe fn dialog_text(hwnd: HWND, id: i32) -> String {
+    let mut buffer = [0u16; 256];
+    let len = GetDlgItemTextW(hwnd, id, &mut buffer) as usize;
+    String::from_utf16_lossy(&buffer[..len])
+}
+
+impl Drop for ManagerUI {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.hwnd.is_invalid() {
+                SetWindowLongPtrW(self.hwnd, GWLP_USERDATA, 0);
+                drop(Arc::from_raw(Arc::as_ptr(&self.state)));
+                DestroyWindow(self.hwnd).ok();
+            }
+        }
+    }
+}
@@ -0,0 +1,456 @@
+use parking_lot::RwLock;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::Graphics::Gdi::*;
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::HiDpi::*;
+use windows::Win32::UI::WindowsAndMessaging::*;
+
+use crate::audio_control::SessionInfo;
+use crate::config::Config;
+use crate::toast_ui::{apply_round_region, extract_icon, parse_hex_color};
+
+const OVERLAY_WIDTH: i32 = 280;
+const ROW_HEIGHT: i32 = 40;
+const PADDING: i32 = 8;
+/// Sessions shown at most ("top few", playing ones first)
+const MAX_ROWS: usize = 6;
+/// How often the bars and peak meters are refreshed while open
+pub const REFRESH_INTERVAL_MS: u64 = 100;
+
+/// Mixer shown while the modifier is held: one row per audio session with
+/// its volume bar and peak meter. The selected row is the target of the
+/// volume keys until the modifier is released. Used only on the thread
+/// that created it (the keyboard hook's), hence the `Rc<RefCell>`.
+pub struct MixerOverlay {
+    hwnd: HWND,
+    state: Rc<RefCell<OverlayState>>,
+}
+
+struct OverlayState {
+    config: Arc<RwLock<Config>>,
+    sessions: Vec<SessionInfo>,
+    selected: usize,
+    /// Icons by executable path, kept for the lifetime of the overlay
+    icons: HashMap<String, HICON>,
+    last_refresh: Instant,
+}
+
+impl MixerOverlay {
+    pub fn new(config: Arc<RwLock<Config>>) -> Result<Self> {
+        unsafe {
+            let instance = GetModuleHandleW(None)?;
+            let class_name = w!("VolimeMixerOverlayClass");
+
+            let wc = WNDCLASSW {
+                style: CS_HREDRAW | CS_VREDRAW | CS_DROPSHADOW,
+                lpfnWndProc: Some(Self::wnd_proc),
+                hInstance: instance.into(),
+                hCursor: LoadCursorW(None, IDC_ARROW)?,
+                hbrBackground: CreateSolidBrush(COLORREF(0x00000000)),
+                lpszClassName: class_name,
+                ..Default::default()
+            };
+
+            let atom = RegisterClassW(&wc);
+            if atom == 0 {
+                return Err(Error::from_win32());
+            }
+
+            // Sized and positioned on every show, once the row count is known
+            let hwnd = CreateWindowExW(
+                WS_EX_TOPMOST | WS_EX_TOOLWINDOW | WS_EX_NOACTIVATE,
+                class_name,
+                w!("Volime Mixer"),
+                WS_POPUP,
+                0,
+                0,
+                OVERLAY_WIDTH,
+                ROW_HEIGHT,
+                None,
+                None,
+                instance,
+                None,
+            )?;
+
+            let state = Rc::new(RefCell::new(OverlayState {
+                config,
+                sessions: Vec::new(),
+                selected: 0,
+                icons: HashMap::new(),
+                last_refresh: Instant::now(),
+            }));
+
+            let state_ptr = Rc::into_raw(state.clone()) as isize;
+            SetWindowLongPtrW(hwnd, GWLP_USERDATA, state_ptr);
+
+            Ok(MixerOverlay { hwnd, state })
+        }
+    }
+
+    /// Open the overlay with `selected_app` preselected when it is listed
+    pub fn show(&self, sessions: Vec<SessionInfo>, selected_app: Option<&str>) {
+        let mut state = self.state.borrow_mut();
+        state.selected = selected_app
+            .and_then(|name| {
+                sessions
+                    .iter()
+                    .take(MAX_ROWS)
+                    .position(|session| session.app_name.eq_ignore_ascii_case(name))
+            })
+            .unwrap_or(0);
+        Self::set_sessions(&mut state, sessions);
+        let rows = state.sessions.len();
+        let corner_radius = state.config.read().toast_corner_radius;
+        drop(state);
+
+        unsafe {
+            Self::place(self.hwnd, rows, corner_radius);
+            let _ = ShowWindow(self.hwnd, SW_SHOWNOACTIVATE);
+            let _ = InvalidateRect(self.hwnd, None, true);
+        }
+    }
+
+    /// Replace the listed sessions, keeping the selection on the same app
+    pub fn update(&self, sessions: Vec<SessionInfo>) {
+        let mut state = self.state.borrow_mut();
+        let selected_app = state
+            .sessions
+            .get(state.selected)
+            .map(|session| session.app_name.clone());
+        let old_rows = state.sessions.len();
+
+        Self::set_sessions(&mut state, sessions);
+        if let Some(name) = selected_app {
+            state.selected = state
+                .sessions
+                .iter()
+                .position(|session| session.app_name.eq_ignore_ascii_case(&name))
+                .unwrap_or(0);
+        }
+
+        let rows = state.sessions.len();
+        let corner_radius = state.config.read().toast_corner_radius;
+        drop(state);
+
+        unsafe {
+            if rows != old_rows {
+                Self::place(self.hwnd, rows, corner_radius);
+            }
+            let _ = InvalidateRect(self.hwnd, None, false);
+        }
+    }
+
+    fn set_sessions(state: &mut OverlayState, mut sessions: Vec<SessionInfo>) {
+        sessions.truncate(MAX_ROWS);
+
+        for path in sessions
+            .iter()
+            .filter_map(|session| session.exe_path.as_ref())
+        {
+            if !state.icons.contains_key(path) {
+                if let Some(icon) = extract_icon(path) {
+                    state.icons.insert(path.clone(), icon);
+                }
+            }
+        }
+
+        state.selected = state.selected.min(sessions.len().saturating_sub(1));
+        state.sessions = sessions;
+        state.last_refresh = Instant::now();
+    }
+
+    /// Move the selection to the next row, wrapping around
+    pub fn select_next(&self) {
+        let mut state = self.state.borrow_mut();
+        if !state.sessions.is_empty() {
+            state.selected = (state.selected + 1) % state.sessions.len();
+        }
+        drop(state);
+
+        unsafe {
+            let _ = InvalidateRect(self.hwnd, None, false);
+        }
+    }
+
+    /// Process name of the selected session
+    pub fn selected_app(&self) -> Option<String> {
+        let state = self.state.borrow();
+        state
+            .sessions
+            .get(state.selected)
+            .map(|session| session.app_name.clone())
+    }
+
+    /// Whether the live values are older than `REFRESH_INTERVAL_MS`
+    pub fn refresh_due(&self) -> bool {
+        let state = self.state.borrow();
+        state.last_refresh.elapsed() >= Duration::from_millis(REFRESH_INTERVAL_MS)
+    }
+
+    pub fn is_visible(&self) -> bool {
+        unsafe { IsWindowVisible(self.hwnd).as_bool() }
+    }
+
+    pub fn hide(&self) {
+        unsafe {
+            let _ = ShowWindow(self.hwnd, SW_HIDE);
+        }
+    }
+
+    /// Size the window for `rows` sessions and center it on the primary screen
+    unsafe fn place(hwnd: HWND, rows: usize, corner_radius: u32) {
+        let scale = GetDpiForWindow(hwnd) as f32 / 96.0;
+        let scaled_width = (OVERLAY_WIDTH as f32 * scale) as i32;
+        let scaled_height = ((PADDING * 2 + ROW_HEIGHT * rows.max(1) as i32) as f32 * scale) as i32;
+        let scaled_radius = (corner_radius as f32 * scale) as i32;
+
+        let screen_width = GetSystemMetrics(SM_CXSCREEN);
+        let screen_height = GetSystemMetrics(SM_CYSCREEN);
+        let _ = SetWindowPos(
+            hwnd,
+            HWND_TOPMOST,
+            (screen_width - scaled_width) / 2,
+            (screen_height - scaled_height) / 2,
+            scaled_width,
+            scaled_height,
+            SWP_NOACTIVATE,
+        );
+
+        apply_round_region(hwnd, scaled_width, scaled_height, scaled_radius);
+    }
+
+    unsafe extern "system" fn wnd_proc(
+        hwnd: HWND,
+        msg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        match msg {
+            WM_PAINT => {
+                let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA);
+                if state_ptr != 0 {
+                    let state = Rc::from_raw(state_ptr as *const RefCell<OverlayState>);
+                    Self::paint(hwnd, &state);
+                    std::mem::forget(state); // No liberar el Rc
+                }
+                LRESULT(0)
+            }
+            _ => DefWindowProcW(hwnd, msg, wparam, lparam),
+        }
+    }
+
+    unsafe fn paint(hwnd: HWND, state: &Rc<RefCell<OverlayState>>) {
+        let mut ps = PAINTSTRUCT::default();
+        let hdc = BeginPaint(hwnd, &mut ps);
+
+        if hdc.is_invalid() {
+            return;
+        }
+
+        let state = state.borrow();
+        let scale = GetDpiForWindow(hwnd) as f32 / 96.0;
+        let scaled = |value: i32| (value as f32 * scale) as i32;
+
+        let mut client_rect = RECT::default();
+        let _ = GetClientRect(hwnd, &mut client_rect);
+        let width = client_rect.right;
+        let height = client_rect.bottom;
+
+        // Mismo fondo y borde que el toast
+        let config = state.config.read();
+        let scaled_radius = (config.toast_corner_radius as f32 * scale) as i32;
+        let border_width = (config.toast_border_width as f32 * scale).round() as i32;
+        let border_color =
+            parse_hex_color(&config.toast_border_color).unwrap_or(COLORREF(0x00404040));
+        drop(config);
+
+        let brush = CreateSolidBrush(COLORREF(0x00282828));
+        let pen = if border_width > 0 {
+            CreatePen(PS_SOLID, border_width, border_color)
+        } else {
+            CreatePen(PS_NULL, 0, border_color)
+        };
+        let old_brush = SelectObject(hdc, brush);
+        let old_pen = SelectObject(hdc, pen);
+        let _ = RoundRect(hdc, 0, 0, width, height, scaled_radius, scaled_radius);
+        SelectObject(hdc, old_brush);
+        SelectObject(hdc, old_pen);
+        let _ = DeleteObject(brush);
+        let _ = DeleteObject(pen);
+
+        SetBkMode(hdc, TRANSPARENT);
+
+        let font_name: Vec<u16> = "Segoe UI Variable\0".encode_utf16().collect();
+        let font = CreateFontW(
+            -scaled(13),
+            0,
+            0,
+            0,
+            FW_NORMAL.0 as i32,
+            0,
+            0,
+            0,
+            DEFAULT_CHARSET.0 as u32,
+            OUT_DEFAULT_PRECIS.0 as u32,
+            CLIP_DEFAULT_PRECIS.0 as u32,
+            CLEARTYPE_QUALITY.0 as u32,
+            (DEFAULT_PITCH.0 | FF_DONTCARE.0) as u32,
+            PCWSTR::from_raw(font_name.as_ptr()),
+        );
+        let old_font = SelectObject(hdc, font);
+
+        for (i, session) in state.sessions.iter().enumerate() {
+            let row_top = scaled(PADDING) + scaled(ROW_HEIGHT) * i as i32;
+            let row_bottom = row_top + scaled(ROW_HEIGHT);
+
+            // Resaltar la fila seleccionada
+            if i == state.selected {
+                let highlight = CreateSolidBrush(COLORREF(0x00404040));
+                let highlight_rect = RECT {
+                    left: scaled(PADDING / 2),
+                    top: row_top,
+                    right: width - scaled(PADDING / 2),
+                    bottom: row_bottom,
+                };
+                FillRect(hdc, &highlight_rect, highlight);
+                let _ = DeleteObject(highlight);
+            }
+
+            let icon_size = scaled(20);
+            let icon_x = scaled(PADDING + 4);
+            if let Some(icon) = session
+                .exe_path
+                .as_ref()
+                .and_then(|path| state.icons.get(path))
+            {
+                let _ = DrawIconEx(
+                    hdc,
+                    icon_x,
+                    row_top + (scaled(ROW_HEIGHT) - icon_size) / 2,
+                    *icon,
+                    icon_size,
+                    icon_size,
+                    0,
+                    None,
+                    DI_NORMAL,
+                );
+            }
+
+            let content_x = icon_x + icon_size + scaled(10);
+            let value_width = scaled(36);
+            let bar_width = width - content_x - value_width - scaled(PADDING + 6);
+
+            // Nombre del proceso
+            SetTextColor(hdc, COLORREF(0x00CCCCCC));
+            let mut name_wide: Vec<u16> = state
+                .config
+                .read()
+                .display_name(&session.app_name)
+                .encode_utf16()
+                .chain(std::iter::once(0))
+                .collect();
+            let mut name_rect = RECT {
+                left: content_x,
+                top: row_top + scaled(4),
+                right: content_x + bar_width,
+                bottom: row_top + scaled(22),
+            };
+            DrawTextW(
+                hdc,
+                &mut name_wide,
+                &mut name_rect,
+                DT_LEFT | DT_SINGLELINE | DT_END_ELLIPSIS,
+            );
+
+            // Barra de volumen
+            let bar_y = row_top + scaled(25);
+            let bar_height = scaled(4);
+            let bg_brush = CreateSolidBrush(COLORREF(0x00AAAAAA));
+            let bg_rect = RECT {
+                left: content_x,
+                top: bar_y,
+                right: content_x + bar_width,
+                bottom: bar_y + bar_height,
+            };
+            FillRect(hdc, &bg_rect, bg_brush);
+            let _ = DeleteObject(bg_brush);
+
+            if !session.is_muted {
+                let fill_brush = CreateSolidBrush(COLORREF(0x00FFCE4E));
+                let fill_rect = RECT {
+                    left: content_x,
+                    top: bar_y,
+                    right: content_x + (bar_width as f32 * session.volume.min(1.0)) as i32,
+                    bottom: bar_y + bar_height,
+                };
+                FillRect(hdc, &fill_rect, fill_brush);
+                let _ = DeleteObject(fill_brush);
+            }
+
+            // Medidor de pico bajo la barra
+            let peak_y = bar_y + bar_height + scaled(2);
+            let peak_brush = CreateSolidBrush(COLORREF(0x0066CC66));
+            let peak_rect = RECT {
+                left: content_x,
+                top: peak_y,
+                right: content_x + (bar_width as f32 * session.peak.clamp(0.0, 1.0)) as i32,
+                bottom: peak_y + scaled(2).max(1),
+            };
+            FillRect(hdc, &peak_rect, peak_brush);
+            let _ = DeleteObject(peak_brush);
+
+            // Porcentaje a la derecha
+            let value_text = if session.is_muted {
+                "M".to_string()
+            } else {
+                let percent = state
+                    .config
+                    .read()
+                    .display_percent(&session.app_name, session.volume);
+                format!("{}", percent)
+            };
+            let mut value_wide: Vec<u16> = value_text
+                .encode_utf16()
+                .chain(std::iter::once(0))
+                .collect();
+            let mut value_rect = RECT {
+                left: width - scaled(PADDING) - value_width,
+                top: row_top,
+                right: width - scaled(PADDING),
+                bottom: row_bottom,
+            };
+            DrawTextW(
+                hdc,
+                &mut value_wide,
+                &mut value_rect,
+                DT_CENTER | DT_SINGLELINE | DT_VCENTER,
+            );
+        }
+
+        SelectObject(hdc, old_font);
+        let _ = DeleteObject(font);
+
+        let _ = EndPaint(hwnd, &ps);
+    }
+}
+
+impl Drop for MixerOverlay {
+    fn drop(&mut self) {
+        unsafe {
+            for (_, icon) in self.state.borrow_mut().icons.drain() {
+                let _ = DestroyIcon(icon);
+            }
+            if !self.hwnd.is_invalid() {
+                SetWindowLongPtrW(self.hwnd, GWLP_USERDATA, 0);
+                drop(Rc::from_raw(Rc::as_ptr(&self.state)));
+                DestroyWindow(self.hwnd).ok();
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use parking_lot::RwLock;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::UI::WindowsAndMessaging::*;
+
+use crate::audio_control::{is_remote_session, AudioController};
+use crate::config::{Config, MouseButton};
+use crate::i18n::{tr, Text};
+use crate::toast_ui::{ToastUI, HIDE_DELAY_MS};
+
+/// What `mouse_proc` needs, like the keyboard hook's context. Runs on the
+/// thread that installed the hook.
+struct MouseContext {
+    audio_controller: Arc<AudioController>,
+    toast_ui: Arc<ToastUI>,
+    config: Arc<RwLock<Config>>,
+    suspended: Arc<AtomicBool>,
+    /// Button whose press was swallowed, so its release is swallowed too
+    swallowed: Cell<Option<MouseButton>>,
+}
+
+static mut MOUSE_CONTEXT: Option<MouseContext> = None;
+
+fn context() -> Option<&'static MouseContext> {
+    unsafe { (*std::ptr::addr_of!(MOUSE_CONTEXT)).as_ref() }
+}
+
+/// Optional `WH_MOUSE_LL` hook mapping mouse buttons and the wheel to
+/// volume actions on the focused app. Everything else passes through.
+pub struct MouseHook {
+    hook: HHOOK,
+}
+
+impl MouseHook {
+    pub fn install(
+        audio_controller: Arc<AudioController>,
+        toast_ui: Arc<ToastUI>,
+        config: Arc<RwLock<Config>>,
+        suspended: Arc<AtomicBool>,
+    ) -> Result<Self> {
+        unsafe {
+            if context().is_some() {
+                return Err(Error::new(
+                    ERROR_ALREADY_EXISTS.to_hresult(),
+                    "Mouse hook is already installed",
+                ));
+            }
+
+            MOUSE_CONTEXT = Some(MouseContext {
+                audio_controller,
+                toast_ui,
+                config,
+                suspended,
+                swallowed: Cell::new(None),
+            });
+
+            let hook = match SetWindowsHookExW(WH_MOUSE_LL, Some(mouse_proc), None, 0) {
+                Ok(hook) if !hook.is_invalid() => hook,
+                result => {
+                    MOUSE_CONTEXT = None;
+                    return Err(result.err().unwrap_or_else(Error::from_win32));
+                }
+            };
+
+            log_event!("Mouse hook installed successfully");
+
+            Ok(MouseHook { hook })
+        }
+    }
+
+    /// Replace the hook with a fresh one, like `KeyboardHook::reinstall`
+    pub fn reinstall(&mut self) -> Result<()> {
+        unsafe {
+            let hook = SetWindowsHookExW(WH_MOUSE_LL, Some(mouse_proc), None, 0)?;
+            if hook.is_invalid() {
+                return Err(Error::from_win32());
+            }
+
+            let _ = UnhookWindowsHookEx(self.hook);
+            self.hook = hook;
+            Ok(())
+        }
+    }
+}
+
+impl Drop for MouseHook {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = UnhookWindowsHookEx(self.hook);
+            MOUSE_CONTEXT = None;
+            log_event!("Mouse hook uninstalled");
+        }
+    }
+}
+
+impl MouseContext {
+    /// Adjust the focused app like Modifier + a volume key would
+    fn adjust(&self, volume_up: bool, volume_down: bool, mute: bool) {
+        match self
+            .audio_controller
+            .adjust_focused_app_volume(volume_up, volume_down, mute)
+        {
+            Ok(volume_info) => {
+                log_event!(
+                    "Volume of '{}' from mouse: {}%{} (matched {})",
+                    volume_info.app_name,
+                    (volume_info.volume * 100.0).round() as i32,
+                    if volume_info.is_muted { " (muted)" } else { "" },
+                    volume_info.match_kind.as_str()
+                );
+                self.toast_ui
+                    .show_volume_in_context(volume_info, &self.audio_controller);
+            }
+            Err(e) => {
+                log_event!("Error adjusting app volume: {}", e);
+                if is_remote_session() {
+                    self.toast_ui.show_message(
+                        tr(Text::RemoteSession).to_string(),
+                        Duration::from_millis(HIDE_DELAY_MS),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Button of a button message, if it is one that can be mapped
+fn event_button(message: u32, mouse_data: u32) -> Option<(MouseButton, bool)> {
+    let xbutton = || match (mouse_data >> 16) as u16 {
+        XBUTTON1 => Some(MouseButton::X1),
+        XBUTTON2 => Some(MouseButton::X2),
+        _ => None,
+    };
+
+    match message {
+        WM_MBUTTONDOWN => Some((MouseButton::Middle, true)),
+        WM_MBUTTONUP => Some((MouseButton::Middle, false)),
+        WM_XBUTTONDOWN => xbutton().map(|button| (button, true)),
+        WM_XBUTTONUP => xbutton().map(|button| (button, false)),
+        _ => None,
+    }
+}
+
+unsafe extern "system" fn mouse_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    // Mouse moves are by far the most frequent event, skip them early
+    if code >= 0 && wparam.0 as u32 != WM_MOUSEMOVE {
+        if let Some(ctx) = context().filter(|ctx| !ctx.suspended.load(Ordering::SeqCst)) {
+            let ms = *(lparam.0 as *const MSLLHOOKSTRUCT);
+            let message = wparam.0 as u32;
+
+            let config = ctx.config.read();
+            let mouse = config.mouse.clone();
+            let modifier_pressed = config.modifier.is_pressed();
+            let invert = config.input.invert_scroll;
+            let fallback_to_master = config.fallback_to_master || config.exclusive_fallback;
+            drop(config);
+
+            if message == WM_MOUSEWHEEL {
+                // The wheel keeps scrolling (e.g. Shift + wheel sideways) over
+                // apps with nothing to adjust
+                let has_target = || {
+                    fallback_to_master
+                        || ctx
+                            .audio_controller
+                            .focused_app_has_session()
+                            .unwrap_or(true)
+                };
+                if mouse.wheel && modifier_pressed && has_target() {
+                    let delta = (ms.mouseData >> 16) as u16 as i16;
+                    let up = (delta > 0) != invert;
+                    ctx.adjust(up, !up, false);
+                    return LRESULT(1);
+                }
+            } else if let Some((button, pressed)) = event_button(message, ms.mouseData) {
+                if !pressed {
+                    if ctx.swallowed.get() == Some(button) {
+                        ctx.swallowed.set(None);
+                        return LRESULT(1);
+                    }
+                } else if modifier_pressed || !mouse.require_modifier {
+                    let volume_up = mouse.volume_up == Some(button);
+                    let volume_down = mouse.volume_down == Some(button);
+                    let mute = mouse.mute == Some(button);
+
+                    if volume_up || volume_down || mute {
+                        ctx.adjust(volume_up, volume_down, mute);
+                        ctx.swallowed.set(Some(button));
+                        return LRESULT(1);
+                    }
+                }
+            }
+        }
+    }
+
+    CallNextHookEx(None, code, wparam, lparam)
+}
@@ -0,0 +1,53 @@
+//! Undocumented `IPolicyConfig` interface, the only way to change the
+//! default audio endpoint. Used by the Windows sound settings and by tools
+//! like SoundSwitch; stable since Windows 7.
+
+// COM method names follow the Windows SDK
+#![allow(non_snake_case)]
+
+use std::ffi::c_void;
+use windows::core::*;
+use windows::Win32::Media::Audio::ERole;
+
+/// CLSID of `CPolicyConfigClient`
+pub const POLICY_CONFIG_CLIENT: GUID = GUID::from_u128(0x870af99c_171d_4f9e_af0d_e63df40c2bc9);
+
+/// Only `SetDefaultEndpoint` is called; the other methods just keep the
+/// vtable layout intact.
+#[interface("f8679f50-850a-41cf-9c72-430f290290c8")]
+pub unsafe trait IPolicyConfig: IUnknown {
+    fn GetMixFormat(&self, device_id: PCWSTR, format: *mut *mut c_void) -> HRESULT;
+    fn GetDeviceFormat(&self, device_id: PCWSTR, default: i32, format: *mut *mut c_void)
+        -> HRESULT;
+    fn ResetDeviceFormat(&self, device_id: PCWSTR) -> HRESULT;
+    fn SetDeviceFormat(
+        &self,
+        device_id: PCWSTR,
+        endpoint: *mut c_void,
+        mix: *mut c_void,
+    ) -> HRESULT;
+    fn GetProcessingPeriod(
+        &self,
+        device_id: PCWSTR,
+        default: i32,
+        default_period: *mut i64,
+        min_period: *mut i64,
+    ) -> HRESULT;
+    fn SetProcessingPeriod(&self, device_id: PCWSTR, period: *mut i64) -> HRESULT;
+    fn GetShareMode(&self, device_id: PCWSTR, mode: *mut c_void) -> HRESULT;
+    fn SetShareMode(&self, device_id: PCWSTR, mode: *mut c_void) -> HRESULT;
+    fn GetPropertyValue(
+        &self,
+        device_id: PCWSTR,
+        key: *const c_void,
+        value: *mut c_void,
+    ) -> HRESULT;
+    fn SetPropertyValue(
+        &self,
+        device_id: PCWSTR,
+        key: *const c_void,
+        value: *mut c_void,
+    ) -> HRESULT;
+    pub fn SetDefaultEndpoint(&self, device_id: PCWSTR, role: ERole) -> HRESULT;
+    fn SetEndpointVisibility(&self, device_id: PCWSTR, visible: i32) -> HRESULT;
+}
@@ -0,0 +1,144 @@
+use parking_lot::RwLock;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+use windows::Win32::Foundation::*;
+use windows::Win32::UI::WindowsAndMessaging::*;
+
+use crate::cli;
+use volime::audio_control::AudioController;
+use volime::config::Config;
+use volime::system_tray::SystemTray;
+use volime::toast_ui::ToastUI;
+
+/// Outcome of one `--selftest` check, with a detail for the report
+struct Check {
+    name: &'static str,
+    result: std::result::Result<String, String>,
+}
+
+/// Run the `--selftest` checks and report them: on the console Volime was
+/// started from, or in a message box otherwise. Nothing stays installed.
+/// Returns the process exit code, 0 when every check passed.
+pub fn run(com_initialized: bool, config: Arc<RwLock<Config>>) -> i32 {
+    let mut checks = vec![Check {
+        name: "COM initialization",
+        result: if com_initialized {
+            Ok("ok".to_string())
+        } else {
+            Err("CoInitializeEx failed".to_string())
+        },
+    }];
+
+    let audio_controller = AudioController::new(config.clone()).map(Arc::new);
+    match &audio_controller {
+        Ok(audio_controller) => {
+            checks.push(Check {
+                name: "Default output device",
+                result: match audio_controller.list_render_devices() {
+                    Ok(devices) => devices
+                        .into_iter()
+                        .find(|(_, is_default)| *is_default)
+                        .map(|(name, _)| name)
+                        .ok_or_else(|| "no default output device".to_string()),
+                    Err(e) => Err(e.to_string()),
+                },
+            });
+            checks.push(Check {
+                name: "Audio sessions",
+                result: audio_controller
+                    .list_sessions()
+                    .map(|sessions| format!("{} found", sessions.len()))
+                    .map_err(|e| e.to_string()),
+            });
+        }
+        Err(e) => checks.push(Check {
+            name: "Audio device enumerator",
+            result: Err(e.to_string()),
+        }),
+    }
+
+    checks.push(Check {
+        name: "Keyboard hook",
+        result: check_keyboard_hook(),
+    });
+
+    checks.push(Check {
+        name: "Toast region",
+        result: check_toast_region(config.clone()),
+    });
+
+    if let Ok(audio_controller) = audio_controller {
+        let tray = SystemTray::new(
+            Arc::new(AtomicBool::new(true)),
+            Arc::new(AtomicBool::new(false)),
+            config,
+            audio_controller,
+        );
+        checks.push(Check {
+            name: "Tray icon",
+            result: match tray {
+                Ok(tray) => {
+                    drop(tray);
+                    Ok("created and removed".to_string())
+                }
+                Err(e) => Err(e.to_string()),
+            },
+        });
+    }
+
+    let passed = checks.iter().all(|check| check.result.is_ok());
+    let mut report = String::new();
+    for check in &checks {
+        let (status, detail) = match &check.result {
+            Ok(detail) => ("PASS", detail),
+            Err(detail) => ("FAIL", detail),
+        };
+        report.push_str(&format!("{} {}: {}\n", status, check.name, detail));
+    }
+    report.push_str(if passed {
+        "\nAll checks passed"
+    } else {
+        "\nSome checks failed"
+    });
+
+    cli::report("Volime self-test", &report, passed);
+
+    if passed {
+        0
+    } else {
+        1
+    }
+}
+
+/// Times `check_toast_region` shows and hides the toast
+const TOAST_CYCLES: u32 = 50;
+
+/// Show and hide a toast repeatedly, checking that it keeps its rounded
+/// region after every show
+fn check_toast_region(config: Arc<RwLock<Config>>) -> std::result::Result<String, String> {
+    let toast_ui = ToastUI::new(config).map_err(|e| e.to_string())?;
+
+    for cycle in 1..=TOAST_CYCLES {
+        toast_ui.show_message("Volime self-test".to_string(), Duration::from_secs(1));
+        if !toast_ui.has_region() {
+            return Err(format!("region lost after {} shows", cycle));
+        }
+        toast_ui.hide();
+    }
+    Ok(format!("kept over {} show/hide cycles", TOAST_CYCLES))
+}
+
+/// Install a pass-through low-level keyboard hook and remove it again
+fn check_keyboard_hook() -> std::result::Result<String, String> {
+    unsafe extern "system" fn pass_through(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        CallNextHookEx(None, code, wparam, lparam)
+    }
+
+    unsafe {
+        let hook = SetWindowsHookExW(WH_KEYBOARD_LL, Some(pass_through), None, 0)
+            .map_err(|e| e.to_string())?;
+        UnhookWindowsHookEx(hook).map_err(|e| e.to_string())?;
+    }
+    Ok("installed and removed".to_string())
+}
@@ -0,0 +1,290 @@
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::Media::Audio::*;
+use windows::Win32::System::Com::{CoTaskMemFree, CLSCTX_ALL};
+use windows::Win32::UI::WindowsAndMessaging::*;
+
+/// Posted to the notify window when a watched session's volume changes
+/// outside Volime (mixer, the app's own slider). `lparam` owns a boxed
+/// `VolumeChange`.
+pub const WM_SESSION_VOLUME_CHANGED: u32 = WM_APP + 1;
+
+pub struct VolumeChange {
+    pub app_name: String,
+    pub volume: f32,
+    pub is_muted: bool,
+}
+
+/// `IAudioSessionEvents` sink for one session. Callbacks arrive on COM
+/// worker threads, so it only posts messages and flips atomics.
+#[implement(IAudioSessionEvents)]
+struct SessionEvents {
+    app_name: String,
+    notify_hwnd: isize,
+    expired: Arc<AtomicBool>,
+}
+
+impl IAudioSessionEvents_Impl for SessionEvents_Impl {
+    fn OnDisplayNameChanged(&self, _name: &PCWSTR, _context: *const GUID) -> Result<()> {
+        Ok(())
+    }
+
+    fn OnIconPathChanged(&self, _path: &PCWSTR, _context: *const GUID) -> Result<()> {
+        Ok(())
+    }
+
+    fn OnSimpleVolumeChanged(
+        &self,
+        newvolume: f32,
+        newmute: BOOL,
+        _context: *const GUID,
+    ) -> Result<()> {
+        let change = Box::new(VolumeChange {
+            app_name: self.app_name.clone(),
+            volume: newvolume,
+            is_muted: newmute.as_bool(),
+        });
+        let change_ptr = Box::into_raw(change);
+
+        unsafe {
+            let posted = PostMessageW(
+                HWND(self.notify_hwnd as *mut _),
+                WM_SESSION_VOLUME_CHANGED,
+                WPARAM(0),
+                LPARAM(change_ptr as isize),
+            );
+            if posted.is_err() {
+                drop(Box::from_raw(change_ptr));
+            }
+        }
+        Ok(())
+    }
+
+    fn OnChannelVolumeChanged(
+        &self,
+        _count: u32,
+        _volumes: *const f32,
+        _changed: u32,
+        _context: *const GUID,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn OnGroupingParamChanged(&self, _param: *const GUID, _context: *const GUID) -> Result<()> {
+        Ok(())
+    }
+
+    fn OnStateChanged(&self, newstate: AudioSessionState) -> Result<()> {
+        if newstate == AudioSessionStateExpired {
+            self.expired.store(true, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    fn OnSessionDisconnected(&self, _reason: AudioSessionDisconnectReason) -> Result<()> {
+        self.expired.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+struct Registration {
+    session: IAudioSessionControl2,
+    events: IAudioSessionEvents,
+    expired: Arc<AtomicBool>,
+}
+
+/// Keeps event registrations for sessions Volime has adjusted, keyed by
+/// session instance identifier.
+pub struct SessionWatcher {
+    notify_hwnd: Mutex<Option<isize>>,
+    registrations: Mutex<HashMap<String, Registration>>,
+}
+
+impl SessionWatcher {
+    pub fn new() -> Self {
+        SessionWatcher {
+            notify_hwnd: Mutex::new(None),
+            registrations: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Window that receives `WM_SESSION_VOLUME_CHANGED`
+    pub fn set_notify_window(&self, hwnd: HWND) {
+        *self.notify_hwnd.lock() = Some(hwnd.0 as isize);
+    }
+
+    pub fn watch(&self, session: &IAudioSessionControl2, app_name: &str) {
+        let Some(notify_hwnd) = *self.notify_hwnd.lock() else {
+            return;
+        };
+
+        let mut registrations = self.registrations.lock();
+
+        // Expired sessions can't be unregistered from inside their own
+        // callback, so they are cleaned up here instead
+        registrations.retain(|_, registration| {
+            if registration.expired.load(Ordering::SeqCst) {
+                unsafe {
+                    let _ = registration
+                        .session
+                        .UnregisterAudioSessionNotification(&registration.events);
+                }
+                false
+            } else {
+                true
+            }
+        });
+
+        let Some(id) = session_instance_id(session) else {
+            return;
+        };
+        if registrations.contains_key(&id) {
+            return;
+        }
+
+        let expired = Arc::new(AtomicBool::new(false));
+        let events: IAudioSessionEvents = SessionEvents {
+            app_name: app_name.to_string(),
+            notify_hwnd,
+            expired: expired.clone(),
+        }
+        .into();
+
+        match unsafe { session.RegisterAudioSessionNotification(&events) } {
+            Ok(()) => {
+                registrations.insert(
+                    id,
+                    Registration {
+                        session: session.clone(),
+                        events,
+                        expired,
+                    },
+                );
+            }
+            Err(e) => log_event!("Error registering session events for '{}': {}", app_name, e),
+        }
+    }
+
+    /// Drop every registration; sessions are registered again as they are
+    /// adjusted
+    pub fn clear(&self) {
+        for (_, registration) in self.registrations.lock().drain() {
+            unsafe {
+                let _ = registration
+                    .session
+                    .UnregisterAudioSessionNotification(&registration.events);
+            }
+        }
+    }
+}
+
+impl Drop for SessionWatcher {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// `IAudioSessionNotification` sink for a device's session manager. Like
+/// `SessionEvents` it is called on COM worker threads, so it only queues the
+/// instance identifiers of new sessions for the main loop.
+#[implement(IAudioSessionNotification)]
+struct SessionCreated {
+    created: Arc<Mutex<Vec<(String, Instant)>>>,
+}
+
+impl IAudioSessionNotification_Impl for SessionCreated_Impl {
+    fn OnSessionCreated(&self, newsession: Option<&IAudioSessionControl>) -> Result<()> {
+        let id = newsession
+            .and_then(|session| session.cast::<IAudioSessionControl2>().ok())
+            .and_then(|session| session_instance_id(&session));
+        if let Some(id) = id {
+            self.created.lock().push((id, Instant::now()));
+        }
+        Ok(())
+    }
+}
+
+/// Notifications of the sessions created on one device from the moment it
+/// is registered, e.g. an app starting to play
+pub struct SessionCreationWatcher {
+    manager: IAudioSessionManager2,
+    notification: IAudioSessionNotification,
+    created: Arc<Mutex<Vec<(String, Instant)>>>,
+}
+
+impl SessionCreationWatcher {
+    pub fn register(device: &IMMDevice) -> Result<Self> {
+        unsafe {
+            let manager: IAudioSessionManager2 = device.Activate(CLSCTX_ALL, None)?;
+            // Windows only starts sending notifications once the existing
+            // sessions have been enumerated
+            manager.GetSessionEnumerator()?;
+
+            let created = Arc::new(Mutex::new(Vec::new()));
+            let notification: IAudioSessionNotification = SessionCreated {
+                created: created.clone(),
+            }
+            .into();
+            manager.RegisterSessionNotification(&notification)?;
+
+            Ok(SessionCreationWatcher {
+                manager,
+                notification,
+                created,
+            })
+        }
+    }
+
+    /// Take the sessions created at least `age` ago that still exist, with
+    /// their instance IDs
+    pub fn take_created(&self, age: Duration) -> Vec<(String, IAudioSessionControl2)> {
+        let due: Vec<String> = {
+            let mut created = self.created.lock();
+            let (due, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut *created)
+                .into_iter()
+                .partition(|(_, at)| at.elapsed() >= age);
+            *created = pending;
+            due.into_iter().map(|(id, _)| id).collect()
+        };
+        if due.is_empty() {
+            return Vec::new();
+        }
+
+        let Ok(sessions) = (unsafe { self.manager.GetSessionEnumerator() }) else {
+            return Vec::new();
+        };
+        let count = unsafe { sessions.GetCount() }.unwrap_or(0);
+        (0..count)
+            .filter_map(|i| unsafe { sessions.GetSession(i) }.ok())
+            .filter_map(|session| session.cast::<IAudioSessionControl2>().ok())
+            .filter_map(|session| {
+                let id = session_instance_id(&session).filter(|id| due.contains(id))?;
+                Some((id, session))
+            })
+            .collect()
+    }
+}
+
+impl Drop for SessionCreationWatcher {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self
+                .manager
+                .UnregisterSessionNotification(&self.notification);
+        }
+    }
+}
+
+fn session_instance_id(session: &IAudioSessionControl2) -> Option<String> {
+    unsafe {
+        let id = session.GetSessionInstanceIdentifier().ok()?;
+        let result = id.to_string().ok();
+        CoTaskMemFree(Some(id.0 as *const _));
+        result
+    }
+}
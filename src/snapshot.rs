@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::System::SystemInformation::GetLocalTime;
+use windows::Win32::UI::Controls::Dialogs::*;
+
+use crate::audio_control::AudioController;
+
+/// Volumes and mute states of the running audio sessions, saved as JSON
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Local time the snapshot was taken, "YYYY-MM-DD HH:MM:SS"
+    pub created: String,
+    pub sessions: Vec<SnapshotEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    /// Process name, e.g. "spotify.exe"
+    pub app_name: String,
+    pub volume: f32,
+    pub muted: bool,
+}
+
+impl Snapshot {
+    /// Capture the sessions currently listed by the controller
+    pub fn take(audio_controller: &AudioController) -> Result<Self> {
+        let sessions = audio_controller
+            .list_sessions()?
+            .into_iter()
+            .map(|session| SnapshotEntry {
+                app_name: session.app_name,
+                volume: session.volume,
+                muted: session.is_muted,
+            })
+            .collect();
+
+        Ok(Snapshot {
+            created: local_time("-", " ", ":"),
+            sessions,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, contents)
+    }
+
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        serde_json::from_str(&contents)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// Reapply the saved volumes to the matching running apps. Apps that
+    /// aren't running are skipped. Returns how many were restored.
+    pub fn restore(&self, audio_controller: &AudioController) -> usize {
+        let mut restored = 0;
+
+        for entry in &self.sessions {
+            match audio_controller.set_app_volume(&entry.app_name, entry.volume, entry.muted) {
+                Ok(volume_info) if volume_info.is_no_session => {
+                    log_event!("Snapshot: '{}' is not running, skipped", entry.app_name);
+                }
+                Ok(_) => restored += 1,
+                Err(e) => log_event!("Snapshot: error restoring '{}': {}", entry.app_name, e),
+            }
+        }
+
+        restored
+    }
+}
+
+/// Ask where to save a new snapshot, suggesting a timestamped file name
+pub fn choose_save_path(owner: HWND) -> Option<PathBuf> {
+    let default_name = format!("volime-snapshot-{}.json", local_time("", "-", ""));
+    file_dialog(owner, Some(&default_name))
+}
+
+/// Ask which snapshot file to restore
+pub fn choose_open_path(owner: HWND) -> Option<PathBuf> {
+    file_dialog(owner, None)
+}
+
+/// Common file dialog for JSON files: a save dialog when `default_name` is
+/// given, an open dialog otherwise
+fn file_dialog(owner: HWND, default_name: Option<&str>) -> Option<PathBuf> {
+    let mut buffer = [0u16; 260];
+    if let Some(name) = default_name {
+        let name_wide: Vec<u16> = name.encode_utf16().collect();
+        let copy_len = name_wide.len().min(buffer.len() - 1);
+        buffer[..copy_len].copy_from_slice(&name_wide[..copy_len]);
+    }
+
+    let mut ofn = OPENFILENAMEW {
+        lStructSize: std::mem::size_of::<OPENFILENAMEW>() as u32,
+        hwndOwner: owner,
+        lpstrFilter: w!("Volime snapshot (*.json)\0*.json\0All files (*.*)\0*.*\0"),
+        lpstrFile: PWSTR::from_raw(buffer.as_mut_ptr()),
+        nMaxFile: buffer.len() as u32,
+        lpstrDefExt: w!("json"),
+        ..Default::default()
+    };
+
+    let accepted = unsafe {
+        if default_name.is_some() {
+            ofn.Flags = OFN_OVERWRITEPROMPT | OFN_PATHMUSTEXIST;
+            GetSaveFileNameW(&mut ofn)
+        } else {
+            ofn.Flags = OFN_FILEMUSTEXIST | OFN_PATHMUSTEXIST;
+            GetOpenFileNameW(&mut ofn)
+        }
+    };
+    if !accepted.as_bool() {
+        return None;
+    }
+
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    Some(PathBuf::from(String::from_utf16_lossy(&buffer[..len])))
+}
+
+/// Current local time with the given separators between date parts, between
+/// date and time, and between time parts
+fn local_time(date_sep: &str, middle: &str, time_sep: &str) -> String {
+    let t = unsafe { GetLocalTime() };
+    format!(
+        "{:04}{d}{:02}{d}{:02}{m}{:02}{t}{:02}{t}{:02}",
+        t.wYear,
+        t.wMonth,
+        t.wDay,
+        t.wHour,
+        t.wMinute,
+        t.wSecond,
+        d = date_sep,
+        m = middle,
+        t = time_sep
+    )
+}
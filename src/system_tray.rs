@@ -1,52 +1,207 @@
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use windows::core::*;
 use windows::Win32::Foundation::*;
+use windows::Win32::Media::Audio::{AudioSessionStateActive, AudioSessionStateInactive};
 use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::System::Registry::*;
 use windows::Win32::UI::Input::KeyboardAndMouse::*;
 use windows::Win32::UI::Shell::*;
 use windows::Win32::UI::WindowsAndMessaging::*;
 
+use crate::audio_control::{AudioController, WM_MUTED_APPS_CHANGED, WM_VOLUME_CONTROL_FAILING};
+use crate::config::{Config, InputConfig};
+use crate::i18n::{tr, tr_with, Text};
+use crate::macros;
+use crate::manager_ui::ManagerUI;
+use crate::snapshot::{self, Snapshot};
+
 const WM_TRAYICON: u32 = WM_USER + 1;
+
+/// Posted to the main thread (not to a window) after the system resumes
+/// from sleep, so the message loop reinstalls the input hooks
+pub const WM_SYSTEM_RESUMED: u32 = WM_APP + 4;
 const IDM_EXIT: u32 = 1001;
 const IDM_MODIFIER_SHIFT: u32 = 1002;
 const IDM_MODIFIER_CTRL: u32 = 1003;
 const IDM_MODIFIER_ALT: u32 = 1004;
+const IDM_CAP_NONE: u32 = 1005;
+const IDM_MODIFIER_WIN: u32 = 1006;
+const IDM_CAP_FIRST: u32 = 1010;
+const IDM_INPUT_INVERT: u32 = 1020;
+const IDM_INPUT_ACCELERATION: u32 = 1021;
+const IDM_INPUT_DISCRETE: u32 = 1022;
+const IDM_INPUT_UNMUTE: u32 = 1023;
+const IDM_INPUT_PROPORTIONAL: u32 = 1024;
+const IDM_SUSPEND: u32 = 1030;
+const IDM_SNAPSHOT_SAVE: u32 = 1031;
+const IDM_SNAPSHOT_RESTORE: u32 = 1032;
+const IDM_MANAGE_APPS: u32 = 1033;
+const IDM_RESET: u32 = 1034;
+const IDM_FLOOR_NONE: u32 = 1035;
+const IDM_FLOOR_FIRST: u32 = 1040;
+/// First of the output device items (one per active device)
+const IDM_DEVICE_FIRST: u32 = 1100;
+const MAX_DEVICE_ITEMS: u32 = 64;
+/// First of the per-app mute items (one per audio session)
+const IDM_APP_MUTE_FIRST: u32 = 1200;
+const MAX_APP_ITEMS: u32 = 64;
+/// First of the macro items (one per configured macro)
+const IDM_MACRO_FIRST: u32 = 1300;
+const MAX_MACRO_ITEMS: u32 = 64;
+
+/// Resource IDs of the tray icon variants (see volime.rc). The themed ones
+/// are optional; the default icon is used when they aren't embedded.
+const ICON_ID_DEFAULT: usize = 1;
+const ICON_ID_LIGHT_THEME: usize = 2;
+const ICON_ID_DARK_THEME: usize = 3;
+/// Shown while an app muted through Volime is still muted
+const ICON_ID_MUTED: usize = 4;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+/// Cap presets (in %) offered for the last adjusted app
+const CAP_PRESETS: [u32; 5] = [90, 80, 70, 60, 50];
+/// Floor presets (in %) offered for the last adjusted app
+const FLOOR_PRESETS: [u32; 4] = [30, 20, 10, 5];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub enum ModifierKey {
     Shift,
     Control,
     Alt,
+    Win,
 }
 
 impl ModifierKey {
-    pub fn to_vk(&self) -> i32 {
+    /// Every supported modifier
+    pub const ALL: [ModifierKey; 4] = [
+        ModifierKey::Shift,
+        ModifierKey::Control,
+        ModifierKey::Alt,
+        ModifierKey::Win,
+    ];
+
+    /// Whether a low-level hook virtual key code belongs to this modifier
+    /// (the hook reports left/right specific codes)
+    pub fn matches_vk(self, vk_code: u32) -> bool {
+        self.vk_codes().iter().any(|vk| vk.0 as u32 == vk_code)
+    }
+
+    /// Whether either side of this modifier is currently held down
+    pub fn is_pressed(self) -> bool {
+        self.vk_codes()
+            .iter()
+            .any(|vk| unsafe { (GetAsyncKeyState(vk.0 as i32) as u16 & 0x8000) != 0 })
+    }
+
+    fn vk_codes(self) -> &'static [VIRTUAL_KEY] {
         match self {
-            ModifierKey::Shift => VK_SHIFT.0 as i32,
-            ModifierKey::Control => VK_CONTROL.0 as i32,
-            ModifierKey::Alt => VK_MENU.0 as i32,
+            ModifierKey::Shift => &[VK_SHIFT, VK_LSHIFT, VK_RSHIFT],
+            ModifierKey::Control => &[VK_CONTROL, VK_LCONTROL, VK_RCONTROL],
+            ModifierKey::Alt => &[VK_MENU, VK_LMENU, VK_RMENU],
+            ModifierKey::Win => &[VK_LWIN, VK_RWIN],
         }
     }
+}
 
-    pub fn to_string(&self) -> &str {
-        match self {
+/// Tray menu item of a modifier
+fn modifier_menu_id(modifier: ModifierKey) -> u32 {
+    match modifier {
+        ModifierKey::Shift => IDM_MODIFIER_SHIFT,
+        ModifierKey::Control => IDM_MODIFIER_CTRL,
+        ModifierKey::Alt => IDM_MODIFIER_ALT,
+        ModifierKey::Win => IDM_MODIFIER_WIN,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseModifierError(String);
+
+impl std::fmt::Display for ParseModifierError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "unknown modifier '{}' (expected shift, control/ctrl, alt or win)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseModifierError {}
+
+impl std::str::FromStr for ModifierKey {
+    type Err = ParseModifierError;
+
+    /// Case-insensitive; accepts every `Display` label plus "ctrl"
+    fn from_str(name: &str) -> std::result::Result<Self, Self::Err> {
+        if name.eq_ignore_ascii_case("ctrl") {
+            return Ok(ModifierKey::Control);
+        }
+
+        Self::ALL
+            .into_iter()
+            .find(|modifier| modifier.to_string().eq_ignore_ascii_case(name))
+            .ok_or_else(|| ParseModifierError(name.to_string()))
+    }
+}
+
+impl std::fmt::Display for ModifierKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
             ModifierKey::Shift => "Shift",
             ModifierKey::Control => "Control",
             ModifierKey::Alt => "Alt",
-        }
+            ModifierKey::Win => "Win",
+        })
+    }
+}
+
+impl TryFrom<String> for ModifierKey {
+    type Error = ParseModifierError;
+
+    fn try_from(name: String) -> std::result::Result<Self, Self::Error> {
+        name.parse()
+    }
+}
+
+impl From<ModifierKey> for String {
+    fn from(modifier: ModifierKey) -> Self {
+        modifier.to_string().to_lowercase()
     }
 }
 
 pub struct SystemTray {
     hwnd: HWND,
-    _modifier_key: Arc<RwLock<ModifierKey>>,
-    _running: Arc<AtomicBool>,
+    context: Rc<TrayContext>,
+}
+
+/// Shared state reachable from the tray window procedure. Only touched on
+/// the thread that owns the window, so it is reference counted with `Rc`.
+struct TrayContext {
+    running: Arc<AtomicBool>,
+    /// The keyboard hook passes every key through while set
+    suspended: Arc<AtomicBool>,
+    config: Arc<RwLock<Config>>,
+    audio_controller: Arc<AudioController>,
+    /// Output device names in the order of the last menu shown
+    device_menu: Mutex<Vec<String>>,
+    /// Apps and whether they were muted, in the order of the last menu shown
+    app_menu: Mutex<Vec<(String, bool)>>,
+    /// Macro names in the order of the last menu shown
+    macro_menu: Mutex<Vec<String>>,
+    manager_ui: ManagerUI,
 }
 
 impl SystemTray {
-    pub fn new(modifier_key: Arc<RwLock<ModifierKey>>, running: Arc<AtomicBool>) -> Result<Self> {
+    pub fn new(
+        running: Arc<AtomicBool>,
+        suspended: Arc<AtomicBool>,
+        config: Arc<RwLock<Config>>,
+        audio_controller: Arc<AudioController>,
+    ) -> Result<Self> {
         unsafe {
             let instance = GetModuleHandleW(None)?;
             let class_name = w!("VolimeTrayClass");
@@ -78,12 +233,22 @@ impl SystemTray {
                 None,
             )?;
 
-            // Guardar punteros usando propiedades de ventana
-            let modifier_ptr = Arc::into_raw(modifier_key.clone()) as *mut std::ffi::c_void;
-            SetPropW(hwnd, w!("modifier_key"), HANDLE(modifier_ptr))?;
+            let manager_ui = ManagerUI::new(config.clone())?;
 
-            let running_ptr = Arc::into_raw(running.clone()) as *mut std::ffi::c_void;
-            SetPropW(hwnd, w!("running"), HANDLE(running_ptr))?;
+            // Guardar el contexto en el GWLP_USERDATA
+            let context = Rc::new(TrayContext {
+                running,
+                suspended,
+                config,
+                audio_controller,
+                device_menu: Mutex::new(Vec::new()),
+                app_menu: Mutex::new(Vec::new()),
+                macro_menu: Mutex::new(Vec::new()),
+                manager_ui,
+            });
+            let context_ptr = Rc::into_raw(context.clone()) as isize;
+            SetWindowLongPtrW(hwnd, GWLP_USERDATA, context_ptr);
+            context.audio_controller.set_notify_window(hwnd);
 
             let mut nid = NOTIFYICONDATAW {
                 cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
@@ -91,47 +256,93 @@ impl SystemTray {
                 uID: 1,
                 uFlags: NIF_ICON | NIF_MESSAGE | NIF_TIP,
                 uCallbackMessage: WM_TRAYICON,
-                hIcon: Self::load_icon()?,
+                hIcon: Self::load_icon(false)?,
                 ..Default::default()
             };
 
-            let tip = w!("Volime - Volume Control");
-            let tip_bytes = tip.as_wide();
-            let copy_len = tip_bytes.len().min(nid.szTip.len() - 1);
-            nid.szTip[..copy_len].copy_from_slice(&tip_bytes[..copy_len]);
+            set_tip(&mut nid, tr(Text::Tooltip));
 
             let result = Shell_NotifyIconW(NIM_ADD, &nid);
             if !result.as_bool() {
                 return Err(Error::from_win32());
             }
 
-            println!("System tray icon created");
+            log_event!("System tray icon created");
 
-            Ok(SystemTray {
-                hwnd,
-                _modifier_key: modifier_key,
-                _running: running,
-            })
+            Ok(SystemTray { hwnd, context })
         }
     }
 
-    fn load_icon() -> Result<HICON> {
-        unsafe {
-            // Try to load embedded icon from resources (ID 1)
-            let instance = GetModuleHandleW(None)?;
-            let hicon = LoadIconW(instance, PCWSTR::from_raw(1 as *const u16));
-
-            if let Ok(icon) = hicon {
-                if !icon.is_invalid() {
-                    println!("Loaded embedded icon from resources");
-                    return Ok(icon);
-                }
+    /// Tray icon for the current taskbar theme, falling back to the default
+    /// embedded icon and then to the system one. `muted` picks the muted
+    /// variant when it is embedded.
+    fn load_icon(muted: bool) -> Result<HICON> {
+        if muted {
+            if let Some(icon) = Self::load_resource_icon(ICON_ID_MUTED) {
+                return Ok(icon);
             }
+        }
+
+        let themed_id = if system_uses_light_theme() {
+            ICON_ID_LIGHT_THEME
+        } else {
+            ICON_ID_DARK_THEME
+        };
+        if let Some(icon) = Self::load_resource_icon(themed_id) {
+            return Ok(icon);
+        }
 
-            // Fallback to default application icon
-            println!("Using default system icon");
-            LoadIconW(None, IDI_APPLICATION)
+        // Try to load embedded icon from resources (ID 1)
+        if let Some(icon) = Self::load_resource_icon(ICON_ID_DEFAULT) {
+            log_event!("Loaded embedded icon from resources");
+            return Ok(icon);
         }
+
+        // Fallback to default application icon
+        log_event!("Using default system icon");
+        unsafe { LoadIconW(None, IDI_APPLICATION) }
+    }
+
+    fn load_resource_icon(id: usize) -> Option<HICON> {
+        unsafe {
+            let instance = GetModuleHandleW(None).ok()?;
+            // MAKEINTRESOURCE: integer IDs are passed in place of the name
+            let icon = LoadIconW(instance, PCWSTR::from_raw(id as *const u16)).ok()?;
+            (!icon.is_invalid()).then_some(icon)
+        }
+    }
+
+    /// Update the icon and tooltip to the current state: suspended, apps
+    /// left muted, or the taskbar theme after it changed
+    unsafe fn refresh_status(hwnd: HWND, context: &TrayContext) {
+        let muted_apps = context.audio_controller.muted_apps();
+        let Ok(icon) = Self::load_icon(!muted_apps.is_empty()) else {
+            return;
+        };
+
+        let tip = if context.suspended.load(Ordering::SeqCst) {
+            tr(Text::TooltipSuspended).to_string()
+        } else if !muted_apps.is_empty() {
+            let config = context.config.read();
+            let names: Vec<String> = muted_apps
+                .iter()
+                .map(|app| config.display_name(app))
+                .collect();
+            format!("{} {}", tr(Text::TooltipMuted), names.join(", "))
+        } else {
+            tr(Text::Tooltip).to_string()
+        };
+
+        let mut nid = NOTIFYICONDATAW {
+            cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+            hWnd: hwnd,
+            uID: 1,
+            uFlags: NIF_ICON | NIF_TIP,
+            hIcon: icon,
+            ..Default::default()
+        };
+        set_tip(&mut nid, &tip);
+        let _ = Shell_NotifyIconW(NIM_MODIFY, &nid);
     }
 
     unsafe extern "system" fn wnd_proc(
@@ -143,37 +354,20 @@ impl SystemTray {
         match msg {
             WM_TRAYICON => {
                 let event = lparam.0 as u32;
-                println!(
-                    "[DEBUG TRAY] WM_TRAYICON message received, event: 0x{:X}",
-                    event
-                );
 
                 if event == WM_RBUTTONUP || event == WM_RBUTTONDOWN {
-                    println!("[DEBUG TRAY] Right click detected!");
+                    log_event!("[DEBUG TRAY] Right click detected!");
 
-                    let modifier_handle = GetPropW(hwnd, w!("modifier_key"));
-                    let running_handle = GetPropW(hwnd, w!("running"));
+                    let context_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA);
+                    if context_ptr != 0 {
+                        let context = Rc::from_raw(context_ptr as *const TrayContext);
 
-                    let modifier_ptr = modifier_handle.0 as isize;
-                    let running_ptr = running_handle.0 as isize;
+                        log_event!("[DEBUG TRAY] Showing context menu...");
+                        Self::show_context_menu(hwnd, &context);
 
-                    println!(
-                        "[DEBUG TRAY] modifier_ptr: {}, running_ptr: {}",
-                        modifier_ptr, running_ptr
-                    );
-
-                    if modifier_ptr != 0 && running_ptr != 0 {
-                        let modifier_key =
-                            Arc::from_raw(modifier_ptr as *const RwLock<ModifierKey>);
-                        let running = Arc::from_raw(running_ptr as *const AtomicBool);
-
-                        println!("[DEBUG TRAY] Showing context menu...");
-                        Self::show_context_menu(hwnd, &modifier_key, &running);
-
-                        std::mem::forget(modifier_key);
-                        std::mem::forget(running);
+                        std::mem::forget(context);
                     } else {
-                        println!("[DEBUG TRAY] ERROR: Invalid pointers!");
+                        log_event!("[DEBUG TRAY] ERROR: Invalid context pointer!");
                     }
                 }
                 LRESULT(0)
@@ -181,119 +375,847 @@ impl SystemTray {
             WM_COMMAND => {
                 let command = (wparam.0 & 0xFFFF) as u32;
 
-                let modifier_handle = GetPropW(hwnd, w!("modifier_key"));
-                let running_handle = GetPropW(hwnd, w!("running"));
-
-                let modifier_ptr = modifier_handle.0 as isize;
-                let running_ptr = running_handle.0 as isize;
-
-                if modifier_ptr != 0 && running_ptr != 0 {
-                    let modifier_key = Arc::from_raw(modifier_ptr as *const RwLock<ModifierKey>);
-                    let running = Arc::from_raw(running_ptr as *const AtomicBool);
+                let context_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA);
+                if context_ptr != 0 {
+                    let context = Rc::from_raw(context_ptr as *const TrayContext);
 
                     match command {
                         IDM_EXIT => {
-                            println!("Exiting from tray menu...");
-                            running.store(false, Ordering::SeqCst);
+                            log_event!("Exiting from tray menu...");
+                            context.running.store(false, Ordering::SeqCst);
                             PostQuitMessage(0);
                         }
-                        IDM_MODIFIER_SHIFT => {
-                            *modifier_key.write() = ModifierKey::Shift;
-                            println!("Modifier key changed to: Shift");
+                        IDM_MODIFIER_SHIFT | IDM_MODIFIER_CTRL | IDM_MODIFIER_ALT
+                        | IDM_MODIFIER_WIN => {
+                            if let Some(modifier) = ModifierKey::ALL
+                                .into_iter()
+                                .find(|modifier| modifier_menu_id(*modifier) == command)
+                            {
+                                Self::set_modifier(&context, modifier);
+                            }
+                        }
+                        IDM_SUSPEND => Self::toggle_suspend(hwnd, &context),
+                        IDM_SNAPSHOT_SAVE => Self::save_snapshot(hwnd, &context),
+                        IDM_SNAPSHOT_RESTORE => Self::restore_snapshot(hwnd, &context),
+                        IDM_MANAGE_APPS => context.manager_ui.show(),
+                        IDM_RESET => Self::reset_to_defaults(hwnd, &context),
+                        IDM_INPUT_INVERT => {
+                            Self::toggle_input(&context, "invert_scroll", |input| {
+                                &mut input.invert_scroll
+                            });
+                        }
+                        IDM_INPUT_ACCELERATION => {
+                            Self::toggle_input(&context, "acceleration", |input| {
+                                &mut input.acceleration
+                            });
+                        }
+                        IDM_INPUT_DISCRETE => {
+                            Self::toggle_input(&context, "discrete_presses", |input| {
+                                &mut input.discrete_presses
+                            });
+                        }
+                        IDM_INPUT_UNMUTE => {
+                            Self::toggle_input(&context, "unmute_on_adjust", |input| {
+                                &mut input.unmute_on_adjust
+                            });
+                        }
+                        IDM_INPUT_PROPORTIONAL => {
+                            Self::toggle_input(&context, "proportional_step", |input| {
+                                &mut input.proportional_step
+                            });
+                        }
+                        _ if (IDM_DEVICE_FIRST..IDM_DEVICE_FIRST + MAX_DEVICE_ITEMS)
+                            .contains(&command) =>
+                        {
+                            Self::set_default_device(
+                                &context,
+                                (command - IDM_DEVICE_FIRST) as usize,
+                            );
+                        }
+                        _ if (IDM_APP_MUTE_FIRST..IDM_APP_MUTE_FIRST + MAX_APP_ITEMS)
+                            .contains(&command) =>
+                        {
+                            Self::toggle_app_mute(
+                                hwnd,
+                                &context,
+                                (command - IDM_APP_MUTE_FIRST) as usize,
+                            );
+                        }
+                        _ if (IDM_MACRO_FIRST..IDM_MACRO_FIRST + MAX_MACRO_ITEMS)
+                            .contains(&command) =>
+                        {
+                            Self::run_macro(hwnd, &context, (command - IDM_MACRO_FIRST) as usize);
                         }
-                        IDM_MODIFIER_CTRL => {
-                            *modifier_key.write() = ModifierKey::Control;
-                            println!("Modifier key changed to: Control");
+                        IDM_CAP_NONE => Self::set_volume_cap(&context, None),
+                        _ if (IDM_CAP_FIRST..IDM_CAP_FIRST + CAP_PRESETS.len() as u32)
+                            .contains(&command) =>
+                        {
+                            let percent = CAP_PRESETS[(command - IDM_CAP_FIRST) as usize];
+                            Self::set_volume_cap(&context, Some(percent as f32 / 100.0));
                         }
-                        IDM_MODIFIER_ALT => {
-                            *modifier_key.write() = ModifierKey::Alt;
-                            println!("Modifier key changed to: Alt");
+                        IDM_FLOOR_NONE => Self::set_volume_floor(&context, None),
+                        _ if (IDM_FLOOR_FIRST..IDM_FLOOR_FIRST + FLOOR_PRESETS.len() as u32)
+                            .contains(&command) =>
+                        {
+                            let percent = FLOOR_PRESETS[(command - IDM_FLOOR_FIRST) as usize];
+                            Self::set_volume_floor(&context, Some(percent as f32 / 100.0));
                         }
                         _ => {}
                     }
 
-                    std::mem::forget(modifier_key);
-                    std::mem::forget(running);
+                    std::mem::forget(context);
                 }
                 LRESULT(0)
             }
+            WM_SETTINGCHANGE => {
+                // Cambio de tema claro/oscuro
+                let setting = PCWSTR::from_raw(lparam.0 as *const u16);
+                if !setting.is_null() && setting.to_string().is_ok_and(|s| s == "ImmersiveColorSet")
+                {
+                    log_event!("Theme changed, updating tray icon");
+                    Self::with_context(hwnd, |context| Self::refresh_status(hwnd, context));
+                }
+                DefWindowProcW(hwnd, msg, wparam, lparam)
+            }
+            WM_MUTED_APPS_CHANGED => {
+                Self::with_context(hwnd, |context| Self::refresh_status(hwnd, context));
+                LRESULT(0)
+            }
+            WM_VOLUME_CONTROL_FAILING => {
+                Self::show_balloon(hwnd, tr(Text::FailureTitle), tr(Text::FailureText));
+                LRESULT(0)
+            }
+            WM_POWERBROADCAST => {
+                if wparam.0 as u32 == PBT_APMRESUMESUSPEND {
+                    log_event!("System resumed from sleep, refreshing audio devices and hooks");
+                    Self::with_context(hwnd, |context| {
+                        match context.audio_controller.reset_after_resume() {
+                            Ok(device) => log_event!(
+                                "Default output device after resume: {}",
+                                device.as_deref().unwrap_or("unknown")
+                            ),
+                            Err(e) => log_event!("Error refreshing audio devices: {}", e),
+                        }
+                    });
+                    let _ = PostMessageW(None, WM_SYSTEM_RESUMED, WPARAM(0), LPARAM(0));
+                }
+                LRESULT(1)
+            }
             _ => DefWindowProcW(hwnd, msg, wparam, lparam),
         }
     }
 
-    unsafe fn show_context_menu(
-        hwnd: HWND,
-        modifier_key: &Arc<RwLock<ModifierKey>>,
-        _running: &Arc<AtomicBool>,
-    ) {
+    /// Show a notification from the tray icon
+    unsafe fn show_balloon(hwnd: HWND, title: &str, text: &str) {
+        let mut nid = NOTIFYICONDATAW {
+            cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+            hWnd: hwnd,
+            uID: 1,
+            uFlags: NIF_INFO,
+            dwInfoFlags: NIIF_WARNING,
+            ..Default::default()
+        };
+        copy_wide(&mut nid.szInfoTitle, title);
+        copy_wide(&mut nid.szInfo, text);
+        let _ = Shell_NotifyIconW(NIM_MODIFY, &nid);
+    }
+
+    /// Run `f` with the context stored in the window, if it is set
+    unsafe fn with_context(hwnd: HWND, f: impl FnOnce(&TrayContext)) {
+        let context_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA);
+        if context_ptr != 0 {
+            let context = Rc::from_raw(context_ptr as *const TrayContext);
+            f(&context);
+            std::mem::forget(context);
+        }
+    }
+
+    fn set_modifier(context: &TrayContext, modifier: ModifierKey) {
+        let mut config = context.config.write();
+        config.modifier = modifier;
+        log_event!("Modifier key changed to: {}", modifier);
+
+        if let Err(e) = config.save() {
+            log_event!("Error saving config: {}", e);
+        }
+    }
+
+    unsafe fn toggle_suspend(hwnd: HWND, context: &TrayContext) {
+        let suspended = !context.suspended.load(Ordering::SeqCst);
+        context.suspended.store(suspended, Ordering::SeqCst);
+        log_event!("Volime {}", if suspended { "suspended" } else { "resumed" });
+
+        Self::refresh_status(hwnd, context);
+    }
+
+    fn set_default_device(context: &TrayContext, index: usize) {
+        let Some(name) = context.device_menu.lock().get(index).cloned() else {
+            return;
+        };
+
+        // The device may have been unplugged since the menu was shown
+        match context.audio_controller.set_default_render_device(&name) {
+            Ok(true) => log_event!("Default output device set to {}", name),
+            Ok(false) => log_event!("Output device {} is no longer available", name),
+            Err(e) => log_event!("Error setting default output device {}: {}", name, e),
+        }
+    }
+
+    unsafe fn toggle_app_mute(hwnd: HWND, context: &TrayContext, index: usize) {
+        let Some((name, muted)) = context.app_menu.lock().get(index).cloned() else {
+            return;
+        };
+
+        // The app may have closed since the menu was shown
+        match context.audio_controller.set_app_mute_by_name(&name, !muted) {
+            Ok(true) => {
+                log_event!(
+                    "'{}' {} from the tray",
+                    name,
+                    if muted { "unmuted" } else { "muted" }
+                );
+                Self::refresh_status(hwnd, context);
+            }
+            Ok(false) => log_event!("'{}' no longer has an audio session", name),
+            Err(e) => log_event!("Error muting '{}': {}", name, e),
+        }
+    }
+
+    /// Run a macro picked from the menu. There is no toast here, so the
+    /// outcome only goes to the log.
+    unsafe fn run_macro(hwnd: HWND, context: &TrayContext, index: usize) {
+        let Some(name) = context.macro_menu.lock().get(index).cloned() else {
+            return;
+        };
+
+        // The config may have been reloaded since the menu was shown
+        let macro_config = context
+            .config
+            .read()
+            .macros
+            .iter()
+            .find(|macro_config| macro_config.name == name)
+            .cloned();
+        match macro_config {
+            Some(macro_config) => {
+                macros::run(&context.audio_controller, &macro_config);
+                Self::refresh_status(hwnd, context);
+            }
+            None => log_event!("Macro '{}' no longer exists", name),
+        }
+    }
+
+    fn save_snapshot(hwnd: HWND, context: &TrayContext) {
+        let snapshot = match Snapshot::take(&context.audio_controller) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                log_event!("Error taking volume snapshot: {}", e);
+                return;
+            }
+        };
+        let Some(path) = snapshot::choose_save_path(hwnd) else {
+            return;
+        };
+
+        match snapshot.save(&path) {
+            Ok(()) => log_event!(
+                "Saved {} volumes to {}",
+                snapshot.sessions.len(),
+                path.display()
+            ),
+            Err(e) => log_event!("Error saving snapshot {}: {}", path.display(), e),
+        }
+    }
+
+    fn restore_snapshot(hwnd: HWND, context: &TrayContext) {
+        let Some(path) = snapshot::choose_open_path(hwnd) else {
+            return;
+        };
+
+        match Snapshot::load(&path) {
+            Ok(snapshot) => {
+                let restored = snapshot.restore(&context.audio_controller);
+                log_event!(
+                    "Restored {} of {} volumes from {}",
+                    restored,
+                    snapshot.sessions.len(),
+                    path.display()
+                );
+            }
+            Err(e) => log_event!("Error reading snapshot {}: {}", path.display(), e),
+        }
+    }
+
+    /// Ask for confirmation, then restore the default config, rewrite the
+    /// config file and forget the in-memory per-app state
+    unsafe fn reset_to_defaults(hwnd: HWND, context: &TrayContext) {
+        let answer = MessageBoxW(
+            hwnd,
+            &HSTRING::from(tr(Text::ResetConfirm)),
+            w!("Volime"),
+            MB_YESNO | MB_ICONQUESTION | MB_DEFBUTTON2,
+        );
+        if answer != IDYES {
+            return;
+        }
+
+        let mut config = context.config.write();
+        *config = Config::default();
+        if let Err(e) = config.save() {
+            log_event!("Error saving config: {}", e);
+        }
+        drop(config);
+
+        context.audio_controller.reset_app_state();
+        log_event!("Settings and per-app data reset to defaults");
+        Self::refresh_status(hwnd, context);
+    }
+
+    fn toggle_input(context: &TrayContext, name: &str, option: fn(&mut InputConfig) -> &mut bool) {
+        let mut config = context.config.write();
+        let value = option(&mut config.input);
+        *value = !*value;
+        log_event!("Input option {} set to {}", name, value);
+
+        if let Err(e) = config.save() {
+            log_event!("Error saving config: {}", e);
+        }
+    }
+
+    fn set_volume_cap(context: &TrayContext, max: Option<f32>) {
+        let Some(app_name) = context.audio_controller.last_app_name() else {
+            return;
+        };
+
+        let mut config = context.config.write();
+        config.set_max_volume(&app_name, max);
+        match max {
+            Some(max) => log_event!(
+                "Volume cap for '{}' set to {}%",
+                app_name,
+                (max * 100.0).round() as i32
+            ),
+            None => log_event!("Volume cap for '{}' removed", app_name),
+        }
+
+        if let Err(e) = config.save() {
+            log_event!("Error saving config: {}", e);
+        }
+    }
+
+    fn set_volume_floor(context: &TrayContext, min: Option<f32>) {
+        let Some(app_name) = context.audio_controller.last_app_name() else {
+            return;
+        };
+
+        let mut config = context.config.write();
+        config.set_min_volume(&app_name, min);
+        match min {
+            Some(min) => log_event!(
+                "Volume floor for '{}' set to {}%",
+                app_name,
+                (min * 100.0).round() as i32
+            ),
+            None => log_event!("Volume floor for '{}' removed", app_name),
+        }
+
+        if let Err(e) = config.save() {
+            log_event!("Error saving config: {}", e);
+        }
+    }
+
+    unsafe fn show_context_menu(hwnd: HWND, context: &TrayContext) {
         let menu = CreatePopupMenu().unwrap();
-        let current_modifier = *modifier_key.read();
+        let current_modifier = context.config.read().modifier;
+
+        // Read-only line with the device being controlled, read on every open
+        let device_label = match context.audio_controller.default_device_name() {
+            Some(name) => tr_with(Text::CurrentDevice, &name),
+            None => tr(Text::NoDefaultDevice).to_string(),
+        };
+        AppendMenuW(menu, MF_STRING | MF_GRAYED, 0, &HSTRING::from(device_label)).ok();
+        AppendMenuW(menu, MF_SEPARATOR, 0, None).ok();
 
         // Submenu for modifier key
         let modifier_menu = CreatePopupMenu().unwrap();
 
-        let shift_flags = if current_modifier == ModifierKey::Shift {
-            MF_STRING | MF_CHECKED
+        for modifier in ModifierKey::ALL {
+            let flags = if modifier == current_modifier {
+                MF_STRING | MF_CHECKED
+            } else {
+                MF_STRING
+            };
+            AppendMenuW(
+                modifier_menu,
+                flags,
+                modifier_menu_id(modifier) as usize,
+                &HSTRING::from(modifier.to_string()),
+            )
+            .ok();
+        }
+
+        // Add submenu to main menu
+        AppendMenuW(
+            menu,
+            MF_STRING | MF_POPUP,
+            modifier_menu.0 as usize,
+            &HSTRING::from(tr(Text::ModifierKey)),
+        )
+        .ok();
+
+        // Submenu for the output device, listed fresh on every open
+        let devices = context
+            .audio_controller
+            .list_render_devices()
+            .unwrap_or_else(|e| {
+                log_event!("Error listing output devices: {}", e);
+                Vec::new()
+            });
+        let device_menu = CreatePopupMenu().unwrap();
+        for (i, (name, is_default)) in devices.iter().take(MAX_DEVICE_ITEMS as usize).enumerate() {
+            let flags = if *is_default {
+                MF_STRING | MF_CHECKED
+            } else {
+                MF_STRING
+            };
+            AppendMenuW(
+                device_menu,
+                flags,
+                IDM_DEVICE_FIRST as usize + i,
+                &HSTRING::from(name.as_str()),
+            )
+            .ok();
+        }
+        let device_flags = if devices.is_empty() {
+            MF_STRING | MF_POPUP | MF_GRAYED
         } else {
-            MF_STRING
+            MF_STRING | MF_POPUP
+        };
+        AppendMenuW(
+            menu,
+            device_flags,
+            device_menu.0 as usize,
+            &HSTRING::from(tr(Text::OutputDevice)),
+        )
+        .ok();
+        *context.device_menu.lock() = devices.into_iter().map(|(name, _)| name).collect();
+
+        // Submenu to mute apps without focusing them, checked when muted
+        let sessions = context
+            .audio_controller
+            .list_sessions()
+            .unwrap_or_else(|e| {
+                log_event!("Error listing audio sessions: {}", e);
+                Vec::new()
+            });
+        let app_menu = CreatePopupMenu().unwrap();
+        let config = context.config.read();
+        for (i, session) in sessions.iter().take(MAX_APP_ITEMS as usize).enumerate() {
+            let flags = if session.is_muted {
+                MF_STRING | MF_CHECKED
+            } else {
+                MF_STRING
+            };
+            AppendMenuW(
+                app_menu,
+                flags,
+                IDM_APP_MUTE_FIRST as usize + i,
+                &HSTRING::from(config.display_name(&session.app_name)),
+            )
+            .ok();
+        }
+        drop(config);
+        let app_flags = if sessions.is_empty() {
+            MF_STRING | MF_POPUP | MF_GRAYED
+        } else {
+            MF_STRING | MF_POPUP
         };
         AppendMenuW(
-            modifier_menu,
-            shift_flags,
-            IDM_MODIFIER_SHIFT as usize,
-            w!("Shift"),
+            menu,
+            app_flags,
+            app_menu.0 as usize,
+            &HSTRING::from(tr(Text::MuteApp)),
+        )
+        .ok();
+        *context.app_menu.lock() = sessions
+            .into_iter()
+            .take(MAX_APP_ITEMS as usize)
+            .map(|session| (session.app_name, session.is_muted))
+            .collect();
+
+        // Read-only list of every session, flagging leftovers of closed apps
+        let troubleshoot_menu = CreatePopupMenu().unwrap();
+        let diagnostics = context
+            .audio_controller
+            .session_diagnostics()
+            .unwrap_or_else(|e| {
+                log_event!("Error listing audio sessions: {}", e);
+                Vec::new()
+            });
+        for session in diagnostics.iter().take(MAX_APP_ITEMS as usize) {
+            let state = if session.state == AudioSessionStateActive {
+                tr(Text::SessionActive)
+            } else if session.state == AudioSessionStateInactive {
+                tr(Text::SessionInactive)
+            } else {
+                tr(Text::SessionExpired)
+            };
+            let mut label = format!("{} (PID {}) - {}", session.name, session.process_id, state);
+            if session.is_system_sounds {
+                label.push_str(&format!(", {}", tr(Text::SystemSounds)));
+            }
+            if session.process_gone {
+                label.push_str(&format!(", {}", tr(Text::ProcessGone)));
+            }
+            AppendMenuW(
+                troubleshoot_menu,
+                MF_STRING | MF_GRAYED,
+                0,
+                &HSTRING::from(label),
+            )
+            .ok();
+        }
+        if diagnostics.is_empty() {
+            AppendMenuW(
+                troubleshoot_menu,
+                MF_STRING | MF_GRAYED,
+                0,
+                &HSTRING::from(tr(Text::NoSessions)),
+            )
+            .ok();
+        }
+
+        // Submenu for the common input options
+        let input = context.config.read().input.clone();
+        let input_menu = CreatePopupMenu().unwrap();
+        for (id, enabled, label) in [
+            (
+                IDM_INPUT_INVERT,
+                input.invert_scroll,
+                Text::InvertVolumeKeys,
+            ),
+            (
+                IDM_INPUT_ACCELERATION,
+                input.acceleration,
+                Text::AccelerateHeldKeys,
+            ),
+            (
+                IDM_INPUT_DISCRETE,
+                input.discrete_presses,
+                Text::OneStepPerPress,
+            ),
+            (
+                IDM_INPUT_UNMUTE,
+                input.unmute_on_adjust,
+                Text::UnmuteOnVolumeChange,
+            ),
+            (
+                IDM_INPUT_PROPORTIONAL,
+                input.proportional_step,
+                Text::ProportionalSteps,
+            ),
+        ] {
+            let flags = if enabled {
+                MF_STRING | MF_CHECKED
+            } else {
+                MF_STRING
+            };
+            AppendMenuW(input_menu, flags, id as usize, &HSTRING::from(tr(label))).ok();
+        }
+        AppendMenuW(
+            menu,
+            MF_STRING | MF_POPUP,
+            input_menu.0 as usize,
+            &HSTRING::from(tr(Text::Input)),
         )
         .ok();
 
-        let ctrl_flags = if current_modifier == ModifierKey::Control {
+        // Submenu for the volume cap of the last adjusted app
+        let last_app = context.audio_controller.last_app_name();
+        let cap_menu = CreatePopupMenu().unwrap();
+        let current_cap = last_app
+            .as_deref()
+            .and_then(|app| context.config.read().max_volume_for(app))
+            .map(|max| (max * 100.0).round() as u32);
+
+        let none_flags = if current_cap.is_none() {
             MF_STRING | MF_CHECKED
         } else {
             MF_STRING
         };
         AppendMenuW(
-            modifier_menu,
-            ctrl_flags,
-            IDM_MODIFIER_CTRL as usize,
-            w!("Control"),
+            cap_menu,
+            none_flags,
+            IDM_CAP_NONE as usize,
+            &HSTRING::from(tr(Text::NoCap)),
+        )
+        .ok();
+
+        for (i, percent) in CAP_PRESETS.iter().enumerate() {
+            let flags = if current_cap == Some(*percent) {
+                MF_STRING | MF_CHECKED
+            } else {
+                MF_STRING
+            };
+            let label = HSTRING::from(format!("{}%", percent));
+            AppendMenuW(cap_menu, flags, IDM_CAP_FIRST as usize + i, &label).ok();
+        }
+
+        let (cap_flags, cap_label) = match &last_app {
+            Some(app) => (
+                MF_STRING | MF_POPUP,
+                format!(
+                    "{} ({})",
+                    tr(Text::VolumeCap),
+                    context.config.read().display_name(app)
+                ),
+            ),
+            None => (
+                MF_STRING | MF_POPUP | MF_GRAYED,
+                tr(Text::VolumeCap).to_string(),
+            ),
+        };
+        AppendMenuW(
+            menu,
+            cap_flags,
+            cap_menu.0 as usize,
+            &HSTRING::from(cap_label),
         )
         .ok();
 
-        let alt_flags = if current_modifier == ModifierKey::Alt {
+        // Submenu for the volume floor of the last adjusted app
+        let floor_menu = CreatePopupMenu().unwrap();
+        let current_floor = last_app
+            .as_deref()
+            .and_then(|app| context.config.read().min_volume_for(app))
+            .map(|min| (min * 100.0).round() as u32);
+
+        let none_flags = if current_floor.is_none() {
             MF_STRING | MF_CHECKED
         } else {
             MF_STRING
         };
         AppendMenuW(
-            modifier_menu,
-            alt_flags,
-            IDM_MODIFIER_ALT as usize,
-            w!("Alt"),
+            floor_menu,
+            none_flags,
+            IDM_FLOOR_NONE as usize,
+            &HSTRING::from(tr(Text::NoFloor)),
         )
         .ok();
 
-        // Add submenu to main menu
+        for (i, percent) in FLOOR_PRESETS.iter().enumerate() {
+            let flags = if current_floor == Some(*percent) {
+                MF_STRING | MF_CHECKED
+            } else {
+                MF_STRING
+            };
+            let label = HSTRING::from(format!("{}%", percent));
+            AppendMenuW(floor_menu, flags, IDM_FLOOR_FIRST as usize + i, &label).ok();
+        }
+
+        let (floor_flags, floor_label) = match &last_app {
+            Some(app) => (
+                MF_STRING | MF_POPUP,
+                format!(
+                    "{} ({})",
+                    tr(Text::VolumeFloor),
+                    context.config.read().display_name(app)
+                ),
+            ),
+            None => (
+                MF_STRING | MF_POPUP | MF_GRAYED,
+                tr(Text::VolumeFloor).to_string(),
+            ),
+        };
+        AppendMenuW(
+            menu,
+            floor_flags,
+            floor_menu.0 as usize,
+            &HSTRING::from(floor_label),
+        )
+        .ok();
+
+        AppendMenuW(
+            menu,
+            MF_STRING,
+            IDM_MANAGE_APPS as usize,
+            &HSTRING::from(tr(Text::AppSettings)),
+        )
+        .ok();
+
+        AppendMenuW(
+            menu,
+            MF_STRING,
+            IDM_SNAPSHOT_SAVE as usize,
+            &HSTRING::from(tr(Text::SaveSnapshot)),
+        )
+        .ok();
+        AppendMenuW(
+            menu,
+            MF_STRING,
+            IDM_SNAPSHOT_RESTORE as usize,
+            &HSTRING::from(tr(Text::RestoreSnapshot)),
+        )
+        .ok();
+
+        // Submenu of the configured macros, when there are any
+        let names: Vec<String> = context
+            .config
+            .read()
+            .macros
+            .iter()
+            .take(MAX_MACRO_ITEMS as usize)
+            .map(|macro_config| macro_config.name.clone())
+            .collect();
+        if !names.is_empty() {
+            let macro_menu = CreatePopupMenu().unwrap();
+            for (i, name) in names.iter().enumerate() {
+                AppendMenuW(
+                    macro_menu,
+                    MF_STRING,
+                    IDM_MACRO_FIRST as usize + i,
+                    &HSTRING::from(name),
+                )
+                .ok();
+            }
+            AppendMenuW(
+                menu,
+                MF_STRING | MF_POPUP,
+                macro_menu.0 as usize,
+                &HSTRING::from(tr(Text::Macros)),
+            )
+            .ok();
+        }
+        *context.macro_menu.lock() = names;
+
+        AppendMenuW(
+            menu,
+            MF_STRING,
+            IDM_RESET as usize,
+            &HSTRING::from(tr(Text::ResetDefaults)),
+        )
+        .ok();
         AppendMenuW(
             menu,
             MF_STRING | MF_POPUP,
-            modifier_menu.0 as usize,
-            w!("Modifier Key"),
+            troubleshoot_menu.0 as usize,
+            &HSTRING::from(tr(Text::Troubleshoot)),
         )
         .ok();
 
         AppendMenuW(menu, MF_SEPARATOR, 0, PCWSTR::null()).ok();
-        AppendMenuW(menu, MF_STRING, IDM_EXIT as usize, w!("Exit")).ok();
+
+        let suspend_flags = if context.suspended.load(Ordering::SeqCst) {
+            MF_STRING | MF_CHECKED
+        } else {
+            MF_STRING
+        };
+        AppendMenuW(
+            menu,
+            suspend_flags,
+            IDM_SUSPEND as usize,
+            &HSTRING::from(tr(Text::Suspend)),
+        )
+        .ok();
+        AppendMenuW(
+            menu,
+            MF_STRING,
+            IDM_EXIT as usize,
+            &HSTRING::from(tr(Text::Exit)),
+        )
+        .ok();
 
         let mut pt = POINT { x: 0, y: 0 };
         let _ = GetCursorPos(&mut pt);
 
+        // The menu only closes on outside clicks while the tray window is in
+        // the foreground; remember who had it to hand it back afterwards
+        let previous = if context.config.read().restore_focus {
+            previous_app_window(hwnd)
+        } else {
+            None
+        };
+
         let _ = SetForegroundWindow(hwnd);
 
         let _ = TrackPopupMenu(menu, TPM_RIGHTBUTTON, pt.x, pt.y, 0, hwnd, None);
+        let _ = PostMessageW(hwnd, WM_NULL, WPARAM(0), LPARAM(0));
 
         let _ = DestroyMenu(menu);
+
+        // Commands are handled after this returns, so windows they open
+        // (App Settings, dialogs) still take the focus from here
+        if let Some(previous) = previous.filter(|previous| IsWindow(*previous).as_bool()) {
+            let _ = SetForegroundWindow(previous);
+        }
+    }
+}
+
+/// Window focused before the tray was clicked. Clicking the icon gives the
+/// focus to the taskbar, so in that case it's the topmost app window below
+/// it in the z-order.
+unsafe fn previous_app_window(tray: HWND) -> Option<HWND> {
+    let foreground = GetForegroundWindow();
+    if !foreground.is_invalid() && foreground != tray && !is_shell_window(foreground) {
+        return Some(foreground);
+    }
+
+    let mut candidate = GetTopWindow(None).ok()?;
+    loop {
+        let ex_style = GetWindowLongW(candidate, GWL_EXSTYLE) as u32;
+        if candidate != tray
+            && IsWindowVisible(candidate).as_bool()
+            && ex_style & WS_EX_TOOLWINDOW.0 == 0
+            && GetWindow(candidate, GW_OWNER).is_err()
+            && !is_shell_window(candidate)
+        {
+            return Some(candidate);
+        }
+        candidate = GetWindow(candidate, GW_HWNDNEXT).ok()?;
     }
 }
 
+/// Taskbar and notification area windows
+unsafe fn is_shell_window(hwnd: HWND) -> bool {
+    let mut buffer = [0u16; 64];
+    let len = GetClassNameW(hwnd, &mut buffer) as usize;
+    matches!(
+        String::from_utf16_lossy(&buffer[..len]).as_str(),
+        "Shell_TrayWnd" | "Shell_SecondaryTrayWnd" | "NotifyIconOverflowWindow"
+    )
+}
+
+/// Copy `tip` into the tooltip buffer, truncating it if needed
+fn set_tip(nid: &mut NOTIFYICONDATAW, tip: &str) {
+    copy_wide(&mut nid.szTip, tip);
+}
+
+/// Copy `text` into a fixed UTF-16 buffer, truncating it to keep the
+/// terminating null
+fn copy_wide(buffer: &mut [u16], text: &str) {
+    let text_wide: Vec<u16> = text.encode_utf16().collect();
+    let copy_len = text_wide.len().min(buffer.len() - 1);
+    buffer[..copy_len].copy_from_slice(&text_wide[..copy_len]);
+}
+
+/// Whether the taskbar uses the light theme (`SystemUsesLightTheme`)
+fn system_uses_light_theme() -> bool {
+    let mut value: u32 = 0;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            w!("Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize"),
+            w!("SystemUsesLightTheme"),
+            RRF_RT_REG_DWORD,
+            None,
+            Some(&mut value as *mut u32 as *mut _),
+            Some(&mut size),
+        )
+    };
+    result.is_ok() && value != 0
+}
+
 impl Drop for SystemTray {
     fn drop(&mut self) {
         unsafe {
@@ -305,9 +1227,79 @@ impl Drop for SystemTray {
             };
 
             let _ = Shell_NotifyIconW(NIM_DELETE, &nid);
+            // Release the window's reference to the context, and with it
+            // the audio controller, before COM is uninitialized
+            SetWindowLongPtrW(self.hwnd, GWLP_USERDATA, 0);
+            drop(Rc::from_raw(Rc::as_ptr(&self.context)));
             let _ = DestroyWindow(self.hwnd);
 
-            println!("System tray icon removed");
+            log_event!("System tray icon removed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modifier_labels() {
+        let expected = [
+            (ModifierKey::Shift, "Shift"),
+            (ModifierKey::Control, "Control"),
+            (ModifierKey::Alt, "Alt"),
+            (ModifierKey::Win, "Win"),
+        ];
+        assert_eq!(expected.len(), ModifierKey::ALL.len());
+
+        for (modifier, label) in expected {
+            assert_eq!(modifier.to_string(), label);
+            assert_eq!(label.parse::<ModifierKey>(), Ok(modifier));
+        }
+    }
+
+    #[test]
+    fn modifier_parsing_ignores_case() {
+        assert_eq!("SHIFT".parse(), Ok(ModifierKey::Shift));
+        assert_eq!("control".parse(), Ok(ModifierKey::Control));
+        assert_eq!("Ctrl".parse(), Ok(ModifierKey::Control));
+        assert_eq!("aLt".parse(), Ok(ModifierKey::Alt));
+    }
+
+    #[test]
+    fn win_modifier_parses() {
+        assert_eq!("win".parse(), Ok(ModifierKey::Win));
+        assert_eq!("WIN".parse(), Ok(ModifierKey::Win));
+    }
+
+    #[test]
+    fn unknown_modifier_is_an_error() {
+        let error = "hyper".parse::<ModifierKey>().unwrap_err();
+        assert!(error.to_string().contains("'hyper'"));
+        assert!("".parse::<ModifierKey>().is_err());
+    }
+
+    #[test]
+    fn modifiers_match_their_virtual_keys() {
+        let expected = [
+            (
+                ModifierKey::Shift,
+                [VK_SHIFT, VK_LSHIFT, VK_RSHIFT].as_slice(),
+            ),
+            (
+                ModifierKey::Control,
+                &[VK_CONTROL, VK_LCONTROL, VK_RCONTROL],
+            ),
+            (ModifierKey::Alt, &[VK_MENU, VK_LMENU, VK_RMENU]),
+            (ModifierKey::Win, &[VK_LWIN, VK_RWIN]),
+        ];
+
+        for (modifier, vk_codes) in expected {
+            for other in ModifierKey::ALL {
+                for vk in vk_codes {
+                    assert_eq!(other.matches_vk(vk.0 as u32), other == modifier);
+                }
+            }
         }
     }
 }
@@ -1,16 +1,48 @@
+use parking_lot::RwLock;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use windows::core::*;
 use windows::Win32::Foundation::*;
+use windows::Win32::Graphics::Dwm::*;
 use windows::Win32::Graphics::Gdi::*;
+use windows::Win32::System::Diagnostics::Debug::MessageBeep;
 use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::System::Registry::*;
+use windows::Win32::UI::Controls::{MARGINS, WM_MOUSELEAVE};
 use windows::Win32::UI::HiDpi::*;
+use windows::Win32::UI::Input::KeyboardAndMouse::{TrackMouseEvent, TME_LEAVE, TRACKMOUSEEVENT};
 use windows::Win32::UI::Shell::*;
 use windows::Win32::UI::WindowsAndMessaging::*;
 
+use crate::audio_control::{app_main_window, AudioController, MatchKind, VolumeInfo};
+use crate::config::{Config, ToastBackdrop, ToastOrientation};
+use crate::i18n::{tr, tr_with, Text};
+use crate::session_events::{VolumeChange, WM_SESSION_VOLUME_CHANGED};
+
 const TOAST_WIDTH: i32 = 194;
 const TOAST_HEIGHT: i32 = 52;
-const HIDE_DELAY_MS: u64 = 2500;
+/// Size of the vertical toast, the horizontal one on its side, and its gap
+/// from the right edge of the screen
+const VERTICAL_WIDTH: i32 = TOAST_HEIGHT;
+const VERTICAL_HEIGHT: i32 = TOAST_WIDTH;
+const VERTICAL_EDGE_MARGIN: i32 = 24;
+/// How long messages stay up
+pub const HIDE_DELAY_MS: u64 = 2500;
+/// Duration of the bar animation with `toast_animate_bar`
+const BAR_ANIMATION_MS: f32 = 150.0;
+/// How long the border flashes with `limit_feedback`
+const LIMIT_FLASH: Duration = Duration::from_millis(300);
+/// Border color of the flash
+const LIMIT_FLASH_COLOR: COLORREF = COLORREF(0x00FFFFFF);
+/// Compact row of `toast_context`: width of each app and margin at the ends
+const CONTEXT_SLOT_WIDTH: i32 = 40;
+const CONTEXT_PADDING: i32 = 10;
+/// Side of the `toast_buttons` squares, unscaled
+const BUTTON_SIZE: i32 = 16;
+/// Room each button takes next to the bar, unscaled
+const BUTTON_SPACE: i32 = 20;
+/// First Windows build with `DWMWA_SYSTEMBACKDROP_TYPE` (Windows 11 22H2)
+const SYSTEM_BACKDROP_BUILD: u32 = 22621;
 
 pub struct ToastUI {
     hwnd: HWND,
@@ -18,15 +50,105 @@ pub struct ToastUI {
 }
 
 struct ToastState {
+    config: Arc<RwLock<Config>>,
+    /// Corner radius the current window region was built with
+    region_radius: u32,
+    /// Backdrop last asked for in the config
+    backdrop: ToastBackdrop,
+    /// A backdrop other than solid is in effect, so the background is
+    /// painted see-through
+    has_backdrop: bool,
+    /// The window is excluded from screen capture
+    capture_excluded: bool,
+    /// Opacity the window was last made layered with; 255 when it isn't
+    opacity: u8,
+    /// Width the window is sized to, unscaled (wider for a long compact row)
+    width: i32,
+    /// Layout the window is sized and placed for
+    orientation: ToastOrientation,
     app_name: String,
     volume: f32,
+    /// Volume the bar is drawn at; eases toward `volume` with
+    /// `toast_animate_bar`, otherwise always equal to it
+    displayed_volume: f32,
+    /// Start of the bar animation in progress and the level it started from
+    animation: Option<(Instant, f32)>,
+    /// Volume before the change, marked on the bar with `toast_show_previous`
+    previous_volume: f32,
     is_muted: bool,
+    cap_reached: bool,
+    floor_reached: bool,
+    /// Start of the border flash for a step that reached 0% or 100%
+    limit_flash: Option<Instant>,
+    is_no_session: bool,
+    device_name: Option<String>,
+    /// The system volume was adjusted instead of an app (no session)
+    is_master: bool,
+    /// The device volume was adjusted for an app in exclusive mode
+    is_exclusive: bool,
+    /// The volume was capped by quiet hours
+    quiet_hours: bool,
+    /// The change is temporary and reverts after this long
+    restore_in: Option<Duration>,
     icon: Option<HICON>,
+    /// Executable `icon` was extracted from, to reuse it with `toast_cache_icon`
+    icon_path: Option<String>,
+    /// Plain text shown instead of the volume bar
+    message: Option<String>,
+    /// Apps of the compact row shown instead of the volume bar with
+    /// `toast_context`; empty for the single bar
+    context: Vec<ContextEntry>,
+    /// Playing apps left out of the compact row
+    context_overflow: usize,
     last_update: Instant,
+    hide_after: Duration,
+    /// Last update while hidden, when showing is delayed (see `check_hide`)
+    pending_show: Option<Instant>,
+    /// The cursor is over the window (`WM_MOUSELEAVE` is being tracked)
+    hovered: bool,
+    /// Adjusts the app shown when its `toast_buttons` are clicked
+    audio_controller: Option<Arc<AudioController>>,
+}
+
+/// One app of the compact row
+struct ContextEntry {
+    app_name: String,
+    volume: f32,
+    is_muted: bool,
+    icon: Option<HICON>,
+    /// The app being adjusted
+    highlighted: bool,
+}
+
+impl ToastState {
+    /// Swap the compact row, freeing the icons of the previous one
+    fn replace_context(&mut self, context: Vec<ContextEntry>, overflow: usize) {
+        for entry in std::mem::replace(&mut self.context, context) {
+            if let Some(icon) = entry.icon {
+                unsafe {
+                    let _ = DestroyIcon(icon);
+                }
+            }
+        }
+        self.context_overflow = overflow;
+    }
+
+    /// Height the window is sized to, unscaled
+    fn height(&self) -> i32 {
+        match self.orientation {
+            ToastOrientation::Horizontal => TOAST_HEIGHT,
+            ToastOrientation::Vertical => VERTICAL_HEIGHT,
+        }
+    }
+
+    /// The volume bar is on screen with `toast_buttons` around it
+    fn shows_buttons(&self) -> bool {
+        self.config.read().toast_buttons && self.message.is_none() && self.context.is_empty()
+    }
 }
 
 impl ToastUI {
-    pub fn new() -> Result<Self> {
+    pub fn new(config: Arc<RwLock<Config>>) -> Result<Self> {
         unsafe {
             let instance = GetModuleHandleW(None)?;
             let class_name = w!("VolimeToastClass");
@@ -53,13 +175,14 @@ impl ToastUI {
             // Escalar dimensiones según DPI
             let scaled_width = (TOAST_WIDTH as f32 * scale) as i32;
             let scaled_height = (TOAST_HEIGHT as f32 * scale) as i32;
-            let scaled_radius = (12.0 * scale) as i32;
 
             // Crear ventana centrada en la parte inferior
-            let screen_width = GetSystemMetrics(SM_CXSCREEN);
-            let screen_height = GetSystemMetrics(SM_CYSCREEN);
-            let x = (screen_width - scaled_width) / 2;
-            let y = screen_height - scaled_height - (150.0 * scale) as i32;
+            let (x, y) = home_position(
+                ToastOrientation::Horizontal,
+                scaled_width,
+                scaled_height,
+                scale,
+            );
 
             let hwnd = CreateWindowExW(
                 WS_EX_TOPMOST | WS_EX_TOOLWINDOW | WS_EX_NOACTIVATE,
@@ -77,15 +200,11 @@ impl ToastUI {
             )?;
 
             // Aplicar región con esquinas redondeadas escaladas según DPI
-            let region = CreateRoundRectRgn(
-                0,
-                0,
-                scaled_width,
-                scaled_height,
-                scaled_radius,
-                scaled_radius,
-            );
-            SetWindowRgn(hwnd, region, true);
+            let corner_radius = config.read().toast_corner_radius;
+            Self::apply_region(hwnd, corner_radius, TOAST_WIDTH, TOAST_HEIGHT);
+
+            let backdrop = config.read().toast_backdrop;
+            let has_backdrop = backdrop != ToastBackdrop::Solid && apply_backdrop(hwnd, backdrop);
 
             // Habilitar sombra suave usando class style
             let current_style = GetClassLongPtrW(hwnd, GCL_STYLE) as isize;
@@ -93,11 +212,39 @@ impl ToastUI {
             SetClassLongPtrW(hwnd, GCL_STYLE, new_style);
 
             let state = Arc::new(Mutex::new(ToastState {
+                config,
+                region_radius: corner_radius,
+                backdrop,
+                has_backdrop,
+                capture_excluded: false,
+                opacity: 255,
+                width: TOAST_WIDTH,
+                orientation: ToastOrientation::Horizontal,
                 app_name: String::new(),
                 volume: 0.0,
+                displayed_volume: 0.0,
+                animation: None,
                 is_muted: false,
+                previous_volume: 0.0,
+                cap_reached: false,
+                floor_reached: false,
+                limit_flash: None,
+                is_no_session: false,
+                device_name: None,
+                is_master: false,
+                is_exclusive: false,
+                quiet_hours: false,
+                restore_in: None,
                 icon: None,
+                icon_path: None,
+                message: None,
+                context: Vec::new(),
+                context_overflow: 0,
                 last_update: Instant::now(),
+                hide_after: Duration::from_millis(HIDE_DELAY_MS),
+                pending_show: None,
+                hovered: false,
+                audio_controller: None,
             }));
 
             // Guardar el estado en el GWLP_USERDATA
@@ -108,24 +255,244 @@ impl ToastUI {
         }
     }
 
-    pub fn show_volume(
-        &self,
-        app_name: String,
-        volume: f32,
-        is_muted: bool,
-        exe_path: Option<String>,
-    ) {
+    pub fn show_volume(&self, info: VolumeInfo) {
+        self.display_volume(info, Vec::new(), 0);
+    }
+
+    /// Like `show_volume`, but with `toast_context` the other playing apps
+    /// are shown next to the adjusted one in a compact row
+    pub fn show_volume_in_context(&self, info: VolumeInfo, audio_controller: &AudioController) {
+        let config = self.state.lock().unwrap().config.clone();
+        let config = config.read();
+        let enabled = config.toast_context;
+        let max_apps = config.toast_context_max_apps();
+        let show_icons = config.toast_show_icons;
+        drop(config);
+
+        if !enabled
+            || info.is_no_session
+            || matches!(info.match_kind, MatchKind::Master | MatchKind::Exclusive)
+        {
+            return self.show_volume(info);
+        }
+
+        let sessions = match audio_controller.list_sessions() {
+            Ok(sessions) => sessions,
+            Err(e) => {
+                log_event!("Error listing audio sessions: {}", e);
+                return self.show_volume(info);
+            }
+        };
+
+        // El orden de `list_sessions` se mantiene; la app ajustada siempre
+        // entra y las demás ocupan los huecos restantes
+        let mut context = Vec::new();
+        let mut overflow = 0;
+        let mut others = 0;
+        for session in sessions {
+            let highlighted = session.app_name.eq_ignore_ascii_case(&info.app_name);
+            if !highlighted {
+                if !session.is_active {
+                    continue;
+                }
+                if others >= max_apps - 1 {
+                    overflow += 1;
+                    continue;
+                }
+                others += 1;
+            }
+
+            context.push(ContextEntry {
+                app_name: session.app_name,
+                volume: if highlighted {
+                    info.volume
+                } else {
+                    session.volume
+                },
+                is_muted: if highlighted {
+                    info.is_muted
+                } else {
+                    session.is_muted
+                },
+                icon: session
+                    .exe_path
+                    .filter(|_| show_icons)
+                    .and_then(|path| extract_icon(&path)),
+                highlighted,
+            });
+        }
+
+        // Sin otras apps sonando no hay contexto que mostrar
+        if others == 0 || !context.iter().any(|entry| entry.highlighted) {
+            for icon in context.into_iter().filter_map(|entry| entry.icon) {
+                unsafe {
+                    let _ = DestroyIcon(icon);
+                }
+            }
+            return self.show_volume(info);
+        }
+
+        self.display_volume(info, context, overflow);
+    }
+
+    fn display_volume(&self, info: VolumeInfo, context: Vec<ContextEntry>, overflow: usize) {
         let mut state = self.state.lock().unwrap();
-        state.app_name = app_name;
-        state.volume = volume;
-        state.is_muted = is_muted;
+
+        // El pitido avisa del límite aunque el toast no se muestre
+        let limit_feedback = state.config.read().limit_feedback;
+        if info.limit_reached && limit_feedback.beeps() {
+            unsafe {
+                let _ = MessageBeep(MB_OK);
+            }
+        }
+
+        let scope = state.config.read().toast_scope;
+        if !scope.shows(info.match_kind == MatchKind::Master) || is_hidden_for_presentation(&state)
+        {
+            drop(state);
+            for icon in context.into_iter().filter_map(|entry| entry.icon) {
+                unsafe {
+                    let _ = DestroyIcon(icon);
+                }
+            }
+            return;
+        }
+
+        let visible = unsafe { IsWindowVisible(self.hwnd).as_bool() };
+
+        // Animar solo si la barra de la misma app ya está en pantalla; un
+        // cambio durante la animación la redirige desde donde va
+        let animate = state.config.read().toast_animate_bar
+            && visible
+            && state.message.is_none()
+            && state.context.is_empty()
+            && context.is_empty()
+            && state.app_name == info.app_name;
+        if animate {
+            state.animation = Some((Instant::now(), state.displayed_volume));
+        } else {
+            state.animation = None;
+            state.displayed_volume = info.volume;
+        }
+
+        state.app_name = info.app_name;
+        state.volume = info.volume;
+        state.previous_volume = info.previous_volume;
+        state.is_muted = info.is_muted;
+        state.cap_reached = info.cap_reached;
+        state.floor_reached = info.floor_reached;
+        state.limit_flash =
+            (info.limit_reached && limit_feedback.flashes()).then_some(Instant::now());
+        state.is_no_session = info.is_no_session;
+        state.device_name = info.device_name;
+        state.is_master = info.match_kind == MatchKind::Master;
+        state.is_exclusive = info.match_kind == MatchKind::Exclusive;
+        state.quiet_hours = info.quiet_hours;
+        state.restore_in = info.restore_in;
+        state.message = None;
         state.last_update = Instant::now();
+        let hide_after = state.config.read().toast_hide_delay();
+        state.hide_after = hide_after;
+
+        // La fila compacta ensancha la ventana según el número de apps y
+        // siempre es horizontal
+        let orientation = if context.is_empty() {
+            state.config.read().toast_orientation
+        } else {
+            ToastOrientation::Horizontal
+        };
+        let width = if !context.is_empty() {
+            let slots = context.len() + usize::from(overflow > 0);
+            CONTEXT_PADDING * 2 + CONTEXT_SLOT_WIDTH * slots as i32
+        } else if orientation == ToastOrientation::Vertical {
+            VERTICAL_WIDTH
+        } else {
+            TOAST_WIDTH
+        };
+        state.replace_context(context, overflow);
+        self.resize(&mut state, width, orientation);
+        self.refresh_region(&mut state);
+        self.refresh_backdrop(&mut state);
+        self.refresh_capture(&mut state);
+        self.refresh_opacity(&mut state);
+
+        // Obtener icono de la aplicación (sin icono se dibuja uno genérico),
+        // reutilizando el anterior si es el mismo ejecutable
+        let config = state.config.read();
+        let show_icons = config.toast_show_icons;
+        let cache_icon = config.toast_cache_icon;
+        drop(config);
+        let icon_path = info.exe_path.filter(|_| show_icons && !state.is_master);
+        if !cache_icon || icon_path.is_none() || icon_path != state.icon_path {
+            let icon = icon_path.as_deref().and_then(extract_icon);
+            if let Some(old_icon) = std::mem::replace(&mut state.icon, icon) {
+                unsafe {
+                    let _ = DestroyIcon(old_icon);
+                }
+            }
+            state.icon_path = icon_path;
+        }
+
+        // Con retardo configurado, esperar a que el estado se estabilice
+        let show_delay = state.config.read().toast_show_delay_ms;
+        state.pending_show = (show_delay > 0 && !visible).then(Instant::now);
+        let pending = state.pending_show.is_some();
+
+        drop(state);
+
+        unsafe {
+            if !pending {
+                let _ = ShowWindow(self.hwnd, SW_SHOWNOACTIVATE);
+            }
+            let _ = InvalidateRect(self.hwnd, None, true);
+        }
+    }
+
+    pub fn hwnd(&self) -> HWND {
+        self.hwnd
+    }
+
+    /// Controller the `toast_buttons` adjust the volume through; without
+    /// one clicking them does nothing
+    pub fn set_audio_controller(&self, audio_controller: Arc<AudioController>) {
+        self.state.lock().unwrap().audio_controller = Some(audio_controller);
+    }
+
+    /// Whether the window has a region covering exactly its current size
+    pub fn has_region(&self) -> bool {
+        unsafe {
+            let mut region_box = RECT::default();
+            let mut window_rect = RECT::default();
+            if GetWindowRgnBox(self.hwnd, &mut region_box) == RGN_ERROR
+                || GetWindowRect(self.hwnd, &mut window_rect).is_err()
+            {
+                return false;
+            }
 
-        // Obtener icono de la aplicación
-        if let Some(path) = exe_path {
-            state.icon = Self::extract_icon(&path);
+            // Las regiones redondeadas pueden quedar un píxel más pequeñas
+            let width = window_rect.right - window_rect.left;
+            let height = window_rect.bottom - window_rect.top;
+            (region_box.right - region_box.left - width).abs() <= 1
+                && (region_box.bottom - region_box.top - height).abs() <= 1
         }
+    }
 
+    /// Show a short text notice (e.g. "Shift armed") for `duration`
+    pub fn show_message(&self, message: String, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        if is_hidden_for_presentation(&state) {
+            return;
+        }
+        state.message = Some(message);
+        state.pending_show = None;
+        state.last_update = Instant::now();
+        state.hide_after = duration;
+        state.replace_context(Vec::new(), 0);
+        self.resize(&mut state, TOAST_WIDTH, ToastOrientation::Horizontal);
+        self.refresh_region(&mut state);
+        self.refresh_backdrop(&mut state);
+        self.refresh_capture(&mut state);
+        self.refresh_opacity(&mut state);
         drop(state);
 
         unsafe {
@@ -134,36 +501,190 @@ impl ToastUI {
         }
     }
 
-    fn extract_icon(path: &str) -> Option<HICON> {
+    /// Rebuild the window region if the configured corner radius changed,
+    /// or if the window lost its region or outgrew it. Called before every
+    /// show, so the corners stay rounded however often it hides and shows.
+    fn refresh_region(&self, state: &mut ToastState) {
+        let corner_radius = state.config.read().toast_corner_radius;
+        if corner_radius != state.region_radius || !self.has_region() {
+            unsafe { Self::apply_region(self.hwnd, corner_radius, state.width, state.height()) };
+            state.region_radius = corner_radius;
+        }
+    }
+
+    /// Switch to the configured backdrop if it changed
+    fn refresh_backdrop(&self, state: &mut ToastState) {
+        let backdrop = state.config.read().toast_backdrop;
+        if backdrop != state.backdrop {
+            state.has_backdrop = unsafe { apply_backdrop(self.hwnd, backdrop) };
+            state.backdrop = backdrop;
+        }
+    }
+
+    /// Exclude the window from screen capture, or include it again, when
+    /// `toast_exclude_from_capture` changed
+    fn refresh_capture(&self, state: &mut ToastState) {
+        let exclude = state.config.read().toast_exclude_from_capture;
+        if exclude == state.capture_excluded {
+            return;
+        }
+
+        let affinity = if exclude {
+            WDA_EXCLUDEFROMCAPTURE
+        } else {
+            WDA_NONE
+        };
+        // Requiere Windows 10 2004; en versiones anteriores la llamada falla
+        match unsafe { SetWindowDisplayAffinity(self.hwnd, affinity) } {
+            Ok(()) => state.capture_excluded = exclude,
+            Err(e) => log_event!("Error changing the toast capture affinity: {}", e),
+        }
+    }
+
+    /// Apply `toast_opacity` when it changed. Below 255 the window is made
+    /// layered with a constant alpha, which keeps its rounded region; at 255
+    /// the layered style is removed again, as it isn't needed.
+    fn refresh_opacity(&self, state: &mut ToastState) {
+        let opacity = state.config.read().toast_opacity();
+        if opacity == state.opacity {
+            return;
+        }
+
         unsafe {
-            let path_wide: Vec<u16> = path.encode_utf16().chain(std::iter::once(0)).collect();
+            let ex_style = GetWindowLongPtrW(self.hwnd, GWL_EXSTYLE);
+            if opacity == 255 {
+                SetWindowLongPtrW(
+                    self.hwnd,
+                    GWL_EXSTYLE,
+                    ex_style & !(WS_EX_LAYERED.0 as isize),
+                );
+                state.opacity = opacity;
+                return;
+            }
 
-            let mut icon: HICON = HICON::default();
-            let result = ExtractIconExW(
-                PCWSTR::from_raw(path_wide.as_ptr()),
-                0,
+            SetWindowLongPtrW(self.hwnd, GWL_EXSTYLE, ex_style | WS_EX_LAYERED.0 as isize);
+            match SetLayeredWindowAttributes(self.hwnd, COLORREF(0), opacity, LWA_ALPHA) {
+                Ok(()) => state.opacity = opacity,
+                Err(e) => log_event!("Error changing the toast opacity: {}", e),
+            }
+        }
+    }
+
+    /// Resize the window to `width` (unscaled), keeping it centered where it
+    /// is. Turning it to another orientation moves it to that one's place.
+    fn resize(&self, state: &mut ToastState, width: i32, orientation: ToastOrientation) {
+        if width == state.width && orientation == state.orientation {
+            return;
+        }
+        let turned = orientation != state.orientation;
+        state.width = width;
+        state.orientation = orientation;
+
+        unsafe {
+            let mut rect = RECT::default();
+            let _ = GetWindowRect(self.hwnd, &mut rect);
+            let scale = GetDpiForWindow(self.hwnd) as f32 / 96.0;
+            let scaled_width = (width as f32 * scale) as i32;
+            let scaled_height = (state.height() as f32 * scale) as i32;
+            let (x, y) = if turned {
+                home_position(orientation, scaled_width, scaled_height, scale)
+            } else {
+                ((rect.left + rect.right) / 2 - scaled_width / 2, rect.top)
+            };
+            let _ = SetWindowPos(
+                self.hwnd,
                 None,
-                Some(&mut icon),
-                1,
+                x,
+                y,
+                scaled_width,
+                scaled_height,
+                SWP_NOZORDER | SWP_NOACTIVATE,
             );
+            Self::apply_region(self.hwnd, state.region_radius, width, state.height());
+        }
+    }
 
-            if result > 0 && !icon.is_invalid() {
-                Some(icon)
-            } else {
-                None
-            }
+    /// Clip the window to a rounded rectangle (square corners for radius 0)
+    unsafe fn apply_region(hwnd: HWND, corner_radius: u32, width: i32, height: i32) {
+        let scale = GetDpiForWindow(hwnd) as f32 / 96.0;
+        let scaled_width = (width as f32 * scale) as i32;
+        let scaled_height = (height as f32 * scale) as i32;
+        let scaled_radius = (corner_radius as f32 * scale) as i32;
+
+        apply_round_region(hwnd, scaled_width, scaled_height, scaled_radius);
+    }
+
+    pub fn hide(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.pending_show = None;
+        // Una animación cortada termina en su destino
+        state.animation = None;
+        state.displayed_volume = state.volume;
+        drop(state);
+        unsafe {
+            let _ = ShowWindow(self.hwnd, SW_HIDE);
         }
     }
 
+    /// Called from the main loop: shows a delayed toast once its state has
+    /// been stable for `toast_show_delay_ms`, and hides it after `hide_after`
     pub fn check_hide(&self) {
-        let state = self.state.lock().unwrap();
-        let elapsed = state.last_update.elapsed();
+        let mut state = self.state.lock().unwrap();
+        if let Some(pending_since) = state.pending_show {
+            let show_delay = Duration::from_millis(state.config.read().toast_show_delay_ms);
+            if pending_since.elapsed() >= show_delay {
+                state.pending_show = None;
+                self.refresh_region(&mut state);
+                drop(state);
+                unsafe {
+                    let _ = ShowWindow(self.hwnd, SW_SHOWNOACTIVATE);
+                }
+            }
+            return;
+        }
+        let paused = state.hovered && state.config.read().toast_pause_on_hover;
+        let expired = !paused && state.last_update.elapsed() > state.hide_after;
         drop(state);
 
-        if elapsed > Duration::from_millis(HIDE_DELAY_MS) {
-            unsafe {
-                let _ = ShowWindow(self.hwnd, SW_HIDE);
+        if expired {
+            self.hide();
+        }
+    }
+
+    /// Called from the main loop: advances the bar animation and ends the
+    /// limit flash, if any
+    pub fn tick(&self) {
+        let mut state = self.state.lock().unwrap();
+        let flash_ended = state
+            .limit_flash
+            .is_some_and(|started| started.elapsed() >= LIMIT_FLASH);
+        if flash_ended {
+            state.limit_flash = None;
+        }
+
+        let Some((started, from)) = state.animation else {
+            drop(state);
+            if flash_ended {
+                unsafe {
+                    let _ = InvalidateRect(self.hwnd, None, true);
+                }
             }
+            return;
+        };
+
+        let progress = started.elapsed().as_secs_f32() * 1000.0 / BAR_ANIMATION_MS;
+        if progress >= 1.0 {
+            state.animation = None;
+            state.displayed_volume = state.volume;
+        } else {
+            // Ease-out: rápido al principio, suave al llegar
+            let eased = 1.0 - (1.0 - progress).powi(3);
+            state.displayed_volume = from + (state.volume - from) * eased;
+        }
+        drop(state);
+
+        unsafe {
+            let _ = InvalidateRect(self.hwnd, None, true);
         }
     }
 
@@ -183,6 +704,64 @@ impl ToastUI {
                 }
                 LRESULT(0)
             }
+            WM_SESSION_VOLUME_CHANGED => {
+                let change = Box::from_raw(lparam.0 as *mut VolumeChange);
+                let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA);
+                if state_ptr != 0 && IsWindowVisible(hwnd).as_bool() {
+                    let state = Arc::from_raw(state_ptr as *const Mutex<ToastState>);
+                    Self::apply_external_change(hwnd, &state, &change);
+                    std::mem::forget(state); // No liberar el Arc
+                }
+                LRESULT(0)
+            }
+            WM_LBUTTONUP => {
+                let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA);
+                if state_ptr != 0 {
+                    let state = Arc::from_raw(state_ptr as *const Mutex<ToastState>);
+                    let x = (lparam.0 & 0xFFFF) as i16 as i32;
+                    let y = ((lparam.0 >> 16) & 0xFFFF) as i16 as i32;
+                    Self::click(hwnd, &state, x, y);
+                    std::mem::forget(state); // No liberar el Arc
+                }
+                LRESULT(0)
+            }
+            WM_MOUSEMOVE | WM_MOUSELEAVE => {
+                let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA);
+                if state_ptr != 0 {
+                    let state = Arc::from_raw(state_ptr as *const Mutex<ToastState>);
+                    Self::track_hover(hwnd, &state, msg == WM_MOUSEMOVE);
+                    std::mem::forget(state); // No liberar el Arc
+                }
+                LRESULT(0)
+            }
+            WM_DPICHANGED => {
+                // Redimensionar al tamaño sugerido para el DPI del nuevo monitor
+                let suggested = &*(lparam.0 as *const RECT);
+                let _ = SetWindowPos(
+                    hwnd,
+                    None,
+                    suggested.left,
+                    suggested.top,
+                    suggested.right - suggested.left,
+                    suggested.bottom - suggested.top,
+                    SWP_NOZORDER | SWP_NOACTIVATE,
+                );
+
+                let state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA);
+                if state_ptr != 0 {
+                    let state = Arc::from_raw(state_ptr as *const Mutex<ToastState>);
+                    let state_guard = state.lock().unwrap();
+                    let (corner_radius, width, height) = (
+                        state_guard.region_radius,
+                        state_guard.width,
+                        state_guard.height(),
+                    );
+                    drop(state_guard);
+                    Self::apply_region(hwnd, corner_radius, width, height);
+                    std::mem::forget(state); // No liberar el Arc
+                }
+                LRESULT(0)
+            }
             WM_DESTROY => {
                 PostQuitMessage(0);
                 LRESULT(0)
@@ -191,6 +770,184 @@ impl ToastUI {
         }
     }
 
+    /// A click on the app icon brings that app's window to the front, and
+    /// one on a `toast_buttons` button steps the volume
+    unsafe fn click(hwnd: HWND, state: &Arc<Mutex<ToastState>>, x: i32, y: i32) {
+        if Self::click_button(hwnd, state, x, y) {
+            return;
+        }
+
+        let state = state.lock().unwrap();
+        let icon_shown = state.config.read().toast_show_icons
+            && state.message.is_none()
+            && state.context.is_empty()
+            && !state.is_master;
+        let app_name = state.app_name.clone();
+        let (width, height, orientation) = (state.width, state.height(), state.orientation);
+        drop(state);
+
+        // Mismo rectángulo en el que `paint` dibuja el icono
+        let scale = GetDpiForWindow(hwnd) as f32 / 96.0;
+        let icon_size = (24.0 * scale) as i32;
+        let (icon_x, icon_y) = icon_position(
+            orientation,
+            (width as f32 * scale) as i32,
+            (height as f32 * scale) as i32,
+            icon_size,
+            scale,
+        );
+        let on_icon =
+            (icon_x..icon_x + icon_size).contains(&x) && (icon_y..icon_y + icon_size).contains(&y);
+        if !icon_shown || !on_icon {
+            return;
+        }
+
+        let Some(window) = app_main_window(&app_name) else {
+            log_event!("'{}' has no window to bring to the front", app_name);
+            return;
+        };
+        if IsIconic(window).as_bool() {
+            let _ = ShowWindow(window, SW_RESTORE);
+        }
+        let _ = SetForegroundWindow(window);
+    }
+
+    /// Step the volume of the app shown (or the system volume) if the click
+    /// is on one of the `toast_buttons`. Returns whether it was.
+    unsafe fn click_button(hwnd: HWND, state: &Arc<Mutex<ToastState>>, x: i32, y: i32) -> bool {
+        let guard = state.lock().unwrap();
+        if !guard.shows_buttons() {
+            return false;
+        }
+        let Some(audio_controller) = guard.audio_controller.clone() else {
+            return false;
+        };
+        let show_icons = guard.config.read().toast_show_icons;
+        let (app_name, is_master) = (guard.app_name.clone(), guard.is_master);
+        let (width, height, orientation) = (guard.width, guard.height(), guard.orientation);
+        drop(guard);
+
+        // Mismos rectángulos en los que `paint` dibuja los botones
+        let scale = GetDpiForWindow(hwnd) as f32 / 96.0;
+        let bar = bar_layout(
+            orientation,
+            (width as f32 * scale) as i32,
+            (height as f32 * scale) as i32,
+            show_icons,
+            true,
+            scale,
+        );
+        let (minus, plus) = button_rects(&bar, scale);
+        let inside = |rect: &RECT| {
+            (rect.left..rect.right).contains(&x) && (rect.top..rect.bottom).contains(&y)
+        };
+        let up = if inside(&plus) {
+            true
+        } else if inside(&minus) {
+            false
+        } else {
+            return false;
+        };
+
+        let result = if is_master {
+            audio_controller.adjust_master_volume(up, !up, false)
+        } else {
+            audio_controller.adjust_app_volume(&app_name, up, !up, false)
+        };
+        let volume_info = match result {
+            Ok(volume_info) => volume_info,
+            Err(e) => {
+                log_event!("Error adjusting '{}' from the toast: {}", app_name, e);
+                return true;
+            }
+        };
+        log_event!(
+            "Volume of '{}' from the toast: {}%",
+            volume_info.app_name,
+            (volume_info.volume * 100.0).round() as i32
+        );
+
+        // Actualizar la barra en el sitio y reiniciar el retardo de ocultado
+        let mut state = state.lock().unwrap();
+        state.previous_volume = volume_info.previous_volume;
+        state.volume = volume_info.volume;
+        state.displayed_volume = volume_info.volume;
+        state.animation = None;
+        state.is_muted = volume_info.is_muted;
+        state.is_no_session = volume_info.is_no_session;
+        state.cap_reached = volume_info.cap_reached;
+        state.floor_reached = volume_info.floor_reached;
+        state.last_update = Instant::now();
+        drop(state);
+
+        let _ = InvalidateRect(hwnd, None, true);
+        true
+    }
+
+    /// Repaint live when the app on screen is changed from elsewhere
+    unsafe fn apply_external_change(
+        hwnd: HWND,
+        state: &Arc<Mutex<ToastState>>,
+        change: &VolumeChange,
+    ) {
+        let mut state = state.lock().unwrap();
+        if state.message.is_some() {
+            return;
+        }
+
+        let mut changed = false;
+        for entry in state
+            .context
+            .iter_mut()
+            .filter(|entry| entry.app_name.eq_ignore_ascii_case(&change.app_name))
+        {
+            entry.volume = change.volume;
+            entry.is_muted = change.is_muted;
+            changed = true;
+        }
+
+        if state.app_name.eq_ignore_ascii_case(&change.app_name) {
+            state.volume = change.volume;
+            if state.animation.is_none() {
+                state.displayed_volume = change.volume;
+            }
+            state.is_muted = change.is_muted;
+            changed = true;
+        }
+        drop(state);
+
+        if !changed {
+            return;
+        }
+
+        let _ = InvalidateRect(hwnd, None, true);
+    }
+
+    /// Note the cursor entering or leaving the window, for
+    /// `toast_pause_on_hover`. Leaving restarts the hide delay.
+    unsafe fn track_hover(hwnd: HWND, state: &Arc<Mutex<ToastState>>, inside: bool) {
+        let mut state = state.lock().unwrap();
+        if inside == state.hovered {
+            return;
+        }
+        state.hovered = inside;
+
+        if inside {
+            // Windows solo envía WM_MOUSELEAVE si se pide tras cada entrada
+            let mut track = TRACKMOUSEEVENT {
+                cbSize: std::mem::size_of::<TRACKMOUSEEVENT>() as u32,
+                dwFlags: TME_LEAVE,
+                hwndTrack: hwnd,
+                dwHoverTime: 0,
+            };
+            if TrackMouseEvent(&mut track).is_err() {
+                state.hovered = false;
+            }
+        } else {
+            state.last_update = Instant::now();
+        }
+    }
+
     unsafe fn paint(hwnd: HWND, state: &Arc<Mutex<ToastState>>) {
         let mut ps = PAINTSTRUCT::default();
         let hdc = BeginPaint(hwnd, &mut ps);
@@ -198,18 +955,46 @@ impl ToastUI {
         if !hdc.is_invalid() {
             let state = state.lock().unwrap();
 
-            // Obtener escalado DPI
-            let dpi = GetDpiForSystem();
+            // Obtener escalado DPI del monitor donde está la ventana
+            let dpi = GetDpiForWindow(hwnd);
             let scale = dpi as f32 / 96.0;
 
             // Escalar dimensiones
-            let scaled_width = (TOAST_WIDTH as f32 * scale) as i32;
-            let scaled_height = (TOAST_HEIGHT as f32 * scale) as i32;
-            let scaled_radius = (12.0 * scale) as i32;
+            let scaled_width = (state.width as f32 * scale) as i32;
+            let scaled_height = (state.height() as f32 * scale) as i32;
+            let vertical = state.orientation == ToastOrientation::Vertical;
 
-            // Fondo con esquinas redondeadas escaladas
-            let brush = CreateSolidBrush(COLORREF(0x00282828));
-            let pen = CreatePen(PS_SOLID, 1, COLORREF(0x00404040));
+            // Radio y borde configurables
+            let config = state.config.read();
+            let scaled_radius = (config.toast_corner_radius as f32 * scale) as i32;
+            let mut border_width = (config.toast_border_width as f32 * scale).round() as i32;
+            let mut border_color =
+                parse_hex_color(&config.toast_border_color).unwrap_or(COLORREF(0x00404040));
+            let show_icons = config.toast_show_icons;
+            let buttons = config.toast_buttons;
+            let font_family = config.toast_font.clone();
+            let font_size = config.toast_font_size();
+            drop(config);
+
+            // Destello del borde al llegar a 0% o 100%
+            if state.limit_flash.is_some() {
+                border_width = border_width.max((2.0 * scale).round() as i32);
+                border_color = LIMIT_FLASH_COLOR;
+            }
+
+            // Fondo con esquinas redondeadas escaladas; con fondo acrílico o
+            // mica el negro deja ver el desenfoque a través del marco extendido
+            let background = if state.has_backdrop {
+                COLORREF(0x00000000)
+            } else {
+                COLORREF(0x00282828)
+            };
+            let brush = CreateSolidBrush(background);
+            let pen = if border_width > 0 {
+                CreatePen(PS_SOLID, border_width, border_color)
+            } else {
+                CreatePen(PS_NULL, 0, border_color)
+            };
             let old_brush = SelectObject(hdc, brush);
             let old_pen = SelectObject(hdc, pen);
 
@@ -228,87 +1013,212 @@ impl ToastUI {
             let _ = DeleteObject(brush);
             let _ = DeleteObject(pen);
 
-            // Dibujar icono centrado verticalmente a la izquierda escalado
-            if let Some(icon) = state.icon {
-                let icon_size = (24.0 * scale) as i32;
-                let icon_x = (10.0 * scale) as i32;
-                let icon_y = (scaled_height - icon_size) / 2;
-                let _ = DrawIconEx(
-                    hdc, icon_x, icon_y, icon, icon_size, icon_size, 0, None, DI_NORMAL,
-                );
+            // Dibujar icono centrado verticalmente a la izquierda escalado (o
+            // arriba en vertical), con el icono genérico de aplicación si no
+            // se pudo extraer
+            if show_icons && state.message.is_none() && state.context.is_empty() {
+                let icon = state.icon.or_else(|| LoadIconW(None, IDI_APPLICATION).ok());
+                if let Some(icon) = icon {
+                    let icon_size = (24.0 * scale) as i32;
+                    let (icon_x, icon_y) = icon_position(
+                        state.orientation,
+                        scaled_width,
+                        scaled_height,
+                        icon_size,
+                        scale,
+                    );
+                    let _ = DrawIconEx(
+                        hdc, icon_x, icon_y, icon, icon_size, icon_size, 0, None, DI_NORMAL,
+                    );
+                }
             }
 
             // Configurar texto
             SetBkMode(hdc, TRANSPARENT);
             SetTextColor(hdc, COLORREF(0x00CCCCCC)); // Gris claro en lugar de blanco puro
 
-            // Crear fuente Calibri escalada según DPI
-            let font_height = -(15.0 * scale) as i32; // Altura negativa para fuentes TrueType
-            let font_name: Vec<u16> = "Segoe UI Variable\0".encode_utf16().collect();
-            let font = CreateFontW(
-                font_height,
-                0,
-                0,
-                0,
-                FW_NORMAL.0 as i32,
-                0,
-                0,
-                0,
-                DEFAULT_CHARSET.0 as u32,
-                OUT_DEFAULT_PRECIS.0 as u32,
-                CLIP_DEFAULT_PRECIS.0 as u32,
-                CLEARTYPE_QUALITY.0 as u32,
-                (DEFAULT_PITCH.0 | FF_DONTCARE.0) as u32,
-                PCWSTR::from_raw(font_name.as_ptr()),
-            );
+            // Crear la fuente configurada escalada según DPI
+            let font_height = -(font_size as f32 * scale) as i32; // Altura negativa para fuentes TrueType
+            let (font, font_family) = create_font(hdc, &font_family, font_height);
             let old_font = SelectObject(hdc, font);
 
+            // Mensaje de texto en lugar de la barra
+            if let Some(message) = &state.message {
+                let mut message_wide: Vec<u16> =
+                    message.encode_utf16().chain(std::iter::once(0)).collect();
+                let mut message_rect = RECT {
+                    left: 0,
+                    top: 0,
+                    right: scaled_width,
+                    bottom: scaled_height,
+                };
+                DrawTextW(
+                    hdc,
+                    &mut message_wide,
+                    &mut message_rect,
+                    DT_CENTER | DT_SINGLELINE | DT_VCENTER,
+                );
+
+                SelectObject(hdc, old_font);
+                let _ = DeleteObject(font);
+                let _ = EndPaint(hwnd, &ps);
+                return;
+            }
+
+            // Fila compacta de apps en lugar de la barra
+            if !state.context.is_empty() {
+                Self::paint_context(hdc, &state, scale, scaled_height);
+
+                SelectObject(hdc, old_font);
+                let _ = DeleteObject(font);
+                let _ = EndPaint(hwnd, &ps);
+                return;
+            }
+
             // Dibujar barra de volumen en el centro escalada
-            let bar_x = (45.0 * scale) as i32;
-            let bar_y = (scaled_height - (4.0 * scale) as i32) / 2;
-            let bar_width = scaled_width - (85.0 * scale) as i32;
-            let bar_height = (4.0 * scale) as i32;
+            let bar = bar_layout(
+                state.orientation,
+                scaled_width,
+                scaled_height,
+                show_icons,
+                buttons,
+                scale,
+            );
 
-            // Fondo de la barra
-            let bg_brush = CreateSolidBrush(COLORREF(0x00AAAAAA));
-            let bg_rect = RECT {
-                left: bar_x,
-                top: bar_y,
-                right: bar_x + bar_width,
-                bottom: bar_y + bar_height,
+            // Botones - y + a los lados de la barra (debajo y encima en
+            // vertical)
+            if buttons {
+                let (minus, plus) = button_rects(&bar, scale);
+                let button_brush = CreateSolidBrush(COLORREF(0x00404040));
+                let glyph_brush = CreateSolidBrush(COLORREF(0x00CCCCCC));
+                let stroke = ((2.0 * scale) as i32).max(1);
+                let arm = (4.0 * scale) as i32;
+                for (rect, is_plus) in [(minus, false), (plus, true)] {
+                    FillRect(hdc, &rect, button_brush);
+                    let center_x = (rect.left + rect.right) / 2;
+                    let center_y = (rect.top + rect.bottom) / 2;
+                    let horizontal = RECT {
+                        left: center_x - arm,
+                        top: center_y - stroke / 2,
+                        right: center_x + arm,
+                        bottom: center_y - stroke / 2 + stroke,
+                    };
+                    FillRect(hdc, &horizontal, glyph_brush);
+                    if is_plus {
+                        let upright = RECT {
+                            left: center_x - stroke / 2,
+                            top: center_y - arm,
+                            right: center_x - stroke / 2 + stroke,
+                            bottom: center_y + arm,
+                        };
+                        FillRect(hdc, &upright, glyph_brush);
+                    }
+                }
+                let _ = DeleteObject(button_brush);
+                let _ = DeleteObject(glyph_brush);
+            }
+            let button_space = if buttons {
+                (BUTTON_SPACE as f32 * scale) as i32
+            } else {
+                0
             };
-            FillRect(hdc, &bg_rect, bg_brush);
+
+            // Fondo de la barra (rojizo si la app no tiene sesión de audio)
+            let bg_color = if state.is_no_session {
+                COLORREF(0x005050B0)
+            } else {
+                COLORREF(0x00AAAAAA)
+            };
+            let bg_brush = CreateSolidBrush(bg_color);
+            FillRect(hdc, &bar.rect(0, bar.length, 0), bg_brush);
             let _ = DeleteObject(bg_brush);
 
             // Barra de progreso
-            if !state.is_muted {
-                let fill_width = (bar_width as f32 * state.volume) as i32;
+            // Con boost (>100%) el relleno se queda en el final de la barra y
+            // el exceso se dibuja aparte
+            if !state.is_muted && !state.is_no_session {
+                let fill_length = bar.offset(state.displayed_volume.min(1.0));
                 let fill_brush = CreateSolidBrush(COLORREF(0x00FFCE4E));
-                let fill_rect = RECT {
-                    left: bar_x,
-                    top: bar_y,
-                    right: bar_x + fill_width,
-                    bottom: bar_y + bar_height,
-                };
-                FillRect(hdc, &fill_rect, fill_brush);
+                FillRect(hdc, &bar.rect(0, fill_length, 0), fill_brush);
                 let _ = DeleteObject(fill_brush);
+
+                // Marca fina en la posición anterior al cambio
+                let show_previous = state.config.read().toast_show_previous;
+                if show_previous && (state.previous_volume - state.volume).abs() >= 0.005 {
+                    let marker = bar.offset(state.previous_volume.min(1.0));
+                    let marker_width = ((2.0 * scale) as i32).max(1);
+                    let marker_brush = CreateSolidBrush(COLORREF(0x00B0B0B0));
+                    let marker_rect = bar.rect(
+                        marker - marker_width / 2,
+                        marker - marker_width / 2 + marker_width,
+                        (2.0 * scale) as i32,
+                    );
+                    FillRect(hdc, &marker_rect, marker_brush);
+                    let _ = DeleteObject(marker_brush);
+                }
+
+                // Marcar el final de la barra cuando se alcanza el límite de
+                // la app, o su mínimo al bajar (en otro color)
+                if state.cap_reached || state.floor_reached {
+                    let cap_length = ((4.0 * scale) as i32).min(fill_length);
+                    let cap_color = if state.cap_reached {
+                        COLORREF(0x0000AAFF)
+                    } else {
+                        COLORREF(0x0060D060)
+                    };
+                    let cap_brush = CreateSolidBrush(cap_color);
+                    FillRect(
+                        hdc,
+                        &bar.rect(fill_length - cap_length, fill_length, 0),
+                        cap_brush,
+                    );
+                    let _ = DeleteObject(cap_brush);
+                }
+
+                // Segmento de boost: el exceso sobre 100% al final de la
+                // barra, más grueso y en otro color
+                let boost = (state.displayed_volume - 1.0).min(1.0);
+                if boost >= 0.005 {
+                    let boost_length = bar.offset(boost).max(1);
+                    let boost_brush = CreateSolidBrush(COLORREF(0x006060FF));
+                    let boost_rect =
+                        bar.rect(bar.length - boost_length, bar.length, (1.0 * scale) as i32);
+                    FillRect(hdc, &boost_rect, boost_brush);
+                    let _ = DeleteObject(boost_brush);
+                }
             }
 
-            // Texto de volumen a la derecha de la barra escalado
-            let volume_text = if state.is_muted {
-                "M".to_string()
+            // Texto de volumen a la derecha de la barra escalado (debajo en
+            // vertical)
+            let volume_text = if state.is_no_session {
+                "–".to_string()
+            } else if state.is_muted {
+                tr(Text::MutedShort).to_string()
             } else {
-                format!("{}", (state.volume * 100.0) as i32)
+                let percent = state
+                    .config
+                    .read()
+                    .display_percent(&state.app_name, state.volume);
+                format!("{}", percent)
             };
             let mut volume_text_wide: Vec<u16> = volume_text
                 .encode_utf16()
                 .chain(std::iter::once(0))
                 .collect();
-            let mut volume_rect = RECT {
-                left: bar_x + bar_width + (10.0 * scale) as i32,
-                top: bar_y - (5.0 * scale) as i32,
-                right: scaled_width - (5.0 * scale) as i32,
-                bottom: bar_y + (12.0 * scale) as i32,
+            let mut volume_rect = if vertical {
+                RECT {
+                    left: 0,
+                    top: bar.start + button_space + (5.0 * scale) as i32,
+                    right: scaled_width,
+                    bottom: scaled_height - (5.0 * scale) as i32,
+                }
+            } else {
+                RECT {
+                    left: bar.start + bar.length + button_space + (10.0 * scale) as i32,
+                    top: bar.cross - (5.0 * scale) as i32,
+                    right: scaled_width - (5.0 * scale) as i32,
+                    bottom: bar.cross + (12.0 * scale) as i32,
+                }
             };
             DrawTextW(
                 hdc,
@@ -317,6 +1227,50 @@ impl ToastUI {
                 DT_CENTER | DT_SINGLELINE | DT_VCENTER,
             );
 
+            // Nombre del dispositivo bajo la barra si no es el predeterminado,
+            // o aviso de que se ajustó el volumen del sistema (o del dispositivo
+            // por modo exclusivo) o de horas de silencio. Un cambio temporal
+            // muestra antes que nada cuándo se revierte. En vertical no cabe.
+            let restore_caption = state
+                .restore_in
+                .map(|delay| tr_with(Text::TemporaryRestoreIn, &delay.as_secs().to_string()));
+            let caption = match &state.device_name {
+                _ if vertical => None,
+                _ if restore_caption.is_some() => restore_caption.as_deref(),
+                Some(device_name) => Some(device_name.as_str()),
+                None if state.is_master => Some(tr(Text::SystemVolumeNoAudio)),
+                None if state.is_exclusive => Some(tr(Text::DeviceVolumeExclusive)),
+                None if state.quiet_hours => Some(tr(Text::QuietHours)),
+                None => None,
+            };
+            if let Some(device_name) = caption {
+                // Misma familia, en proporción 11/15 del tamaño principal
+                let small_height = -(font_size as f32 * 11.0 / 15.0 * scale) as i32;
+                let small_font = new_font(&font_family, small_height);
+                SelectObject(hdc, small_font);
+                SetTextColor(hdc, COLORREF(0x00999999));
+
+                let mut device_text_wide: Vec<u16> = device_name
+                    .encode_utf16()
+                    .chain(std::iter::once(0))
+                    .collect();
+                let mut device_rect = RECT {
+                    left: bar.start,
+                    top: bar.cross + bar.thickness + (3.0 * scale) as i32,
+                    right: bar.start + bar.length,
+                    bottom: scaled_height - (2.0 * scale) as i32,
+                };
+                DrawTextW(
+                    hdc,
+                    &mut device_text_wide,
+                    &mut device_rect,
+                    DT_LEFT | DT_SINGLELINE | DT_END_ELLIPSIS,
+                );
+
+                SelectObject(hdc, font);
+                let _ = DeleteObject(small_font);
+            }
+
             // Restaurar y limpiar fuente
             SelectObject(hdc, old_font);
             let _ = DeleteObject(font);
@@ -324,14 +1278,455 @@ impl ToastUI {
             let _ = EndPaint(hwnd, &ps);
         }
     }
+
+    /// Compact row of `toast_context`: an icon and a mini-bar per app, the
+    /// adjusted one on a lighter background, and "+N" for the apps left out
+    unsafe fn paint_context(hdc: HDC, state: &ToastState, scale: f32, scaled_height: i32) {
+        let padding = (CONTEXT_PADDING as f32 * scale) as i32;
+        let slot_width = (CONTEXT_SLOT_WIDTH as f32 * scale) as i32;
+        let icon_size = (20.0 * scale) as i32;
+        let bar_width = (28.0 * scale) as i32;
+        let bar_height = ((3.0 * scale) as i32).max(1);
+        let bar_y = (36.0 * scale) as i32;
+        let generic_icon = LoadIconW(None, IDI_APPLICATION).ok();
+
+        for (index, entry) in state.context.iter().enumerate() {
+            let slot_x = padding + slot_width * index as i32;
+
+            // Fondo más claro detrás de la app que se está ajustando
+            if entry.highlighted {
+                let radius = (8.0 * scale) as i32;
+                let brush = CreateSolidBrush(COLORREF(0x00404040));
+                let pen = CreatePen(PS_NULL, 0, COLORREF(0));
+                let old_brush = SelectObject(hdc, brush);
+                let old_pen = SelectObject(hdc, pen);
+                let _ = RoundRect(
+                    hdc,
+                    slot_x + (2.0 * scale) as i32,
+                    (4.0 * scale) as i32,
+                    slot_x + slot_width - (2.0 * scale) as i32,
+                    scaled_height - (4.0 * scale) as i32,
+                    radius,
+                    radius,
+                );
+                SelectObject(hdc, old_brush);
+                SelectObject(hdc, old_pen);
+                let _ = DeleteObject(brush);
+                let _ = DeleteObject(pen);
+            }
+
+            if let Some(icon) = entry.icon.or(generic_icon) {
+                let _ = DrawIconEx(
+                    hdc,
+                    slot_x + (slot_width - icon_size) / 2,
+                    (10.0 * scale) as i32,
+                    icon,
+                    icon_size,
+                    icon_size,
+                    0,
+                    None,
+                    DI_NORMAL,
+                );
+            }
+
+            // Mini-barra bajo el icono, atenuada para las demás apps
+            let bar_x = slot_x + (slot_width - bar_width) / 2;
+            let bg_color = if entry.highlighted {
+                COLORREF(0x00AAAAAA)
+            } else {
+                COLORREF(0x00606060)
+            };
+            let bg_brush = CreateSolidBrush(bg_color);
+            let bg_rect = RECT {
+                left: bar_x,
+                top: bar_y,
+                right: bar_x + bar_width,
+                bottom: bar_y + bar_height,
+            };
+            FillRect(hdc, &bg_rect, bg_brush);
+            let _ = DeleteObject(bg_brush);
+
+            if !entry.is_muted {
+                let fill_color = if entry.highlighted {
+                    COLORREF(0x00FFCE4E)
+                } else {
+                    COLORREF(0x00A08A50)
+                };
+                let fill_brush = CreateSolidBrush(fill_color);
+                let fill_rect = RECT {
+                    left: bar_x,
+                    top: bar_y,
+                    right: bar_x + (bar_width as f32 * entry.volume.min(1.0)) as i32,
+                    bottom: bar_y + bar_height,
+                };
+                FillRect(hdc, &fill_rect, fill_brush);
+                let _ = DeleteObject(fill_brush);
+            }
+        }
+
+        if state.context_overflow > 0 {
+            let slot_x = padding + slot_width * state.context.len() as i32;
+            let mut overflow_wide: Vec<u16> = format!("+{}", state.context_overflow)
+                .encode_utf16()
+                .chain(std::iter::once(0))
+                .collect();
+            let mut overflow_rect = RECT {
+                left: slot_x,
+                top: 0,
+                right: slot_x + slot_width,
+                bottom: scaled_height,
+            };
+            DrawTextW(
+                hdc,
+                &mut overflow_wide,
+                &mut overflow_rect,
+                DT_CENTER | DT_SINGLELINE | DT_VCENTER,
+            );
+        }
+    }
 }
 
 impl Drop for ToastUI {
     fn drop(&mut self) {
+        // Soltar el controlador de audio antes de CoUninitialize
+        self.state.lock().unwrap().audio_controller = None;
         unsafe {
             if !self.hwnd.is_invalid() {
+                SetWindowLongPtrW(self.hwnd, GWLP_USERDATA, 0);
+                drop(Arc::from_raw(Arc::as_ptr(&self.state)));
                 DestroyWindow(self.hwnd).ok();
             }
         }
     }
 }
+
+/// Clip `hwnd` to a `width` x `height` rounded rectangle, in physical pixels.
+/// Call it whenever the window is created or resized.
+///
+/// Region ownership: once `SetWindowRgn` succeeds the system owns the new
+/// region and deletes the previous one, so neither may be deleted here or
+/// later. Only when the call fails does the region still belong to us.
+pub(crate) unsafe fn apply_round_region(hwnd: HWND, width: i32, height: i32, radius: i32) {
+    let region = CreateRoundRectRgn(0, 0, width, height, radius, radius);
+    if region.is_invalid() {
+        return;
+    }
+
+    if SetWindowRgn(hwnd, region, true) == 0 {
+        let _ = DeleteObject(region);
+    }
+}
+
+/// Volume bar of the toast: filling left to right, or bottom to top when
+/// vertical. Positions along it are pixels from where the fill starts.
+struct Bar {
+    /// Left end, or bottom end when vertical
+    start: i32,
+    /// Top edge, or left edge when vertical
+    cross: i32,
+    length: i32,
+    thickness: i32,
+    vertical: bool,
+}
+
+impl Bar {
+    /// Position along the bar of a volume from 0.0 to 1.0
+    fn offset(&self, fraction: f32) -> i32 {
+        (self.length as f32 * fraction) as i32
+    }
+
+    /// Rectangle covering `from..to` along the bar, sticking out `widen`
+    /// pixels past both of its edges
+    fn rect(&self, from: i32, to: i32, widen: i32) -> RECT {
+        if self.vertical {
+            RECT {
+                left: self.cross - widen,
+                top: self.start - to,
+                right: self.cross + self.thickness + widen,
+                bottom: self.start - from,
+            }
+        } else {
+            RECT {
+                left: self.start + from,
+                top: self.cross - widen,
+                right: self.start + to,
+                bottom: self.cross + self.thickness + widen,
+            }
+        }
+    }
+}
+
+/// Layout of the volume bar in a toast of the given scaled size. Without
+/// icons the bar also takes the icon's room; with `toast_buttons` it leaves
+/// room for a button at each end.
+fn bar_layout(
+    orientation: ToastOrientation,
+    scaled_width: i32,
+    scaled_height: i32,
+    show_icons: bool,
+    buttons: bool,
+    scale: f32,
+) -> Bar {
+    let icon_space = if show_icons { 30.0 } else { 0.0 };
+    let button_space = if buttons { BUTTON_SPACE as f32 } else { 0.0 };
+    let thickness = (4.0 * scale) as i32;
+    match orientation {
+        ToastOrientation::Vertical => {
+            // De abajo arriba, entre el icono y el número
+            let top = ((15.0 + icon_space + button_space) * scale) as i32;
+            let bottom = scaled_height - ((30.0 + button_space) * scale) as i32;
+            Bar {
+                start: bottom,
+                cross: (scaled_width - thickness) / 2,
+                length: bottom - top,
+                thickness,
+                vertical: true,
+            }
+        }
+        ToastOrientation::Horizontal => Bar {
+            start: ((15.0 + icon_space + button_space) * scale) as i32,
+            cross: (scaled_height - thickness) / 2,
+            length: scaled_width - ((55.0 + icon_space + button_space * 2.0) * scale) as i32,
+            thickness,
+            vertical: false,
+        },
+    }
+}
+
+/// Rectangles of the - and + `toast_buttons`: before and after the bar, or
+/// below and above it when vertical
+fn button_rects(bar: &Bar, scale: f32) -> (RECT, RECT) {
+    let size = (BUTTON_SIZE as f32 * scale) as i32;
+    let gap = ((BUTTON_SPACE - BUTTON_SIZE) as f32 * scale) as i32;
+    let cross = bar.cross + bar.thickness / 2 - size / 2;
+    let square = |left: i32, top: i32| RECT {
+        left,
+        top,
+        right: left + size,
+        bottom: top + size,
+    };
+    if bar.vertical {
+        (
+            square(cross, bar.start + gap),
+            square(cross, bar.start - bar.length - gap - size),
+        )
+    } else {
+        (
+            square(bar.start - gap - size, cross),
+            square(bar.start + bar.length + gap, cross),
+        )
+    }
+}
+
+/// Where a toast of the given scaled size appears: centered near the bottom
+/// of the screen, or halfway down by the right edge when vertical
+unsafe fn home_position(
+    orientation: ToastOrientation,
+    scaled_width: i32,
+    scaled_height: i32,
+    scale: f32,
+) -> (i32, i32) {
+    let screen_width = GetSystemMetrics(SM_CXSCREEN);
+    let screen_height = GetSystemMetrics(SM_CYSCREEN);
+    match orientation {
+        ToastOrientation::Horizontal => (
+            (screen_width - scaled_width) / 2,
+            screen_height - scaled_height - (150.0 * scale) as i32,
+        ),
+        ToastOrientation::Vertical => (
+            screen_width - scaled_width - (VERTICAL_EDGE_MARGIN as f32 * scale) as i32,
+            (screen_height - scaled_height) / 2,
+        ),
+    }
+}
+
+/// Top-left corner of the app icon: left of the bar, or above it when
+/// vertical
+fn icon_position(
+    orientation: ToastOrientation,
+    scaled_width: i32,
+    scaled_height: i32,
+    icon_size: i32,
+    scale: f32,
+) -> (i32, i32) {
+    match orientation {
+        ToastOrientation::Horizontal => ((10.0 * scale) as i32, (scaled_height - icon_size) / 2),
+        ToastOrientation::Vertical => ((scaled_width - icon_size) / 2, (10.0 * scale) as i32),
+    }
+}
+
+/// Whether `toast_hide_when_presenting` is on and Windows reports a
+/// presentation or a full-screen app, when notifications shouldn't show
+fn is_hidden_for_presentation(state: &ToastState) -> bool {
+    if !state.config.read().toast_hide_when_presenting {
+        return false;
+    }
+
+    let presenting = matches!(
+        unsafe { SHQueryUserNotificationState() },
+        Ok(QUNS_PRESENTATION_MODE | QUNS_RUNNING_D3D_FULL_SCREEN | QUNS_BUSY)
+    );
+    if presenting {
+        log_event!("[DEBUG] Toast skipped: presentation or full-screen app in front");
+    }
+    presenting
+}
+
+/// Put `backdrop` behind the toast: DWM draws it wherever the client area
+/// is painted black, once the frame extends over all of it. The window
+/// region still clips it to the rounded corners. Returns whether a backdrop
+/// other than solid is in effect; on older Windows builds, or if DWM
+/// refuses, the toast stays solid.
+unsafe fn apply_backdrop(hwnd: HWND, backdrop: ToastBackdrop) -> bool {
+    let backdrop_type = match backdrop {
+        ToastBackdrop::Solid => DWMSBT_NONE,
+        ToastBackdrop::Acrylic => DWMSBT_TRANSIENTWINDOW,
+        ToastBackdrop::Mica => DWMSBT_MAINWINDOW,
+    };
+    let enabled = backdrop != ToastBackdrop::Solid;
+    if enabled && windows_build() < SYSTEM_BACKDROP_BUILD {
+        log_event!("The toast backdrop needs Windows 11 22H2 or later, using the solid background");
+        return false;
+    }
+
+    let extend = if enabled { -1 } else { 0 };
+    let margins = MARGINS {
+        cxLeftWidth: extend,
+        cxRightWidth: extend,
+        cyTopHeight: extend,
+        cyBottomHeight: extend,
+    };
+    // El tinte oscuro combina con los colores del toast
+    let dark_mode = BOOL::from(true);
+    let result = DwmExtendFrameIntoClientArea(hwnd, &margins)
+        .and_then(|()| {
+            DwmSetWindowAttribute(
+                hwnd,
+                DWMWA_USE_IMMERSIVE_DARK_MODE,
+                &dark_mode as *const BOOL as *const _,
+                std::mem::size_of::<BOOL>() as u32,
+            )
+        })
+        .and_then(|()| {
+            DwmSetWindowAttribute(
+                hwnd,
+                DWMWA_SYSTEMBACKDROP_TYPE,
+                &backdrop_type as *const DWM_SYSTEMBACKDROP_TYPE as *const _,
+                std::mem::size_of::<DWM_SYSTEMBACKDROP_TYPE>() as u32,
+            )
+        });
+
+    match result {
+        Ok(()) => enabled,
+        Err(e) => {
+            log_event!("Error applying the toast backdrop: {}", e);
+            let _ = DwmExtendFrameIntoClientArea(hwnd, &MARGINS::default());
+            false
+        }
+    }
+}
+
+/// Build number of the running Windows (`CurrentBuildNumber`), 0 if unknown
+fn windows_build() -> u32 {
+    let mut buffer = [0u16; 16];
+    let mut size = std::mem::size_of_val(&buffer) as u32;
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            w!("SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion"),
+            w!("CurrentBuildNumber"),
+            RRF_RT_REG_SZ,
+            None,
+            Some(buffer.as_mut_ptr() as *mut _),
+            Some(&mut size),
+        )
+    };
+    if result.is_err() {
+        return 0;
+    }
+
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    String::from_utf16_lossy(&buffer[..len])
+        .parse()
+        .unwrap_or(0)
+}
+
+/// Font used when the configured one isn't installed
+const FALLBACK_FONT: &str = "Segoe UI";
+
+/// Font `family` at `height` (negative for the character height, as in
+/// `CreateFontW`), or `FALLBACK_FONT` when GDI would substitute another font
+/// for it. Returns the font and the family actually used; the caller selects
+/// it out and deletes it.
+unsafe fn create_font(hdc: HDC, family: &str, height: i32) -> (HFONT, String) {
+    let font = new_font(family, height);
+
+    // La familia real tras la sustitución de GDI; se compara el prefijo
+    // porque una fuente variable puede resolverse como una de sus variantes
+    let old_font = SelectObject(hdc, font);
+    let mut face = [0u16; 32];
+    let len = GetTextFaceW(hdc, Some(&mut face)).max(0) as usize;
+    SelectObject(hdc, old_font);
+    let face = String::from_utf16_lossy(&face[..len.min(face.len())]);
+    let face = face.trim_end_matches('\0').to_lowercase();
+
+    if face.starts_with(&family.to_lowercase()) {
+        (font, family.to_string())
+    } else {
+        let _ = DeleteObject(font);
+        (new_font(FALLBACK_FONT, height), FALLBACK_FONT.to_string())
+    }
+}
+
+unsafe fn new_font(family: &str, height: i32) -> HFONT {
+    CreateFontW(
+        height,
+        0,
+        0,
+        0,
+        FW_NORMAL.0 as i32,
+        0,
+        0,
+        0,
+        DEFAULT_CHARSET.0 as u32,
+        OUT_DEFAULT_PRECIS.0 as u32,
+        CLIP_DEFAULT_PRECIS.0 as u32,
+        CLEARTYPE_QUALITY.0 as u32,
+        (DEFAULT_PITCH.0 | FF_DONTCARE.0) as u32,
+        &HSTRING::from(family),
+    )
+}
+
+/// First icon of an executable, if it has one
+pub fn extract_icon(path: &str) -> Option<HICON> {
+    unsafe {
+        let path_wide: Vec<u16> = path.encode_utf16().chain(std::iter::once(0)).collect();
+
+        let mut icon: HICON = HICON::default();
+        let result = ExtractIconExW(
+            PCWSTR::from_raw(path_wide.as_ptr()),
+            0,
+            None,
+            Some(&mut icon),
+            1,
+        );
+
+        if result > 0 && !icon.is_invalid() {
+            Some(icon)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parse a "#RRGGBB" color into a GDI `COLORREF` (0x00BBGGRR)
+pub fn parse_hex_color(value: &str) -> Option<COLORREF> {
+    let hex = value.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    let (r, g, b) = ((rgb >> 16) & 0xFF, (rgb >> 8) & 0xFF, rgb & 0xFF);
+    Some(COLORREF((b << 16) | (g << 8) | r))
+}